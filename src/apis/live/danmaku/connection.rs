@@ -0,0 +1,831 @@
+//! A blocking connection to the broadcast of a live room
+
+use std::{
+  io::ErrorKind,
+  net::{TcpStream, ToSocketAddrs},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Receiver, SyncSender},
+    Arc,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+
+use tungstenite::{handshake::HandshakeError, stream::MaybeTlsStream, Message, WebSocket};
+
+use super::{
+  get_danmu_info,
+  packet::{AuthBody, AuthReply, Operation, Packet},
+  DanmuHost,
+};
+use crate::{
+  apis::{
+    live::{event::LiveEvent, info::get_room_init_info},
+    misc::buvid::Buvid,
+  },
+  client::{Client, RetryClass, RetryPolicy},
+  credential::Credential,
+  error::ApiError,
+};
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// How often the reader thread checks whether a heartbeat is due or the connection was
+/// closed, while no message arrives
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Messages not yet received from a [DanmakuConnection] before the reader thread waits
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Used if `getDanmuInfo` hands out no host
+const FALLBACK_HOST: &str = "broadcastlv.chat.bilibili.com";
+
+#[derive(Debug, Clone)]
+pub struct DanmakuOptions {
+  /// 使用`wss_port`上的wss, 否则使用`ws_port`上的明文ws. Defaults to true.
+  pub tls: bool,
+  /// 心跳包的发送间隔, 默认30秒. 超过两个间隔没有收到任何消息时视为断线.
+  pub heartbeat_interval: Duration,
+  /// Timeout of connecting, the WebSocket handshake and the auth reply
+  pub timeout: Duration,
+  /// When and how often to reconnect after the connection is lost, see
+  /// [RetryPolicy::danmaku_reconnect]. Failures it does not classify, e.g., a rejected
+  /// token, are retried as [RetryClass::Network] while reconnecting.
+  pub reconnect: RetryPolicy,
+}
+
+impl Default for DanmakuOptions {
+  fn default() -> Self {
+    Self {
+      tls: true,
+      heartbeat_interval: Duration::from_secs(30),
+      timeout: Duration::from_secs(10),
+      reconnect: RetryPolicy::danmaku_reconnect(),
+    }
+  }
+}
+
+/// A decoded message of the broadcast, or a change of the connection state
+#[derive(Debug, Clone, PartialEq)]
+pub enum DanmakuMessage {
+  /// 已连接并通过认证, 每次重连成功后也会收到
+  Connected { host: DanmuHost },
+  /// 连接断开, 将在`delay`后第`attempt`次重连
+  Reconnecting {
+    attempt: u32,
+    delay: Duration,
+    /// Why the connection or the previous attempt failed
+    reason: String,
+  },
+  /// 重连时token被拒绝, 将重新获取token
+  AuthFailed { code: i32 },
+  /// 心跳回复中的人气值
+  Popularity(u32),
+  /// 广播消息, 如弹幕和礼物
+  Event(Box<LiveEvent>),
+}
+
+impl DanmakuMessage {
+  pub(super) fn from_packet(packet: &Packet) -> Option<crate::Result<Self>> {
+    match packet.operation {
+      Operation::HeartbeatReply => packet.popularity().map(|p| Ok(Self::Popularity(p))),
+      Operation::Message => Some(
+        packet
+          .json()
+          .and_then(LiveEvent::from_json)
+          .map(|event| Self::Event(Box::new(event))),
+      ),
+      _ => None,
+    }
+  }
+}
+
+/// 直播间信息流连接.
+///
+/// A background thread keeps the heartbeat going and decodes the messages, which are
+/// yielded by iterating over the connection. Lost connections are reconnected under
+/// [DanmakuOptions::reconnect], rotating through the hosts of `getDanmuInfo`, and the
+/// state changes are yielded as messages as well. Iteration ends after the error that
+/// the connection gives up on. Dropping the connection closes it.
+///
+/// Up to 1024 messages are buffered; the background thread waits while they are not
+/// iterated over, so a slow consumer may be disconnected and reconnected after a while.
+///
+/// ```no_run
+/// use bili_api_rs::{
+///   apis::live::{
+///     danmaku::{DanmakuConnection, DanmakuMessage},
+///     event::LiveEvent,
+///   },
+///   Client,
+/// };
+///
+/// let client = Client::new();
+/// for message in DanmakuConnection::connect(&client, 1029, None)? {
+///   match message? {
+///     DanmakuMessage::Event(event) => {
+///       if let LiveEvent::Danmaku(danmaku) = *event {
+///         println!("{}: {}", danmaku.uname, danmaku.text);
+///       }
+///     }
+///     DanmakuMessage::Reconnecting { reason, .. } => eprintln!("Disconnected: {reason}"),
+///     _ => {}
+///   }
+/// }
+/// # Ok::<(), bili_api_rs::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct DanmakuConnection {
+  room_id: i32,
+  messages: Receiver<crate::Result<DanmakuMessage>>,
+  closed: Arc<AtomicBool>,
+}
+
+impl DanmakuConnection {
+  /// Connects to the broadcast of `room_id`, which may be a short id. With `credential`, the
+  /// connection is authenticated as its user, otherwise senders of danmaku are masked.
+  ///
+  /// Fails without reconnecting if the first connection cannot be established with any
+  /// of the hosts.
+  pub fn connect(
+    client: &Client,
+    room_id: i32,
+    credential: Option<&Credential>,
+  ) -> crate::Result<Self> {
+    Self::connect_with(client, room_id, credential, &DanmakuOptions::default())
+  }
+
+  pub fn connect_with(
+    client: &Client,
+    room_id: i32,
+    credential: Option<&Credential>,
+    options: &DanmakuOptions,
+  ) -> crate::Result<Self> {
+    let room_id = get_room_init_info(client, room_id)?.data.room_id;
+    let (sender, messages) = mpsc::sync_channel(CHANNEL_CAPACITY);
+    let closed = Arc::new(AtomicBool::new(false));
+    let mut session = Session {
+      client: client.clone(),
+      credential: credential.cloned(),
+      room_id,
+      options: options.clone(),
+      auth: AuthBody::new(0, room_id, "", ""),
+      hosts: Vec::new(),
+      next_host: 0,
+      sender,
+      closed: Arc::clone(&closed),
+    };
+    session.fetch_info()?;
+    let (socket, host) = session.connect_any()?;
+    session.emit(Ok(DanmakuMessage::Connected { host }));
+    thread::Builder::new()
+      .name(format!("bili-danmaku-{room_id}"))
+      .spawn(move || session.run(socket))
+      .expect("Failed to spawn danmaku thread");
+    Ok(Self {
+      room_id,
+      messages,
+      closed,
+    })
+  }
+
+  /// 真实房间号
+  pub fn room_id(&self) -> i32 {
+    self.room_id
+  }
+}
+
+impl Iterator for DanmakuConnection {
+  type Item = crate::Result<DanmakuMessage>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.messages.recv().ok()
+  }
+}
+
+impl Drop for DanmakuConnection {
+  fn drop(&mut self) {
+    self.closed.store(true, Ordering::Relaxed);
+  }
+}
+
+/// The auth packet as the user of `credential`, or anonymously with `buvid`
+pub(super) fn auth_body(
+  credential: Option<Credential>,
+  buvid: Option<Buvid>,
+  room_id: i32,
+  token: String,
+) -> AuthBody {
+  let uid = credential
+    .as_ref()
+    .and_then(|c| c.dede_user_id.as_deref()?.parse().ok())
+    .unwrap_or(0);
+  let buvid = credential
+    .and_then(|c| c.buvid3)
+    .or_else(|| buvid.map(|b| b.buvid3))
+    .unwrap_or_default();
+  AuthBody::new(uid, room_id, buvid, token)
+}
+
+/// The hosts of `getDanmuInfo`, or the well-known one if it hands out none
+pub(super) fn hosts_or_fallback(mut hosts: Vec<DanmuHost>) -> Vec<DanmuHost> {
+  if hosts.is_empty() {
+    hosts.push(DanmuHost {
+      host: FALLBACK_HOST.to_string(),
+      port: 2243,
+      wss_port: 443,
+      ws_port: 2244,
+    });
+  }
+  hosts
+}
+
+/// Connects to `host` and completes the WebSocket handshake
+fn open(host: &DanmuHost, options: &DanmakuOptions) -> crate::Result<Socket> {
+  let (scheme, port) = match options.tls {
+    true => ("wss", host.wss_port),
+    false => ("ws", host.ws_port),
+  };
+  let url = format!("{scheme}://{}:{port}/sub", host.host);
+
+  let mut last_error = None;
+  let stream = (host.host.as_str(), port)
+    .to_socket_addrs()?
+    .find_map(
+      |addr| match TcpStream::connect_timeout(&addr, options.timeout) {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+          last_error = Some(e);
+          None
+        }
+      },
+    )
+    .ok_or_else(|| last_error.unwrap_or_else(|| ErrorKind::NotFound.into()))?;
+  stream.set_read_timeout(Some(options.timeout))?;
+  stream.set_write_timeout(Some(options.timeout))?;
+
+  match tungstenite::client_tls(url, stream) {
+    Ok((socket, _)) => Ok(socket),
+    Err(HandshakeError::Failure(e)) => Err(e.into()),
+    Err(HandshakeError::Interrupted(_)) => Err(std::io::Error::from(ErrorKind::TimedOut).into()),
+  }
+}
+
+/// Sends the auth packet and waits for its reply. A rejected token is an [ApiError].
+fn authenticate(mut socket: Socket, auth: &AuthBody) -> crate::Result<Socket> {
+  socket.send(Message::Binary(Packet::auth(auth).encode()))?;
+  loop {
+    let Message::Binary(bytes) = socket.read()? else {
+      continue;
+    };
+    for packet in Packet::decode(&bytes)? {
+      if packet.operation == Operation::AuthReply {
+        let reply: AuthReply = packet.json()?;
+        return match reply.is_success() {
+          true => Ok(socket),
+          false => Err(ApiError::new(reply.code, "Danmaku auth rejected".to_string()).into()),
+        };
+      }
+    }
+  }
+}
+
+/// How long to wait before reconnecting after `error`. Reconnecting failures the policy does
+/// not classify, e.g., a token rejected again or a garbled auth reply, are taken for
+/// network errors, as the room was reachable before.
+pub(super) fn reconnect_delay(
+  policy: &RetryPolicy,
+  error: &crate::Error,
+  attempt: u32,
+) -> Option<Duration> {
+  let class = RetryClass::of(error).unwrap_or(RetryClass::Network);
+  policy.next_delay_for(class, attempt)
+}
+
+pub(super) fn disconnected(kind: ErrorKind, message: &str) -> crate::Error {
+  std::io::Error::new(kind, message).into()
+}
+
+/// Everything needed to keep a connection alive, owned by its background thread
+struct Session {
+  client: Client,
+  credential: Option<Credential>,
+  room_id: i32,
+  options: DanmakuOptions,
+  auth: AuthBody,
+  hosts: Vec<DanmuHost>,
+  /// Index into `hosts` of the host to connect to next
+  next_host: usize,
+  sender: SyncSender<crate::Result<DanmakuMessage>>,
+  closed: Arc<AtomicBool>,
+}
+
+impl Session {
+  /// Fetches a new token and host list
+  fn fetch_info(&mut self) -> crate::Result<()> {
+    let info = get_danmu_info(&self.client, self.room_id, self.credential.as_ref())?.data;
+    let credential = self.credential.as_ref().map(|c| self.client.credential(c));
+    self.auth = auth_body(credential, self.client.buvid(), self.room_id, info.token);
+    self.hosts = hosts_or_fallback(info.host_list);
+    self.next_host = 0;
+    Ok(())
+  }
+
+  /// Connects to the next host in turn
+  fn connect_next(&mut self) -> crate::Result<(Socket, DanmuHost)> {
+    let host = self.hosts[self.next_host % self.hosts.len()].clone();
+    self.next_host += 1;
+    let socket = open(&host, &self.options).and_then(|socket| authenticate(socket, &self.auth))?;
+    Ok((socket, host))
+  }
+
+  /// Tries each host once, but stops if the token is rejected
+  fn connect_any(&mut self) -> crate::Result<(Socket, DanmuHost)> {
+    let mut last_error = None;
+    for _ in 0..self.hosts.len() {
+      match self.connect_next() {
+        Ok(connected) => return Ok(connected),
+        Err(e @ crate::Error::Api(_)) => return Err(e),
+        Err(e) => last_error = Some(e),
+      }
+    }
+    Err(last_error.expect("At least one host"))
+  }
+
+  fn run(mut self, mut socket: Socket) {
+    loop {
+      let result = self.read_messages(&mut socket);
+      let _ = socket.close(None);
+      let _ = socket.flush();
+      let Err(lost) = result else {
+        return;
+      };
+      match self.reconnect(lost) {
+        Some(reconnected) => socket = reconnected,
+        None => return,
+      }
+    }
+  }
+
+  /// Reconnects until it succeeds, the [RetryPolicy] gives up or the connection is closed.
+  /// A rejected token is refetched on the next attempt, see [reconnect_delay].
+  fn reconnect(&mut self, mut error: crate::Error) -> Option<Socket> {
+    let mut attempt = 0;
+    let mut refetch = false;
+    loop {
+      attempt += 1;
+      let Some(delay) = reconnect_delay(&self.options.reconnect, &error, attempt) else {
+        self.emit(Err(error));
+        return None;
+      };
+      let reconnecting = DanmakuMessage::Reconnecting {
+        attempt,
+        delay,
+        reason: error.to_string(),
+      };
+      if !self.emit(Ok(reconnecting)) || !self.sleep(delay) {
+        return None;
+      }
+
+      if refetch {
+        if let Err(e) = self.fetch_info() {
+          error = e;
+          continue;
+        }
+        refetch = false;
+      }
+      match self.connect_next() {
+        Ok((socket, host)) => {
+          return self
+            .emit(Ok(DanmakuMessage::Connected { host }))
+            .then_some(socket);
+        }
+        Err(crate::Error::Api(e)) => {
+          if !self.emit(Ok(DanmakuMessage::AuthFailed { code: e.code() })) {
+            return None;
+          }
+          refetch = true;
+          error = e.into();
+        }
+        Err(e) => error = e,
+      }
+    }
+  }
+
+  /// Returns Ok once the connection is closed, or the error that it is lost with
+  fn read_messages(&mut self, socket: &mut Socket) -> crate::Result<()> {
+    set_read_timeout(socket, POLL_INTERVAL)?;
+    let mut next_heartbeat = Instant::now();
+    let mut last_received = Instant::now();
+    while !self.closed.load(Ordering::Relaxed) {
+      if Instant::now() >= next_heartbeat {
+        socket.send(Message::Binary(Packet::heartbeat().encode()))?;
+        next_heartbeat += self.options.heartbeat_interval;
+      }
+      let bytes = match socket.read() {
+        Ok(Message::Binary(bytes)) => bytes,
+        Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => {
+          return Err(disconnected(
+            ErrorKind::ConnectionAborted,
+            "Closed by the server",
+          ));
+        }
+        Ok(_) => {
+          last_received = Instant::now();
+          continue;
+        }
+        Err(tungstenite::Error::Io(e))
+          if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+        {
+          // The server replies to every heartbeat, so it is gone if silent for long
+          if last_received.elapsed() > self.options.heartbeat_interval * 2 {
+            return Err(disconnected(
+              ErrorKind::TimedOut,
+              "No message from the server",
+            ));
+          }
+          continue;
+        }
+        Err(e) => return Err(e.into()),
+      };
+      last_received = Instant::now();
+      // A malformed frame does not break the connection
+      let packets = match Packet::decode(&bytes) {
+        Ok(packets) => packets,
+        Err(e) => match self.emit(Err(e)) {
+          true => continue,
+          false => return Ok(()),
+        },
+      };
+      for packet in packets {
+        if let Some(message) = DanmakuMessage::from_packet(&packet) {
+          if !self.emit(message) {
+            return Ok(());
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Returns false if the connection was dropped
+  fn emit(&self, message: crate::Result<DanmakuMessage>) -> bool {
+    self.sender.send(message).is_ok()
+  }
+
+  /// Returns false if the connection was closed meanwhile
+  fn sleep(&self, duration: Duration) -> bool {
+    let until = Instant::now() + duration;
+    while !self.closed.load(Ordering::Relaxed) {
+      let left = until.saturating_duration_since(Instant::now());
+      if left.is_zero() {
+        return true;
+      }
+      thread::sleep(left.min(POLL_INTERVAL));
+    }
+    false
+  }
+}
+
+fn set_read_timeout(socket: &Socket, timeout: Duration) -> std::io::Result<()> {
+  match socket.get_ref() {
+    MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(timeout)),
+    MaybeTlsStream::NativeTls(stream) => stream.get_ref().set_read_timeout(Some(timeout)),
+    _ => Ok(()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{net::TcpListener, sync::atomic::AtomicUsize, thread::JoinHandle};
+
+  use reqwest::StatusCode;
+  use serde_json::Value;
+
+  use super::*;
+  use crate::{
+    apis::live::danmaku::packet::{PacketHeader, ProtocolVersion},
+    client::{HttpRequest, HttpResponse, RetryClass},
+  };
+
+  const ROOM_INIT: &str = r#"{"code":0,"msg":"ok","message":"ok","data":{"room_id":5440,"short_id":1029,"uid":9617619,"need_p2p":0,"is_hidden":false,"is_locked":false,"is_portrait":false,"live_status":1,"hidden_till":0,"lock_till":0,"encrypted":false,"pwd_verified":false,"live_time":0,"room_shield":0,"is_sp":0,"special_type":0}}"#;
+
+  type ServerSocket = WebSocket<TcpStream>;
+
+  /// A frame of `fixtures/danmaku` for the stand-in servers to send. All of them are
+  /// synthetic, see its README.
+  macro_rules! frame {
+    ($name:literal) => {
+      include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fixtures/danmaku/",
+        $name,
+        ".bin"
+      ))
+      .to_vec()
+    };
+  }
+
+  /// A client whose `getDanmuInfo` lists `ports` on localhost, handing out `token1`,
+  /// `token2`, ... as the token
+  fn client(ports: &[u16]) -> Client {
+    let hosts: Vec<_> = ports
+      .iter()
+      .map(|port| format!(r#"{{"host":"127.0.0.1","port":2243,"wss_port":443,"ws_port":{port}}}"#))
+      .collect();
+    let hosts = hosts.join(",");
+    let fetched = AtomicUsize::new(0);
+    Client::builder()
+      .transport(move |request: HttpRequest| {
+        let body = match request.url.path() {
+          "/room/v1/Room/room_init" => ROOM_INIT.to_string(),
+          "/xlive/web-room/v1/index/getDanmuInfo" => {
+            assert_eq!(Some("id=5440&type=0"), request.url.query());
+            let n = fetched.fetch_add(1, Ordering::Relaxed) + 1;
+            format!(
+              r#"{{"code":0,"message":"0","ttl":1,"data":{{"group":"live","business_id":0,"refresh_row_factor":0.125,"refresh_rate":100,"max_delay":5000,"token":"token{n}","host_list":[{hosts}]}}}}"#
+            )
+          }
+          _ => r#"{"code":-404,"message":"啥都木有"}"#.to_string(),
+        };
+        Ok(HttpResponse::new(StatusCode::OK, body))
+      })
+      .build()
+  }
+
+  fn options(reconnect: RetryPolicy) -> DanmakuOptions {
+    DanmakuOptions {
+      tls: false,
+      reconnect,
+      ..Default::default()
+    }
+  }
+
+  /// Reconnects quickly, on network errors only
+  fn quick_reconnect() -> RetryPolicy {
+    let mut policy = RetryPolicy::none();
+    policy
+      .max_attempts(10)
+      .base_delay(Duration::from_millis(10))
+      .jitter(0.0)
+      .retry_on(RetryClass::Network);
+    policy
+  }
+
+  /// A port nothing listens on
+  fn dead_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+      .unwrap()
+      .local_addr()
+      .unwrap()
+      .port()
+  }
+
+  /// A stand-in broadcast server, serving `count` connections one after another with
+  /// `handle`, which is given the index of the connection
+  fn stand_in(
+    count: usize,
+    handle: impl Fn(usize, ServerSocket) + Send + 'static,
+  ) -> (u16, JoinHandle<()>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+      for i in 0..count {
+        let (stream, _) = listener.accept().unwrap();
+        handle(i, tungstenite::accept(stream).unwrap());
+      }
+    });
+    (port, server)
+  }
+
+  /// Reads the auth packet and replies with `code`, returns the token
+  fn auth(socket: &mut ServerSocket, code: i32) -> String {
+    let auth = Packet::decode(&socket.read().unwrap().into_data()).unwrap();
+    assert_eq!(Operation::Auth, auth[0].operation);
+    let auth: Value = auth[0].json().unwrap();
+    assert_eq!(5440, auth["roomid"]);
+    let reply = Packet::new(
+      ProtocolVersion::Control,
+      Operation::AuthReply,
+      format!(r#"{{"code":{code}}}"#).into_bytes(),
+    );
+    socket.send(Message::Binary(reply.encode())).unwrap();
+    auth["key"].as_str().unwrap().to_string()
+  }
+
+  /// Sends `frames` after the first heartbeat
+  fn serve(socket: &mut ServerSocket, frames: Vec<Vec<u8>>) {
+    let heartbeat = Packet::decode(&socket.read().unwrap().into_data()).unwrap();
+    assert_eq!(vec![Packet::heartbeat()], heartbeat);
+    for frame in frames {
+      socket.send(Message::Binary(frame)).unwrap();
+    }
+  }
+
+  /// One line per message, telling whether connected to `port`
+  fn summarize(
+    messages: impl Iterator<Item = crate::Result<DanmakuMessage>>,
+    port: u16,
+  ) -> Vec<String> {
+    messages
+      .map(|message| match message.unwrap() {
+        DanmakuMessage::Connected { host } => format!("connected {}", host.ws_port == port),
+        DanmakuMessage::Reconnecting { attempt, delay, .. } => {
+          format!("reconnecting {attempt} {}", delay.as_millis())
+        }
+        DanmakuMessage::AuthFailed { code } => format!("auth failed {code}"),
+        DanmakuMessage::Popularity(popularity) => format!("popularity {popularity}"),
+        DanmakuMessage::Event(event) => event.cmd().to_string(),
+      })
+      .collect()
+  }
+
+  /// Closes the connection and waits for the client to acknowledge
+  fn close(mut socket: ServerSocket) {
+    socket.close(None).unwrap();
+    while socket.read().is_ok() {}
+  }
+
+  #[test]
+  fn test_connect() {
+    let (port, server) = stand_in(1, |_, mut socket| {
+      assert_eq!("token1", auth(&mut socket, 0));
+      serve(
+        &mut socket,
+        vec![frame!("heartbeat_reply"), frame!("zlib_messages")],
+      );
+      close(socket);
+    });
+    let options = options(RetryPolicy::none());
+    let mut connection =
+      DanmakuConnection::connect_with(&client(&[port]), 1029, None, &options).unwrap();
+    assert_eq!(5440, connection.room_id());
+
+    let messages: Vec<_> = connection.by_ref().take(4).map(Result::unwrap).collect();
+    assert!(matches!(&messages[0], DanmakuMessage::Connected { host } if host.ws_port == port));
+    assert_eq!(DanmakuMessage::Popularity(1), messages[1]);
+    let DanmakuMessage::Event(event) = &messages[2] else {
+      panic!("Not an event: {:?}", messages[2]);
+    };
+    let LiveEvent::Danmaku(danmaku) = &**event else {
+      panic!("Not a danmaku: {event:?}");
+    };
+    assert_eq!(("第一", 233), (danmaku.text.as_str(), danmaku.uid));
+    let DanmakuMessage::Event(watched) = &messages[3] else {
+      panic!("Not an event: {:?}", messages[3]);
+    };
+    assert_eq!("WATCHED_CHANGE", watched.cmd());
+
+    // Not reconnected under this policy
+    let Some(Err(crate::Error::Io(e))) = connection.next() else {
+      panic!("Not disconnected");
+    };
+    assert_eq!(ErrorKind::ConnectionAborted, e.kind());
+    assert!(connection.next().is_none());
+    server.join().unwrap();
+  }
+
+  #[test]
+  fn test_reconnect() {
+    let (port, server) = stand_in(3, |i, mut socket| match i {
+      0 => {
+        auth(&mut socket, 0);
+        serve(&mut socket, vec![frame!("heartbeat_reply")]);
+        close(socket);
+      }
+      1 => {
+        auth(&mut socket, -101);
+      }
+      _ => {
+        assert_eq!("token2", auth(&mut socket, 0));
+        serve(&mut socket, vec![frame!("heartbeat_reply")]);
+        // Until the connection is dropped
+        while socket.read().is_ok() {}
+      }
+    });
+    let dead = dead_port();
+    let options = options(quick_reconnect());
+    let connection =
+      DanmakuConnection::connect_with(&client(&[dead, port]), 1029, None, &options).unwrap();
+
+    assert_eq!(
+      vec![
+        // The dead host is skipped
+        "connected true",
+        "popularity 1",
+        "reconnecting 1 10",
+        // Rotated to the dead host
+        "reconnecting 2 20",
+        "auth failed -101",
+        "reconnecting 3 40",
+        // The new token starts over with the dead host
+        "reconnecting 4 80",
+        "connected true",
+        "popularity 1",
+      ],
+      summarize(connection.take(9), port)
+    );
+    server.join().unwrap();
+  }
+
+  #[test]
+  fn test_reconnect_after_repeated_auth_failures() {
+    let (port, server) = stand_in(4, |i, mut socket| match i {
+      0 => {
+        auth(&mut socket, 0);
+        close(socket);
+      }
+      1 | 2 => {
+        assert_eq!(format!("token{i}"), auth(&mut socket, -101));
+      }
+      _ => {
+        assert_eq!("token3", auth(&mut socket, 0));
+        serve(&mut socket, vec![frame!("heartbeat_reply")]);
+        while socket.read().is_ok() {}
+      }
+    });
+    let options = options(quick_reconnect());
+    let connection =
+      DanmakuConnection::connect_with(&client(&[port]), 1029, None, &options).unwrap();
+
+    assert_eq!(
+      vec![
+        "connected true",
+        "reconnecting 1 10",
+        "auth failed -101",
+        // Not given up on, though the policy only retries network errors
+        "reconnecting 2 20",
+        "auth failed -101",
+        "reconnecting 3 40",
+        "connected true",
+        "popularity 1",
+      ],
+      summarize(connection.take(8), port)
+    );
+    server.join().unwrap();
+  }
+
+  #[test]
+  fn test_auth_rejected() {
+    let (port, server) = stand_in(1, |_, mut socket| {
+      auth(&mut socket, -101);
+    });
+    let result =
+      DanmakuConnection::connect_with(&client(&[port]), 1029, None, &options(quick_reconnect()));
+    crate::utils::assert_error_code(result, -101);
+    server.join().unwrap();
+  }
+
+  /// Reads frames of a busy room authenticated with `protover` until one of `version` arrives
+  fn capture(protover: u16, version: ProtocolVersion) -> Vec<u8> {
+    let client = Client::new();
+    let room_id = get_room_init_info(&client, 6).unwrap().data.room_id;
+    let info = get_danmu_info(&client, room_id, None).unwrap().data;
+    let mut auth = auth_body(None, client.buvid(), room_id, info.token);
+    auth.protover = protover;
+    let host = hosts_or_fallback(info.host_list).remove(0);
+    let options = DanmakuOptions::default();
+    let mut socket = authenticate(open(&host, &options).unwrap(), &auth).unwrap();
+    socket
+      .send(Message::Binary(Packet::heartbeat().encode()))
+      .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(60);
+    while Instant::now() < deadline {
+      let bytes = match socket.read() {
+        Ok(Message::Binary(bytes)) => bytes,
+        Ok(_) => continue,
+        Err(tungstenite::Error::Io(e))
+          if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+        {
+          continue
+        }
+        Err(e) => panic!("{e}"),
+      };
+      let packets = Packet::decode(&bytes).unwrap();
+      if PacketHeader::parse(&bytes).unwrap().version == version {
+        assert!(!packets.is_empty());
+        return bytes;
+      }
+    }
+    panic!("No {version:?} frame within a minute");
+  }
+
+  /// Decodes real frames, which the synthetic fixtures stand in for. With `BILI_RECORD` set,
+  /// saves them to `fixtures/danmaku` for `packet::tests::test_decode_captured`.
+  #[test]
+  #[ignore = "connects to a real live room"]
+  fn test_decode_live_frames() {
+    for (protover, version, name) in [
+      (2, ProtocolVersion::Zlib, "captured_zlib"),
+      (3, ProtocolVersion::Brotli, "captured_brotli"),
+    ] {
+      let bytes = capture(protover, version);
+      if std::env::var_os("BILI_RECORD").is_some() {
+        let path = format!("{}/fixtures/danmaku/{name}.bin", env!("CARGO_MANIFEST_DIR"));
+        std::fs::write(path, bytes).unwrap();
+      }
+    }
+  }
+}
@@ -0,0 +1,667 @@
+//! Listening to many live rooms at once on a tokio runtime
+
+use std::{
+  collections::HashMap,
+  io::ErrorKind,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+  },
+  time::Duration,
+};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::{
+  net::TcpStream,
+  sync::{mpsc, Semaphore},
+  task::JoinHandle,
+  time::{timeout, Instant},
+};
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+
+use super::{
+  connection::{auth_body, disconnected, hosts_or_fallback, reconnect_delay},
+  get_danmu_info_async,
+  packet::{AuthBody, AuthReply, Operation, Packet},
+  DanmakuMessage, DanmakuOptions, DanmuHost,
+};
+use crate::{
+  apis::live::info::get_room_init_info_async,
+  client::{AsyncClient, RateLimit, TokenBucket},
+  credential::Credential,
+  error::ApiError,
+};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Messages not yet received from a [DanmakuListener] before the connections wait
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct ListenerOptions {
+  /// Options of each connection
+  pub connection: DanmakuOptions,
+  /// 同时保持的连接数上限, 超出的直播间等到有连接被移除后才连接. 默认200.
+  pub max_connections: usize,
+  /// 所有直播间建立连接(包括重连)的速率限制, 默认每秒5个
+  pub connect_rate: RateLimit,
+}
+
+impl Default for ListenerOptions {
+  fn default() -> Self {
+    Self {
+      connection: DanmakuOptions::default(),
+      max_connections: 200,
+      connect_rate: RateLimit::new(5, Duration::from_millis(200)),
+    }
+  }
+}
+
+/// A [DanmakuMessage] of the room `room_id` (真实房间号)
+#[derive(Debug)]
+pub struct RoomMessage {
+  pub room_id: i32,
+  pub message: crate::Result<DanmakuMessage>,
+}
+
+/// 多直播间信息流监听.
+///
+/// Each room is listened to by a task on the tokio runtime, which connects, reconnects and
+/// keeps the heartbeat going like a [DanmakuConnection](super::DanmakuConnection), including
+/// the first connection. The messages of all rooms arrive at the receiver returned by
+/// [DanmakuListener::new], tagged with their room. A room whose connection gives up sends
+/// its last error and is removed.
+///
+/// ```no_run
+/// use bili_api_rs::{
+///   apis::live::danmaku::{DanmakuListener, ListenerOptions},
+///   AsyncClient,
+/// };
+///
+/// # async fn run() -> bili_api_rs::Result<()> {
+/// let (listener, mut messages) =
+///   DanmakuListener::new(AsyncClient::new(), None, ListenerOptions::default());
+/// for room_id in [1029, 21452505] {
+///   listener.add_room(room_id).await?;
+/// }
+/// while let Some(message) = messages.recv().await {
+///   println!("{}: {:?}", message.room_id, message.message);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct DanmakuListener {
+  client: AsyncClient,
+  credential: Option<Credential>,
+  options: ListenerOptions,
+  shared: Arc<Shared>,
+  sender: mpsc::Sender<RoomMessage>,
+}
+
+/// What the tasks of the rooms share with the listener
+struct Shared {
+  /// The connection budget, a task holds a permit while it is connected or reconnecting
+  budget: Arc<Semaphore>,
+  connect_rate: TokenBucket,
+  rooms: Mutex<HashMap<i32, Room>>,
+  /// Tells a task apart from a later one of the same room
+  generations: AtomicU64,
+}
+
+struct Room {
+  generation: u64,
+  task: JoinHandle<()>,
+}
+
+impl DanmakuListener {
+  /// A listener without rooms, and the receiver of the messages of all rooms. With
+  /// `credential`, the connections are authenticated as its user.
+  pub fn new(
+    client: AsyncClient,
+    credential: Option<Credential>,
+    options: ListenerOptions,
+  ) -> (Self, mpsc::Receiver<RoomMessage>) {
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    let shared = Arc::new(Shared {
+      budget: Arc::new(Semaphore::new(options.max_connections)),
+      connect_rate: TokenBucket::new(options.connect_rate),
+      rooms: Mutex::new(HashMap::new()),
+      generations: AtomicU64::new(0),
+    });
+    let listener = Self {
+      client,
+      credential,
+      options,
+      shared,
+      sender,
+    };
+    (listener, receiver)
+  }
+
+  /// Starts listening to `room_id`, which may be a short id, and returns its real id, which
+  /// the messages are tagged with. Does nothing if the room is listened to already.
+  pub async fn add_room(&self, room_id: i32) -> crate::Result<i32> {
+    let room_id = get_room_init_info_async(&self.client, room_id)
+      .await?
+      .data
+      .room_id;
+    let mut rooms = self.shared.rooms.lock().expect("Lock poisoned");
+    if rooms.contains_key(&room_id) {
+      return Ok(room_id);
+    }
+    let generation = self.shared.generations.fetch_add(1, Ordering::Relaxed);
+    let session = Session {
+      client: self.client.clone(),
+      credential: self.credential.clone(),
+      room_id,
+      generation,
+      options: self.options.connection.clone(),
+      auth: AuthBody::new(0, room_id, "", ""),
+      hosts: Vec::new(),
+      next_host: 0,
+      refetch: false,
+      shared: Arc::clone(&self.shared),
+      sender: self.sender.clone(),
+    };
+    // The task cannot remove itself from `rooms` before it is inserted, as the lock is held
+    let task = tokio::spawn(session.run());
+    rooms.insert(room_id, Room { generation, task });
+    Ok(room_id)
+  }
+
+  /// Stops listening to the room of the real id `room_id`, returns false if it was not
+  /// listened to
+  pub fn remove_room(&self, room_id: i32) -> bool {
+    let task = self
+      .shared
+      .rooms
+      .lock()
+      .expect("Lock poisoned")
+      .remove(&room_id);
+    match task {
+      Some(room) => {
+        room.task.abort();
+        true
+      }
+      None => false,
+    }
+  }
+
+  /// The real ids of the rooms listened to
+  pub fn rooms(&self) -> Vec<i32> {
+    let rooms = self.shared.rooms.lock().expect("Lock poisoned");
+    let mut ids: Vec<_> = rooms.keys().copied().collect();
+    ids.sort_unstable();
+    ids
+  }
+
+  /// Number of rooms connected or reconnecting, as opposed to waiting for the budget
+  pub fn connections(&self) -> usize {
+    self.options.max_connections - self.shared.budget.available_permits()
+  }
+}
+
+impl Drop for DanmakuListener {
+  fn drop(&mut self) {
+    let rooms = self.shared.rooms.lock().expect("Lock poisoned");
+    for room in rooms.values() {
+      room.task.abort();
+    }
+  }
+}
+
+/// Connects to `host` and completes the WebSocket handshake
+async fn open(host: &DanmuHost, options: &DanmakuOptions) -> crate::Result<Socket> {
+  let (scheme, port) = match options.tls {
+    true => ("wss", host.wss_port),
+    false => ("ws", host.ws_port),
+  };
+  let url = format!("{scheme}://{}:{port}/sub", host.host);
+  let connect = async {
+    let stream = TcpStream::connect((host.host.as_str(), port)).await?;
+    let (socket, _) = tokio_tungstenite::client_async_tls(url, stream).await?;
+    Ok(socket)
+  };
+  timeout(options.timeout, connect)
+    .await
+    .unwrap_or_else(|_| Err(std::io::Error::from(ErrorKind::TimedOut).into()))
+}
+
+/// Sends the auth packet and waits for its reply, within the timeout of `options`
+async fn authenticate(
+  mut socket: Socket,
+  auth: &AuthBody,
+  options: &DanmakuOptions,
+) -> crate::Result<Socket> {
+  socket
+    .send(Message::Binary(Packet::auth(auth).encode()))
+    .await?;
+  let reply = async {
+    while let Some(message) = socket.next().await {
+      let Message::Binary(bytes) = message? else {
+        continue;
+      };
+      for packet in Packet::decode(&bytes)? {
+        if packet.operation == Operation::AuthReply {
+          return packet.json::<AuthReply>();
+        }
+      }
+    }
+    Err(disconnected(
+      ErrorKind::ConnectionAborted,
+      "Closed by the server",
+    ))
+  };
+  let reply: AuthReply = timeout(options.timeout, reply)
+    .await
+    .unwrap_or_else(|_| Err(std::io::Error::from(ErrorKind::TimedOut).into()))?;
+  match reply.is_success() {
+    true => Ok(socket),
+    false => Err(ApiError::new(reply.code, "Danmaku auth rejected".to_string()).into()),
+  }
+}
+
+/// The task of a room, see `Session` of [DanmakuConnection](super::DanmakuConnection)
+struct Session {
+  client: AsyncClient,
+  credential: Option<Credential>,
+  room_id: i32,
+  generation: u64,
+  options: DanmakuOptions,
+  auth: AuthBody,
+  /// Fetched before connecting, and again if the token is rejected
+  hosts: Vec<DanmuHost>,
+  next_host: usize,
+  /// Whether the token was rejected and is to be refetched before connecting
+  refetch: bool,
+  shared: Arc<Shared>,
+  sender: mpsc::Sender<RoomMessage>,
+}
+
+impl Session {
+  async fn run(mut self) {
+    let budget = Arc::clone(&self.shared.budget);
+    let Ok(permit) = budget.acquire_owned().await else {
+      return;
+    };
+    let result = self.listen().await;
+    drop(permit);
+    {
+      let mut rooms = self.shared.rooms.lock().expect("Lock poisoned");
+      if rooms
+        .get(&self.room_id)
+        .is_some_and(|room| room.generation == self.generation)
+      {
+        rooms.remove(&self.room_id);
+      }
+    }
+    if let Err(e) = result {
+      self.emit(Err(e)).await;
+    }
+  }
+
+  /// Connects and reconnects until the [RetryPolicy](crate::client::RetryPolicy) gives up,
+  /// which is the error returned. Returns Ok if the receiver is dropped.
+  async fn listen(&mut self) -> crate::Result<()> {
+    let mut attempt = 0;
+    let mut lost = None;
+    loop {
+      if let Some(error) = lost.take() {
+        attempt += 1;
+        let Some(delay) = reconnect_delay(&self.options.reconnect, &error, attempt) else {
+          return Err(error);
+        };
+        let reconnecting = DanmakuMessage::Reconnecting {
+          attempt,
+          delay,
+          reason: error.to_string(),
+        };
+        if !self.emit(Ok(reconnecting)).await {
+          return Ok(());
+        }
+        tokio::time::sleep(delay).await;
+      }
+
+      match self.connect().await {
+        Ok(None) => return Ok(()),
+        Ok(Some((socket, host))) => {
+          attempt = 0;
+          if !self.emit(Ok(DanmakuMessage::Connected { host })).await {
+            return Ok(());
+          }
+          match self.read_messages(socket).await {
+            Ok(()) => return Ok(()),
+            Err(e) => lost = Some(e),
+          }
+        }
+        Err(e) => lost = Some(e),
+      }
+    }
+  }
+
+  /// Connects to the next host in turn. A rejected token is refetched on the next attempt.
+  /// Returns None if the receiver is dropped.
+  async fn connect(&mut self) -> crate::Result<Option<(Socket, DanmuHost)>> {
+    if self.hosts.is_empty() || self.refetch {
+      self.fetch_info().await?;
+      self.refetch = false;
+    }
+    match self.connect_next().await {
+      Err(crate::Error::Api(e)) => {
+        if !self
+          .emit(Ok(DanmakuMessage::AuthFailed { code: e.code() }))
+          .await
+        {
+          return Ok(None);
+        }
+        self.refetch = true;
+        Err(e.into())
+      }
+      result => result.map(Some),
+    }
+  }
+
+  async fn fetch_info(&mut self) -> crate::Result<()> {
+    let info = get_danmu_info_async(&self.client, self.room_id, self.credential.as_ref())
+      .await?
+      .data;
+    let credential = self.credential.as_ref().map(|c| self.client.credential(c));
+    self.auth = auth_body(credential, self.client.buvid(), self.room_id, info.token);
+    self.hosts = hosts_or_fallback(info.host_list);
+    self.next_host = 0;
+    Ok(())
+  }
+
+  async fn connect_next(&mut self) -> crate::Result<(Socket, DanmuHost)> {
+    let wait = self.shared.connect_rate.reserve();
+    if !wait.is_zero() {
+      tokio::time::sleep(wait).await;
+    }
+    let host = self.hosts[self.next_host % self.hosts.len()].clone();
+    self.next_host += 1;
+    let socket = open(&host, &self.options).await?;
+    let socket = authenticate(socket, &self.auth, &self.options).await?;
+    Ok((socket, host))
+  }
+
+  /// Returns Ok if the receiver is dropped, or the error that the connection is lost with
+  async fn read_messages(&self, mut socket: Socket) -> crate::Result<()> {
+    let heartbeat_interval = self.options.heartbeat_interval;
+    let mut next_heartbeat = Instant::now();
+    let mut last_received = Instant::now();
+    loop {
+      if Instant::now() >= next_heartbeat {
+        socket
+          .send(Message::Binary(Packet::heartbeat().encode()))
+          .await?;
+        next_heartbeat += heartbeat_interval;
+      }
+      let bytes = match timeout(next_heartbeat - Instant::now(), socket.next()).await {
+        Ok(Some(Ok(Message::Binary(bytes)))) => bytes,
+        Ok(Some(Ok(Message::Close(_)))) | Ok(None) => {
+          return Err(disconnected(
+            ErrorKind::ConnectionAborted,
+            "Closed by the server",
+          ));
+        }
+        Ok(Some(Ok(_))) => {
+          last_received = Instant::now();
+          continue;
+        }
+        Ok(Some(Err(e))) => return Err(e.into()),
+        Err(_) => {
+          // The server replies to every heartbeat, so it is gone if silent for long
+          if last_received.elapsed() > heartbeat_interval * 2 {
+            return Err(disconnected(
+              ErrorKind::TimedOut,
+              "No message from the server",
+            ));
+          }
+          continue;
+        }
+      };
+      last_received = Instant::now();
+      let packets = match Packet::decode(&bytes) {
+        Ok(packets) => packets,
+        Err(e) => match self.emit(Err(e)).await {
+          true => continue,
+          false => return Ok(()),
+        },
+      };
+      for packet in packets {
+        if let Some(message) = DanmakuMessage::from_packet(&packet) {
+          if !self.emit(message).await {
+            return Ok(());
+          }
+        }
+      }
+    }
+  }
+
+  /// Returns false if the receiver is dropped
+  async fn emit(&self, message: crate::Result<DanmakuMessage>) -> bool {
+    let message = RoomMessage {
+      room_id: self.room_id,
+      message,
+    };
+    self.sender.send(message).await.is_ok()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    net::TcpListener,
+    thread::{self, JoinHandle},
+  };
+
+  use reqwest::StatusCode;
+  use serde_json::Value;
+  use tungstenite::WebSocket;
+
+  use super::*;
+  use crate::{
+    apis::live::danmaku::packet::ProtocolVersion,
+    client::{AsyncTransport, HttpRequest, HttpResponse, RetryClass, RetryPolicy, TransportFuture},
+  };
+
+  /// Every room is a real room of its own id, served by the broadcast server at `port`
+  struct Fake {
+    port: u16,
+  }
+
+  impl AsyncTransport for Fake {
+    fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+      let port = self.port;
+      Box::pin(async move {
+        let body = match request.url.path() {
+          "/room/v1/Room/room_init" => {
+            let (_, id) = request.url.query_pairs().find(|(k, _)| k == "id").unwrap();
+            format!(
+              r#"{{"code":0,"msg":"ok","message":"ok","data":{{"room_id":{id},"short_id":0,"uid":9617619,"need_p2p":0,"is_hidden":false,"is_locked":false,"is_portrait":false,"live_status":1,"hidden_till":0,"lock_till":0,"encrypted":false,"pwd_verified":false,"live_time":0,"room_shield":0,"is_sp":0,"special_type":0}}}}"#
+            )
+          }
+          "/xlive/web-room/v1/index/getDanmuInfo" => format!(
+            r#"{{"code":0,"message":"0","ttl":1,"data":{{"group":"live","business_id":0,"refresh_row_factor":0.125,"refresh_rate":100,"max_delay":5000,"token":"token","host_list":[{{"host":"127.0.0.1","port":2243,"wss_port":443,"ws_port":{port}}}]}}}}"#
+          ),
+          _ => r#"{"code":-404,"message":"啥都木有"}"#.to_string(),
+        };
+        Ok(HttpResponse::new(StatusCode::OK, body))
+      })
+    }
+  }
+
+  fn listener(port: u16, max_connections: usize) -> (DanmakuListener, mpsc::Receiver<RoomMessage>) {
+    listener_with(port, max_connections, RetryPolicy::none())
+  }
+
+  fn listener_with(
+    port: u16,
+    max_connections: usize,
+    reconnect: RetryPolicy,
+  ) -> (DanmakuListener, mpsc::Receiver<RoomMessage>) {
+    let client = AsyncClient::builder()
+      .async_transport(Fake { port })
+      .build_async();
+    let options = ListenerOptions {
+      connection: DanmakuOptions {
+        tls: false,
+        reconnect,
+        ..Default::default()
+      },
+      max_connections,
+      ..Default::default()
+    };
+    DanmakuListener::new(client, None, options)
+  }
+
+  /// A stand-in broadcast server accepting `count` connections, each of which is
+  /// authenticated, answers the first heartbeat and is kept until the client leaves
+  fn stand_in(count: usize) -> (u16, JoinHandle<Vec<i64>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+      let connections: Vec<_> = (0..count)
+        .map(|_| {
+          let (stream, _) = listener.accept().unwrap();
+          thread::spawn(move || {
+            let mut socket = tungstenite::accept(stream).unwrap();
+            let room_id = auth(&mut socket, 0);
+            let heartbeat = Packet::decode(&socket.read().unwrap().into_data()).unwrap();
+            assert_eq!(vec![Packet::heartbeat()], heartbeat);
+            let reply = include_bytes!(concat!(
+              env!("CARGO_MANIFEST_DIR"),
+              "/fixtures/danmaku/heartbeat_reply.bin"
+            ));
+            socket.send(Message::Binary(reply.to_vec())).unwrap();
+            while socket.read().is_ok() {}
+            room_id
+          })
+        })
+        .collect();
+      connections.into_iter().map(|c| c.join().unwrap()).collect()
+    });
+    (port, server)
+  }
+
+  /// Reads the auth packet and replies with `code`, returns the room id
+  fn auth(socket: &mut WebSocket<std::net::TcpStream>, code: i32) -> i64 {
+    let auth = Packet::decode(&socket.read().unwrap().into_data()).unwrap();
+    assert_eq!(Operation::Auth, auth[0].operation);
+    let auth: Value = auth[0].json().unwrap();
+    let reply = Packet::new(
+      ProtocolVersion::Control,
+      Operation::AuthReply,
+      format!(r#"{{"code":{code}}}"#).into_bytes(),
+    );
+    socket.send(Message::Binary(reply.encode())).unwrap();
+    auth["roomid"].as_i64().unwrap()
+  }
+
+  fn summary(message: &RoomMessage) -> String {
+    let summary = match &message.message {
+      Ok(DanmakuMessage::Connected { .. }) => "connected".to_string(),
+      Ok(DanmakuMessage::Popularity(popularity)) => format!("popularity {popularity}"),
+      Ok(message) => format!("{message:?}"),
+      Err(e) => format!("error {e}"),
+    };
+    format!("{} {summary}", message.room_id)
+  }
+
+  #[tokio::test]
+  async fn test_connection_budget() {
+    let (port, server) = stand_in(2);
+    let (listener, mut messages) = listener(port, 1);
+    assert_eq!(5440, listener.add_room(5440).await.unwrap());
+    assert_eq!(5441, listener.add_room(5441).await.unwrap());
+    // Already listened to
+    assert_eq!(5441, listener.add_room(5441).await.unwrap());
+    assert_eq!(vec![5440, 5441], listener.rooms());
+
+    for expected in ["5440 connected", "5440 popularity 1"] {
+      assert_eq!(expected, summary(&messages.recv().await.unwrap()));
+    }
+    // The other room waits for the connection budget
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(messages.try_recv().is_err());
+    assert_eq!(1, listener.connections());
+
+    assert!(listener.remove_room(5440));
+    assert!(!listener.remove_room(5440));
+    for expected in ["5441 connected", "5441 popularity 1"] {
+      assert_eq!(expected, summary(&messages.recv().await.unwrap()));
+    }
+    assert_eq!(vec![5441], listener.rooms());
+
+    // The connections are closed as the runtime drops the aborted tasks
+    drop(listener);
+    let rooms = tokio::task::spawn_blocking(move || server.join().unwrap());
+    assert_eq!(vec![5440, 5441], rooms.await.unwrap());
+  }
+
+  #[tokio::test]
+  async fn test_reconnect_after_repeated_auth_failures() {
+    let server = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = server.local_addr().unwrap().port();
+    let server = thread::spawn(move || {
+      for code in [-101, -101, 0] {
+        let (stream, _) = server.accept().unwrap();
+        let mut socket = tungstenite::accept(stream).unwrap();
+        auth(&mut socket, code);
+        if code == 0 {
+          while socket.read().is_ok() {}
+        }
+      }
+    });
+    let mut policy = RetryPolicy::none();
+    policy
+      .max_attempts(5)
+      .base_delay(Duration::from_millis(10))
+      .jitter(0.0)
+      .retry_on(RetryClass::Network);
+    let (listener, mut messages) = listener_with(port, 1, policy);
+    listener.add_room(5440).await.unwrap();
+
+    let mut summaries = Vec::new();
+    for _ in 0..5 {
+      summaries.push(match messages.recv().await.unwrap().message {
+        Ok(DanmakuMessage::AuthFailed { code }) => format!("auth failed {code}"),
+        Ok(DanmakuMessage::Reconnecting { attempt, .. }) => format!("reconnecting {attempt}"),
+        Ok(DanmakuMessage::Connected { .. }) => "connected".to_string(),
+        message => panic!("Unexpected message: {message:?}"),
+      });
+    }
+    // Not given up on, though the policy only retries network errors
+    assert_eq!(
+      vec![
+        "auth failed -101",
+        "reconnecting 1",
+        "auth failed -101",
+        "reconnecting 2",
+        "connected",
+      ],
+      summaries
+    );
+    drop(listener);
+    tokio::task::spawn_blocking(move || server.join().unwrap())
+      .await
+      .unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_give_up() {
+    let port = TcpListener::bind("127.0.0.1:0")
+      .unwrap()
+      .local_addr()
+      .unwrap()
+      .port();
+    let (listener, mut messages) = listener(port, 1);
+    listener.add_room(5440).await.unwrap();
+    let message = messages.recv().await.unwrap();
+    assert_eq!(5440, message.room_id);
+    assert!(matches!(message.message, Err(crate::Error::Io(_))));
+    // Removed, and the budget is free for other rooms
+    assert!(listener.rooms().is_empty());
+    assert_eq!(0, listener.connections());
+  }
+}
@@ -0,0 +1,88 @@
+//! 直播间弹幕及其他广播消息 (信息流WebSocket)
+//!
+//! https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/live/message_stream.md
+
+mod connection;
+#[cfg(feature = "danmaku-async")]
+mod listener;
+pub mod packet;
+
+use serde::{Deserialize, Serialize};
+
+pub use self::connection::{DanmakuConnection, DanmakuMessage, DanmakuOptions};
+#[cfg(feature = "danmaku-async")]
+pub use self::listener::{DanmakuListener, ListenerOptions, RoomMessage};
+#[cfg(feature = "async")]
+use crate::client::AsyncClient;
+use crate::{
+  client::{Client, Endpoint, Host, Params},
+  credential::Credential,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DanmuInfoResponse {
+  pub data: DanmuInfoData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DanmuInfoData {
+  pub group: String,
+  pub business_id: i32,
+  pub refresh_row_factor: f64,
+  pub refresh_rate: i32,
+  pub max_delay: i32,
+  /// 认证包所需的token
+  pub token: String,
+  /// 可用的信息流服务器
+  pub host_list: Vec<DanmuHost>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DanmuHost {
+  pub host: String,
+  /// TCP端口
+  pub port: u16,
+  pub wss_port: u16,
+  pub ws_port: u16,
+}
+
+/// 信息流服务器及认证token
+#[derive(Debug, Clone)]
+pub struct GetDanmuInfo {
+  /// 真实房间号
+  pub room_id: i32,
+}
+
+impl Endpoint for GetDanmuInfo {
+  type Response = DanmuInfoResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
+
+  fn path(&self) -> String {
+    "/xlive/web-room/v1/index/getDanmuInfo".to_string()
+  }
+
+  fn query(&self) -> crate::Result<Params> {
+    Ok(Params::new().with("id", self.room_id).with("type", 0))
+  }
+}
+
+/// 登录后获取的token才能在认证包中带上uid, 否则收到的弹幕发送者信息是打码的
+pub fn get_danmu_info(
+  client: &Client,
+  room_id: i32,
+  credential: Option<&Credential>,
+) -> crate::Result<DanmuInfoResponse> {
+  client.call(&GetDanmuInfo { room_id }, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_danmu_info_async(
+  client: &AsyncClient,
+  room_id: i32,
+  credential: Option<&Credential>,
+) -> crate::Result<DanmuInfoResponse> {
+  client.call(&GetDanmuInfo { room_id }, credential).await
+}
@@ -0,0 +1,487 @@
+//! 信息流的数据包格式
+//!
+//! 每个数据包由16字节的头部和正文组成, 头部各字段均为大端序:
+//!
+//! | 偏移 | 长度 | 字段 |
+//! | --- | --- | --- |
+//! | 0 | 4 | 数据包总长度, 包括头部 |
+//! | 4 | 2 | 头部长度, 固定为16 |
+//! | 6 | 2 | 协议版本, see [ProtocolVersion] |
+//! | 8 | 4 | 操作码, see [Operation] |
+//! | 12 | 4 | 序列号 |
+//!
+//! 一个WebSocket消息可能包含多个数据包, 而压缩的数据包的正文解压后又是若干个数据包.
+
+use std::io::Read;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::apis::live::info::Uid;
+
+pub const HEADER_LEN: usize = 16;
+
+/// 心跳包的正文, 与网页端一致
+const HEARTBEAT_BODY: &[u8] = b"[object Object]";
+
+/// 数据包的操作码
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+  /// 心跳包, 客户端每30秒发送一次
+  Heartbeat,
+  /// 心跳回复, 正文为人气值
+  HeartbeatReply,
+  /// 广播消息, 正文为JSON, 如弹幕和礼物
+  Message,
+  /// 认证包, 连接后第一个发送的数据包
+  Auth,
+  /// 认证回复
+  AuthReply,
+  /// 未收录的操作码
+  Other(u32),
+}
+
+impl From<u32> for Operation {
+  fn from(value: u32) -> Self {
+    match value {
+      2 => Self::Heartbeat,
+      3 => Self::HeartbeatReply,
+      5 => Self::Message,
+      7 => Self::Auth,
+      8 => Self::AuthReply,
+      other => Self::Other(other),
+    }
+  }
+}
+
+impl From<Operation> for u32 {
+  fn from(value: Operation) -> Self {
+    match value {
+      Operation::Heartbeat => 2,
+      Operation::HeartbeatReply => 3,
+      Operation::Message => 5,
+      Operation::Auth => 7,
+      Operation::AuthReply => 8,
+      Operation::Other(other) => other,
+    }
+  }
+}
+
+/// 数据包的协议版本, 即正文的格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProtocolVersion {
+  /// 未压缩的JSON
+  Json,
+  /// 未压缩, 用于心跳和认证
+  Control,
+  /// zlib压缩的若干数据包
+  Zlib,
+  /// brotli压缩的若干数据包
+  Brotli,
+  /// 未收录的协议版本
+  Other(u16),
+}
+
+impl From<u16> for ProtocolVersion {
+  fn from(value: u16) -> Self {
+    match value {
+      0 => Self::Json,
+      1 => Self::Control,
+      2 => Self::Zlib,
+      3 => Self::Brotli,
+      other => Self::Other(other),
+    }
+  }
+}
+
+impl From<ProtocolVersion> for u16 {
+  fn from(value: ProtocolVersion) -> Self {
+    match value {
+      ProtocolVersion::Json => 0,
+      ProtocolVersion::Control => 1,
+      ProtocolVersion::Zlib => 2,
+      ProtocolVersion::Brotli => 3,
+      ProtocolVersion::Other(other) => other,
+    }
+  }
+}
+
+/// The 16-byte header of a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketHeader {
+  /// Length of the whole packet, including the header
+  pub packet_len: u32,
+  pub header_len: u16,
+  pub version: ProtocolVersion,
+  pub operation: Operation,
+  pub sequence: u32,
+}
+
+impl PacketHeader {
+  /// Parses the header at the start of `bytes`
+  pub fn parse(bytes: &[u8]) -> crate::Result<Self> {
+    let Some(bytes) = bytes.get(..HEADER_LEN) else {
+      return Err(malformed(format!(
+        "header needs {HEADER_LEN} bytes, got {}",
+        bytes.len()
+      )));
+    };
+    let u16_at = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+    let u32_at =
+      |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+    let header = Self {
+      packet_len: u32_at(0),
+      header_len: u16_at(4),
+      version: u16_at(6).into(),
+      operation: u32_at(8).into(),
+      sequence: u32_at(12),
+    };
+    if (header.header_len as usize) < HEADER_LEN || header.packet_len < header.header_len as u32 {
+      return Err(malformed(format!(
+        "packet length {} with header length {}",
+        header.packet_len, header.header_len
+      )));
+    }
+    Ok(header)
+  }
+
+  pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+    let mut bytes = [0; HEADER_LEN];
+    bytes[0..4].copy_from_slice(&self.packet_len.to_be_bytes());
+    bytes[4..6].copy_from_slice(&self.header_len.to_be_bytes());
+    bytes[6..8].copy_from_slice(&u16::from(self.version).to_be_bytes());
+    bytes[8..12].copy_from_slice(&u32::from(self.operation).to_be_bytes());
+    bytes[12..16].copy_from_slice(&self.sequence.to_be_bytes());
+    bytes
+  }
+}
+
+/// 认证包的正文
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AuthBody {
+  /// 未登录时为0
+  pub uid: Uid,
+  /// 真实房间号, 不能是短号
+  pub roomid: i32,
+  /// 希望服务器下发的协议版本, 3为brotli
+  pub protover: u16,
+  pub buvid: String,
+  pub platform: String,
+  #[serde(rename = "type")]
+  pub kind: i32,
+  /// `getDanmuInfo`返回的token
+  pub key: String,
+}
+
+impl AuthBody {
+  pub fn new(uid: Uid, room_id: i32, buvid: impl Into<String>, key: impl Into<String>) -> Self {
+    Self {
+      uid,
+      roomid: room_id,
+      protover: 3,
+      buvid: buvid.into(),
+      platform: "web".to_string(),
+      kind: 2,
+      key: key.into(),
+    }
+  }
+}
+
+/// 认证回复的正文
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct AuthReply {
+  /// 0: 成功; -101: token错误
+  pub code: i32,
+}
+
+impl AuthReply {
+  pub fn is_success(self) -> bool {
+    self.code == 0
+  }
+}
+
+/// A packet of the broadcast protocol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+  pub version: ProtocolVersion,
+  pub operation: Operation,
+  pub sequence: u32,
+  pub body: Vec<u8>,
+}
+
+impl Packet {
+  pub fn new(version: ProtocolVersion, operation: Operation, body: Vec<u8>) -> Self {
+    Self {
+      version,
+      operation,
+      sequence: 1,
+      body,
+    }
+  }
+
+  pub fn auth(body: &AuthBody) -> Self {
+    let body = serde_json::to_vec(body).expect("AuthBody is always serializable");
+    Self::new(ProtocolVersion::Control, Operation::Auth, body)
+  }
+
+  pub fn heartbeat() -> Self {
+    Self::new(
+      ProtocolVersion::Control,
+      Operation::Heartbeat,
+      HEARTBEAT_BODY.to_vec(),
+    )
+  }
+
+  pub fn header(&self) -> PacketHeader {
+    PacketHeader {
+      packet_len: (HEADER_LEN + self.body.len()) as u32,
+      header_len: HEADER_LEN as u16,
+      version: self.version,
+      operation: self.operation,
+      sequence: self.sequence,
+    }
+  }
+
+  /// The bytes to send as a binary WebSocket message
+  pub fn encode(&self) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN + self.body.len());
+    bytes.extend_from_slice(&self.header().to_bytes());
+    bytes.extend_from_slice(&self.body);
+    bytes
+  }
+
+  /// Decodes the packets of a binary WebSocket message. Compressed packets are replaced by
+  /// the packets they contain, so none of the returned packets is compressed.
+  pub fn decode(mut bytes: &[u8]) -> crate::Result<Vec<Packet>> {
+    let mut packets = Vec::new();
+    while !bytes.is_empty() {
+      let header = PacketHeader::parse(bytes)?;
+      let Some(packet) = bytes.get(..header.packet_len as usize) else {
+        return Err(malformed(format!(
+          "packet length {} exceeds the {} bytes left",
+          header.packet_len,
+          bytes.len()
+        )));
+      };
+      let body = &packet[header.header_len as usize..];
+      match header.version {
+        ProtocolVersion::Zlib => packets.extend(Self::decode(&decompress(
+          flate2::read::ZlibDecoder::new(body),
+        )?)?),
+        ProtocolVersion::Brotli => packets.extend(Self::decode(&decompress(
+          brotli_decompressor::Decompressor::new(body, 4096),
+        )?)?),
+        version => packets.push(Packet {
+          version,
+          operation: header.operation,
+          sequence: header.sequence,
+          body: body.to_vec(),
+        }),
+      }
+      bytes = &bytes[packet.len()..];
+    }
+    Ok(packets)
+  }
+
+  /// 心跳回复中的人气值
+  pub fn popularity(&self) -> Option<u32> {
+    match (self.operation, self.body.get(..4)) {
+      (Operation::HeartbeatReply, Some(bytes)) => Some(u32::from_be_bytes(
+        bytes.try_into().expect("Slice of 4 bytes"),
+      )),
+      _ => None,
+    }
+  }
+
+  /// Deserializes the JSON body, e.g., of a [Operation::Message] or an [AuthReply]
+  pub fn json<T: DeserializeOwned>(&self) -> crate::Result<T> {
+    Ok(serde_json::from_slice(&self.body)?)
+  }
+}
+
+fn decompress(mut reader: impl Read) -> crate::Result<Vec<u8>> {
+  let mut bytes = Vec::new();
+  reader
+    .read_to_end(&mut bytes)
+    .map_err(|e| malformed(format!("cannot decompress: {e}")))?;
+  Ok(bytes)
+}
+
+fn malformed(message: String) -> crate::Error {
+  crate::Error::Protocol(message)
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::Value;
+
+  use super::*;
+
+  /// A frame of `fixtures/danmaku`. All of them are synthetic, see its README.
+  macro_rules! frame {
+    ($name:literal) => {
+      include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/fixtures/danmaku/",
+        $name,
+        ".bin"
+      ))
+    };
+  }
+
+  fn commands(packets: &[Packet]) -> Vec<String> {
+    packets
+      .iter()
+      .map(|p| {
+        assert_eq!(Operation::Message, p.operation);
+        assert_eq!(ProtocolVersion::Json, p.version);
+        p.json::<Value>().unwrap()["cmd"]
+          .as_str()
+          .unwrap()
+          .to_string()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn test_encode_auth() {
+    let body = AuthBody::new(233, 21452505, "buvid", "token");
+    let bytes = Packet::auth(&body).encode();
+    let json = br#"{"uid":233,"roomid":21452505,"protover":3,"buvid":"buvid","platform":"web","type":2,"key":"token"}"#;
+    let mut expected = vec![
+      0,
+      0,
+      0,
+      16 + json.len() as u8,
+      0,
+      16,
+      0,
+      1,
+      0,
+      0,
+      0,
+      7,
+      0,
+      0,
+      0,
+      1,
+    ];
+    expected.extend_from_slice(json);
+    assert_eq!(expected, bytes);
+  }
+
+  #[test]
+  fn test_encode_heartbeat() {
+    let bytes = Packet::heartbeat().encode();
+    assert_eq!(
+      [0, 0, 0, 31, 0, 16, 0, 1, 0, 0, 0, 2, 0, 0, 0, 1],
+      bytes[..HEADER_LEN]
+    );
+    assert_eq!(b"[object Object]", &bytes[HEADER_LEN..]);
+    assert_eq!(vec![Packet::heartbeat()], Packet::decode(&bytes).unwrap());
+  }
+
+  #[test]
+  fn test_header_round_trip() {
+    let header = PacketHeader {
+      packet_len: 20,
+      header_len: 16,
+      version: ProtocolVersion::Other(9),
+      operation: Operation::Other(1000),
+      sequence: 42,
+    };
+    assert_eq!(header, PacketHeader::parse(&header.to_bytes()).unwrap());
+  }
+
+  #[test]
+  fn test_decode_heartbeat_reply() {
+    let packets = Packet::decode(frame!("heartbeat_reply")).unwrap();
+    assert_eq!(1, packets.len());
+    assert_eq!(Operation::HeartbeatReply, packets[0].operation);
+    assert_eq!(Some(1), packets[0].popularity());
+  }
+
+  #[test]
+  fn test_decode_auth_reply() {
+    let packets = Packet::decode(frame!("auth_reply")).unwrap();
+    assert_eq!(Operation::AuthReply, packets[0].operation);
+    assert!(packets[0].json::<AuthReply>().unwrap().is_success());
+    assert_eq!(None, packets[0].popularity());
+  }
+
+  #[test]
+  fn test_decode_zlib() {
+    let packets = Packet::decode(frame!("zlib_messages")).unwrap();
+    assert_eq!(vec!["DANMU_MSG", "WATCHED_CHANGE"], commands(&packets));
+    let danmaku: Value = packets[0].json().unwrap();
+    assert_eq!("第一", danmaku["info"][1]);
+  }
+
+  #[test]
+  fn test_decode_brotli() {
+    let packets = Packet::decode(frame!("brotli_messages")).unwrap();
+    assert_eq!(
+      vec!["DANMU_MSG", "WATCHED_CHANGE", "ONLINE_RANK_COUNT"],
+      commands(&packets)
+    );
+  }
+
+  #[test]
+  fn test_decode_concatenated() {
+    let frame = [
+      &frame!("auth_reply")[..],
+      frame!("heartbeat_reply"),
+      frame!("zlib_messages"),
+    ]
+    .concat();
+    let operations: Vec<_> = Packet::decode(&frame)
+      .unwrap()
+      .iter()
+      .map(|p| p.operation)
+      .collect();
+    assert_eq!(
+      vec![
+        Operation::AuthReply,
+        Operation::HeartbeatReply,
+        Operation::Message,
+        Operation::Message
+      ],
+      operations
+    );
+  }
+
+  #[test]
+  fn test_decode_malformed() {
+    let frame = frame!("zlib_messages");
+    for bytes in [&frame[..10], &frame[..frame.len() - 1]] {
+      assert!(matches!(
+        Packet::decode(bytes),
+        Err(crate::Error::Protocol(_))
+      ));
+    }
+    // Zlib header, but garbage body
+    let mut corrupt = frame.to_vec();
+    corrupt[HEADER_LEN + 4..].fill(0xff);
+    assert!(matches!(
+      Packet::decode(&corrupt),
+      Err(crate::Error::Protocol(_))
+    ));
+  }
+
+  /// Frames captured from a real room by `test_decode_live_frames`, if any were saved
+  #[test]
+  fn test_decode_captured() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/danmaku");
+    for entry in std::fs::read_dir(dir).unwrap() {
+      let path = entry.unwrap().path();
+      let captured = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with("captured_") && name.ends_with(".bin"));
+      if captured {
+        let bytes = std::fs::read(&path).unwrap();
+        let packets = Packet::decode(&bytes).unwrap();
+        assert!(!packets.is_empty(), "{}", path.display());
+      }
+    }
+  }
+}
@@ -0,0 +1,549 @@
+//! 直播间广播消息 (信息流中的`cmd`)
+//!
+//! https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/live/message_stream.md
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::apis::live::info::Uid;
+
+/// 粉丝勋章
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct FanMedal {
+  #[serde(rename = "medal_level")]
+  pub level: i32,
+  #[serde(rename = "medal_name")]
+  pub name: String,
+  /// 主播用户名, `INTERACT_WORD`中没有
+  #[serde(default, rename = "anchor_uname")]
+  pub anchor_name: String,
+  /// 主播的直播间
+  #[serde(rename = "anchor_roomid")]
+  pub room_id: i32,
+  /// 主播的UID
+  #[serde(rename = "target_id")]
+  pub anchor_uid: Uid,
+  /// 0: 无; 1: 总督; 2: 提督; 3: 舰长
+  #[serde(default)]
+  pub guard_level: i32,
+}
+
+/// Deserializes the placeholder medal of users without one, i.e., level 0, as None
+fn fan_medal<'de, D: Deserializer<'de>>(d: D) -> Result<Option<FanMedal>, D::Error> {
+  let medal: Option<FanMedal> = Option::deserialize(d)?;
+  Ok(medal.filter(|medal| medal.level > 0))
+}
+
+/// 表情弹幕的表情
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Emoticon {
+  #[serde(rename = "emoticon_unique")]
+  pub unique: String,
+  pub url: String,
+  pub width: i32,
+  pub height: i32,
+}
+
+/// 弹幕所回复的用户
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplyTo {
+  pub uid: Uid,
+  pub uname: String,
+}
+
+/// 弹幕, i.e., `DANMU_MSG`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Danmaku {
+  pub text: String,
+  /// 发送者UID, 未登录的连接收到的为0
+  pub uid: Uid,
+  /// 发送者用户名, 未登录的连接收到的是打码的
+  pub uname: String,
+  /// 是否为房管
+  pub is_admin: bool,
+  /// see [LiveMessageMode](crate::apis::live::msg::LiveMessageMode)
+  pub mode: i32,
+  pub font_size: i32,
+  pub color: i32,
+  /// 发送时间, Unix时间戳(毫秒)
+  pub timestamp: i64,
+  /// 用户等级
+  pub user_level: i32,
+  /// 发送者在本直播间的大航海等级, 0为无
+  pub guard_level: i32,
+  /// 佩戴的粉丝勋章
+  pub medal: Option<FanMedal>,
+  /// 表情弹幕, 此时`text`为表情名
+  pub emoticon: Option<Emoticon>,
+  pub reply_to: Option<ReplyTo>,
+}
+
+impl Danmaku {
+  /// Parses the positional `info` array
+  fn from_info(info: &Value) -> crate::Result<Self> {
+    let meta = &info[0];
+    let user = &info[2];
+    let (Some(text), Some(uid)) = (info[1].as_str(), user[0].as_i64()) else {
+      return Err(malformed("DANMU_MSG", "no text or sender"));
+    };
+
+    let medal = &info[3];
+    let medal = match medal[0].as_i64() {
+      Some(level) if level > 0 => Some(FanMedal {
+        level: level as i32,
+        name: str_at(&medal[1]),
+        anchor_name: str_at(&medal[2]),
+        room_id: int_at(&medal[3]) as i32,
+        anchor_uid: int_at(&medal[12]),
+        guard_level: int_at(&medal[10]) as i32,
+      }),
+      _ => None,
+    };
+    let emoticon = match int_at(&meta[12]) {
+      1 => serde_json::from_value(meta[13].clone()).ok(),
+      _ => None,
+    };
+    // 回复信息在一个JSON字符串里
+    let extra: Value = meta[15]["extra"]
+      .as_str()
+      .and_then(|extra| serde_json::from_str(extra).ok())
+      .unwrap_or_default();
+    let reply_to = match int_at(&extra["reply_mid"]) {
+      0 => None,
+      uid => Some(ReplyTo {
+        uid,
+        uname: str_at(&extra["reply_uname"]),
+      }),
+    };
+
+    Ok(Self {
+      text: text.to_string(),
+      uid,
+      uname: str_at(&user[1]),
+      is_admin: int_at(&user[2]) == 1,
+      mode: int_at(&meta[1]) as i32,
+      font_size: int_at(&meta[2]) as i32,
+      color: int_at(&meta[3]) as i32,
+      timestamp: int_at(&meta[4]),
+      user_level: int_at(&info[4][0]) as i32,
+      guard_level: int_at(&info[7]) as i32,
+      medal,
+      emoticon,
+      reply_to,
+    })
+  }
+}
+
+/// 送礼, i.e., `SEND_GIFT`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Gift {
+  #[serde(rename = "giftId")]
+  pub gift_id: i64,
+  #[serde(rename = "giftName")]
+  pub gift_name: String,
+  pub num: i32,
+  pub uid: Uid,
+  pub uname: String,
+  pub face: String,
+  /// e.g. "投喂"
+  pub action: String,
+  /// 单价
+  pub price: i64,
+  /// "gold": 金瓜子(1000金瓜子=1元); "silver": 银瓜子
+  pub coin_type: String,
+  pub total_coin: i64,
+  pub timestamp: i64,
+  /// 同一批连击礼物的ID, see [ComboGift]
+  pub batch_combo_id: String,
+  #[serde(deserialize_with = "fan_medal")]
+  pub medal_info: Option<FanMedal>,
+}
+
+/// 礼物连击, i.e., `COMBO_SEND`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct ComboGift {
+  pub gift_id: i64,
+  pub gift_name: String,
+  pub uid: Uid,
+  pub uname: String,
+  pub action: String,
+  pub combo_num: i32,
+  pub total_num: i32,
+  pub combo_total_coin: i64,
+  pub batch_combo_id: String,
+  #[serde(deserialize_with = "fan_medal")]
+  pub medal_info: Option<FanMedal>,
+}
+
+/// 上舰, i.e., `GUARD_BUY`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct GuardBuy {
+  pub uid: Uid,
+  pub username: String,
+  /// 1: 总督; 2: 提督; 3: 舰长
+  pub guard_level: i32,
+  /// 月数
+  pub num: i32,
+  /// 金瓜子
+  pub price: i64,
+  pub gift_id: i64,
+  pub gift_name: String,
+  pub start_time: i64,
+  pub end_time: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct SuperChatUser {
+  pub uname: String,
+  pub face: String,
+  pub guard_level: i32,
+  pub user_level: i32,
+}
+
+/// 醒目留言, i.e., `SUPER_CHAT_MESSAGE`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct SuperChat {
+  pub id: i64,
+  pub uid: Uid,
+  /// 元
+  pub price: i32,
+  pub message: String,
+  pub start_time: i64,
+  pub end_time: i64,
+  /// 持续时间(秒)
+  pub time: i32,
+  pub user_info: SuperChatUser,
+  #[serde(deserialize_with = "fan_medal")]
+  pub medal_info: Option<FanMedal>,
+}
+
+/// 进场或关注等互动, i.e., `INTERACT_WORD`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Interact {
+  pub uid: Uid,
+  pub uname: String,
+  /// 1: 进入直播间; 2: 关注; 3: 分享; 4: 特别关注; 5: 互粉
+  pub msg_type: i32,
+  #[serde(rename = "roomid")]
+  pub room_id: i32,
+  pub timestamp: i64,
+  #[serde(rename = "fans_medal", deserialize_with = "fan_medal")]
+  pub medal: Option<FanMedal>,
+}
+
+/// 点赞, i.e., `LIKE_INFO_V3_CLICK`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct LikeClick {
+  pub uid: Uid,
+  pub uname: String,
+  pub like_text: String,
+  #[serde(rename = "fans_medal", deserialize_with = "fan_medal")]
+  pub medal: Option<FanMedal>,
+}
+
+/// 看过人数, i.e., `WATCHED_CHANGE`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct Watched {
+  pub num: i64,
+  pub text_small: String,
+  pub text_large: String,
+}
+
+/// 直播间信息更改, i.e., `ROOM_CHANGE`
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+#[serde(default)]
+pub struct RoomChange {
+  pub title: String,
+  pub area_id: i32,
+  pub area_name: String,
+  pub parent_area_id: i32,
+  pub parent_area_name: String,
+}
+
+/// 直播间广播消息, parsed from the JSON of a broadcast message by [LiveEvent::from_json].
+///
+/// Commands not covered here, including ones Bilibili adds later, are kept as
+/// [LiveEvent::Unknown].
+#[derive(Debug, Clone, PartialEq)]
+pub enum LiveEvent {
+  /// `DANMU_MSG`
+  Danmaku(Danmaku),
+  /// `SEND_GIFT`
+  Gift(Gift),
+  /// `COMBO_SEND`
+  Combo(ComboGift),
+  /// `GUARD_BUY`
+  GuardBuy(GuardBuy),
+  /// `SUPER_CHAT_MESSAGE`
+  SuperChat(SuperChat),
+  /// `INTERACT_WORD`
+  Interact(Interact),
+  /// `LIKE_INFO_V3_CLICK`
+  Like(LikeClick),
+  /// `LIKE_INFO_V3_UPDATE`, 本场点赞数
+  LikeCount { click_count: i64 },
+  /// `ONLINE_RANK_COUNT`, 高能用户数
+  OnlineRankCount { count: i64 },
+  /// `WATCHED_CHANGE`
+  Watched(Watched),
+  /// `LIVE`, 开播
+  Live { room_id: i32 },
+  /// `PREPARING`, 下播
+  Preparing { room_id: i32 },
+  /// `ROOM_CHANGE`
+  RoomChange(RoomChange),
+  /// `WARNING`, 超管警告
+  Warning { message: String },
+  /// `CUT_OFF`, 直播被超管切断
+  CutOff { message: String },
+  /// 未收录的`cmd`及完整的消息
+  Unknown(String, Value),
+}
+
+impl LiveEvent {
+  /// Parses a broadcast message, i.e., the JSON body of an
+  /// [Operation::Message](super::danmaku::packet::Operation::Message) packet.
+  ///
+  /// Fails only if the message has no `cmd`, or a known command is not in the expected
+  /// shape.
+  pub fn from_json(mut value: Value) -> crate::Result<Self> {
+    let Some(cmd) = value["cmd"].as_str() else {
+      return Err(crate::Error::Protocol(format!(
+        "Broadcast message without cmd: {value}"
+      )));
+    };
+    // 旧版协议的cmd带有后缀, e.g. `DANMU_MSG:4:0:2:2:2:0`
+    let cmd = cmd.split(':').next().unwrap_or_default().to_string();
+    let event = match cmd.as_str() {
+      "DANMU_MSG" => Self::Danmaku(Danmaku::from_info(&value["info"])?),
+      "SEND_GIFT" => Self::Gift(data(&mut value)?),
+      "COMBO_SEND" => Self::Combo(data(&mut value)?),
+      "GUARD_BUY" => Self::GuardBuy(data(&mut value)?),
+      "SUPER_CHAT_MESSAGE" => Self::SuperChat(data(&mut value)?),
+      "INTERACT_WORD" => Self::Interact(data(&mut value)?),
+      "LIKE_INFO_V3_CLICK" => Self::Like(data(&mut value)?),
+      "LIKE_INFO_V3_UPDATE" => Self::LikeCount {
+        click_count: int_at(&value["data"]["click_count"]),
+      },
+      "ONLINE_RANK_COUNT" => Self::OnlineRankCount {
+        count: int_at(&value["data"]["count"]),
+      },
+      "WATCHED_CHANGE" => Self::Watched(data(&mut value)?),
+      "LIVE" => Self::Live {
+        room_id: room_id(&cmd, &value)?,
+      },
+      "PREPARING" => Self::Preparing {
+        room_id: room_id(&cmd, &value)?,
+      },
+      "ROOM_CHANGE" => Self::RoomChange(data(&mut value)?),
+      "WARNING" => Self::Warning {
+        message: str_at(&value["msg"]),
+      },
+      "CUT_OFF" => Self::CutOff {
+        message: str_at(&value["msg"]),
+      },
+      _ => Self::Unknown(cmd, value),
+    };
+    Ok(event)
+  }
+
+  /// The `cmd` of the message
+  pub fn cmd(&self) -> &str {
+    match self {
+      Self::Danmaku(_) => "DANMU_MSG",
+      Self::Gift(_) => "SEND_GIFT",
+      Self::Combo(_) => "COMBO_SEND",
+      Self::GuardBuy(_) => "GUARD_BUY",
+      Self::SuperChat(_) => "SUPER_CHAT_MESSAGE",
+      Self::Interact(_) => "INTERACT_WORD",
+      Self::Like(_) => "LIKE_INFO_V3_CLICK",
+      Self::LikeCount { .. } => "LIKE_INFO_V3_UPDATE",
+      Self::OnlineRankCount { .. } => "ONLINE_RANK_COUNT",
+      Self::Watched(_) => "WATCHED_CHANGE",
+      Self::Live { .. } => "LIVE",
+      Self::Preparing { .. } => "PREPARING",
+      Self::RoomChange(_) => "ROOM_CHANGE",
+      Self::Warning { .. } => "WARNING",
+      Self::CutOff { .. } => "CUT_OFF",
+      Self::Unknown(cmd, _) => cmd,
+    }
+  }
+}
+
+fn data<T: DeserializeOwned>(value: &mut Value) -> crate::Result<T> {
+  let data = value.get_mut("data").map(Value::take).unwrap_or_default();
+  Ok(serde_json::from_value(data)?)
+}
+
+/// `roomid`, which is a string in some commands
+fn room_id(cmd: &str, value: &Value) -> crate::Result<i32> {
+  let room_id = &value["roomid"];
+  room_id
+    .as_i64()
+    .or_else(|| room_id.as_str()?.parse().ok())
+    .map(|id| id as i32)
+    .ok_or_else(|| malformed(cmd, "no roomid"))
+}
+
+fn int_at(value: &Value) -> i64 {
+  value.as_i64().unwrap_or_default()
+}
+
+fn str_at(value: &Value) -> String {
+  value.as_str().unwrap_or_default().to_string()
+}
+
+fn malformed(cmd: &str, reason: &str) -> crate::Error {
+  crate::Error::Protocol(format!("Malformed {cmd}: {reason}"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn messages() -> Vec<Value> {
+    serde_json::from_str(include_str!(concat!(
+      env!("CARGO_MANIFEST_DIR"),
+      "/fixtures/live_events.json"
+    )))
+    .unwrap()
+  }
+
+  fn events() -> Vec<LiveEvent> {
+    messages()
+      .into_iter()
+      .map(|message| LiveEvent::from_json(message).unwrap())
+      .collect()
+  }
+
+  #[test]
+  fn test_danmaku() {
+    let events = events();
+    let LiveEvent::Danmaku(danmaku) = &events[0] else {
+      panic!("Not a danmaku: {:?}", events[0]);
+    };
+    assert_eq!("@主播 晚上好", danmaku.text);
+    assert_eq!((233, "弹幕测试员"), (danmaku.uid, danmaku.uname.as_str()));
+    assert!(danmaku.is_admin);
+    assert_eq!(1700000000123, danmaku.timestamp);
+    assert_eq!((25, 3), (danmaku.user_level, danmaku.guard_level));
+    let medal = danmaku.medal.as_ref().unwrap();
+    assert_eq!((21, "粉丝牌"), (medal.level, medal.name.as_str()));
+    assert_eq!((5440, 9617619), (medal.room_id, medal.anchor_uid));
+    assert_eq!(
+      Some(ReplyTo {
+        uid: 9617619,
+        uname: "主播".to_string()
+      }),
+      danmaku.reply_to
+    );
+    assert_eq!(None, danmaku.emoticon);
+
+    // An emoticon sent by a user without medal, as seen by a connection not logged in
+    let LiveEvent::Danmaku(danmaku) = &events[1] else {
+      panic!("Not a danmaku: {:?}", events[1]);
+    };
+    assert_eq!(0, danmaku.uid);
+    assert_eq!(None, danmaku.medal);
+    assert_eq!(None, danmaku.reply_to);
+    assert_eq!("official_147", danmaku.emoticon.as_ref().unwrap().unique);
+  }
+
+  #[test]
+  fn test_gifts() {
+    let events = events();
+    let LiveEvent::Gift(gift) = &events[2] else {
+      panic!("Not a gift: {:?}", events[2]);
+    };
+    assert_eq!(
+      (31036, "小花花", 5),
+      (gift.gift_id, gift.gift_name.as_str(), gift.num)
+    );
+    assert_eq!(21, gift.medal_info.as_ref().unwrap().level);
+    let LiveEvent::Combo(combo) = &events[3] else {
+      panic!("Not a combo: {:?}", events[3]);
+    };
+    assert_eq!(gift.batch_combo_id, combo.batch_combo_id);
+    assert_eq!(None, combo.medal_info);
+    let LiveEvent::GuardBuy(guard) = &events[4] else {
+      panic!("Not a guard: {:?}", events[4]);
+    };
+    assert_eq!((3, "舰长"), (guard.guard_level, guard.gift_name.as_str()));
+    let LiveEvent::SuperChat(super_chat) = &events[5] else {
+      panic!("Not a super chat: {:?}", events[5]);
+    };
+    assert_eq!(
+      (30, "主播加油"),
+      (super_chat.price, super_chat.message.as_str())
+    );
+    assert_eq!("弹幕测试员", super_chat.user_info.uname);
+  }
+
+  #[test]
+  fn test_room_events() {
+    let events = events();
+    let LiveEvent::Interact(interact) = &events[6] else {
+      panic!("Not an interaction: {:?}", events[6]);
+    };
+    assert_eq!((2, 5440), (interact.msg_type, interact.room_id));
+    assert_eq!("", interact.medal.as_ref().unwrap().anchor_name);
+    let LiveEvent::Like(like) = &events[7] else {
+      panic!("Not a like: {:?}", events[7]);
+    };
+    assert_eq!(None, like.medal);
+    assert_eq!(LiveEvent::LikeCount { click_count: 10086 }, events[8]);
+    assert_eq!(LiveEvent::OnlineRankCount { count: 42 }, events[9]);
+    let LiveEvent::Watched(watched) = &events[10] else {
+      panic!("Not watched: {:?}", events[10]);
+    };
+    assert_eq!(1234, watched.num);
+    assert_eq!(LiveEvent::Live { room_id: 5440 }, events[11]);
+    assert_eq!(LiveEvent::Preparing { room_id: 5440 }, events[12]);
+    let LiveEvent::RoomChange(change) = &events[13] else {
+      panic!("Not a room change: {:?}", events[13]);
+    };
+    assert_eq!("新的标题", change.title);
+    assert!(matches!(&events[14], LiveEvent::Warning { message } if message == "违反直播规范"));
+    assert!(matches!(&events[15], LiveEvent::CutOff { .. }));
+  }
+
+  #[test]
+  fn test_unknown() {
+    let events = events();
+    let LiveEvent::Unknown(cmd, value) = &events[16] else {
+      panic!("Not unknown: {:?}", events[16]);
+    };
+    assert_eq!("STOP_LIVE_ROOM_LIST", cmd);
+    assert_eq!(3, value["data"]["room_id_list"].as_array().unwrap().len());
+    assert_eq!("STOP_LIVE_ROOM_LIST", events[16].cmd());
+  }
+
+  #[test]
+  fn test_cmd() {
+    for (message, event) in messages().iter().zip(events()) {
+      assert_eq!(message["cmd"], event.cmd());
+    }
+    let legacy =
+      serde_json::json!({"cmd": "DANMU_MSG:4:0:2:2:2:0", "info": [[0, 1], "hi", [1, "a"]]});
+    assert_eq!("DANMU_MSG", LiveEvent::from_json(legacy).unwrap().cmd());
+  }
+
+  #[test]
+  fn test_malformed() {
+    for message in [
+      serde_json::json!({"msg": "no cmd"}),
+      serde_json::json!({"cmd": "DANMU_MSG", "info": []}),
+      serde_json::json!({"cmd": "SEND_GIFT", "data": {"num": "five"}}),
+    ] {
+      assert!(LiveEvent::from_json(message).is_err());
+    }
+  }
+}
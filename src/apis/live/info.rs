@@ -4,8 +4,9 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
-use crate::client::Client;
-use crate::utils::handle_api_response;
+#[cfg(feature = "async")]
+use crate::client::AsyncClient;
+use crate::client::{Auth, Client, Endpoint, Host, Params};
 
 /// 默认错误码
 pub const GENERAL_ERROR: i32 = 1;
@@ -96,14 +97,38 @@ pub struct StudioInfo {
   pub master_list: Vec<String>, // Adjust this based on actual expected type
 }
 
+/// 直播间信息
+#[derive(Debug, Clone)]
+pub struct GetLiveRoomInfo {
+  pub room_id: i32,
+}
+
+impl Endpoint for GetLiveRoomInfo {
+  type Response = LiveRoomInfoResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
+
+  fn path(&self) -> String {
+    "/room/v1/Room/get_info".to_string()
+  }
+
+  fn query(&self) -> crate::Result<Params> {
+    Ok(Params::new().with("room_id", self.room_id))
+  }
+}
+
 pub fn get_live_room_info(client: &Client, room_id: i32) -> crate::Result<LiveRoomInfoResponse> {
-  const API_URL: &str = "https://api.live.bilibili.com/room/v1/Room/get_info";
-  let url = format!("{}?room_id={}", API_URL, room_id);
-  let request = client
-    .get(url)
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
-  handle_api_response(client.execute(request)?)
+  client.call(&GetLiveRoomInfo { room_id }, Auth::None)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_live_room_info_async(
+  client: &AsyncClient,
+  room_id: i32,
+) -> crate::Result<LiveRoomInfoResponse> {
+  client.call(&GetLiveRoomInfo { room_id }, Auth::None).await
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -131,14 +156,38 @@ pub struct RoomInitData {
   pub special_type: i32,
 }
 
+/// 直播间初始化信息, 可用于将短号转换为真实房间号
+#[derive(Debug, Clone)]
+pub struct GetRoomInitInfo {
+  pub room_id: i32,
+}
+
+impl Endpoint for GetRoomInitInfo {
+  type Response = RoomInitInfoResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
+
+  fn path(&self) -> String {
+    "/room/v1/Room/room_init".to_string()
+  }
+
+  fn query(&self) -> crate::Result<Params> {
+    Ok(Params::new().with("id", self.room_id))
+  }
+}
+
 pub fn get_room_init_info(client: &Client, room_id: i32) -> crate::Result<RoomInitInfoResponse> {
-  const API_URL: &str = "https://api.live.bilibili.com/room/v1/Room/room_init";
-  let url = format!("{}?id={}", API_URL, room_id);
-  let reqwest = client
-    .get(url)
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
-  handle_api_response(client.execute(reqwest)?)
+  client.call(&GetRoomInitInfo { room_id }, Auth::None)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_room_init_info_async(
+  client: &AsyncClient,
+  room_id: i32,
+) -> crate::Result<RoomInitInfoResponse> {
+  client.call(&GetRoomInitInfo { room_id }, Auth::None).await
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -195,14 +244,38 @@ pub struct RoomNews {
   pub ctime_text: String,
 }
 
+/// 主播信息
+#[derive(Debug, Clone)]
+pub struct GetStreamerInfo {
+  pub uid: Uid,
+}
+
+impl Endpoint for GetStreamerInfo {
+  type Response = StreamerInfoResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
+
+  fn path(&self) -> String {
+    "/live_user/v1/Master/info".to_string()
+  }
+
+  fn query(&self) -> crate::Result<Params> {
+    Ok(Params::new().with("uid", self.uid))
+  }
+}
+
 pub fn get_streamer_info(client: &Client, uid: Uid) -> crate::Result<StreamerInfoResponse> {
-  const API_URL: &str = "https://api.live.bilibili.com/live_user/v1/Master/info";
-  let url = format!("{}?uid={}", API_URL, uid);
-  let request = client
-    .get(url)
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
-  handle_api_response(client.execute(request)?)
+  client.call(&GetStreamerInfo { uid }, Auth::None)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_streamer_info_async(
+  client: &AsyncClient,
+  uid: Uid,
+) -> crate::Result<StreamerInfoResponse> {
+  client.call(&GetStreamerInfo { uid }, Auth::None).await
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -236,22 +309,59 @@ pub struct LiveRoomStatus {
   pub broadcast_type: i32,
 }
 
+/// 批量查询直播间状态
+#[derive(Debug, Clone)]
+pub struct QueryRoomStatusBatch {
+  pub uids: Vec<Uid>,
+}
+
+impl Endpoint for QueryRoomStatusBatch {
+  type Response = RoomStatusBatchResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
+
+  fn path(&self) -> String {
+    "/room/v1/Room/get_status_info_by_uids".to_string()
+  }
+
+  fn query(&self) -> crate::Result<Params> {
+    Ok(
+      self
+        .uids
+        .iter()
+        .map(|uid| ("uids[]", uid.to_string()))
+        .collect(),
+    )
+  }
+}
+
 pub fn query_room_status_batch(
   client: &Client,
   uids: &[Uid],
 ) -> crate::Result<RoomStatusBatchResponse> {
-  const API_URL: &str = "https://api.live.bilibili.com/room/v1/Room/get_status_info_by_uids";
-  let uids_query: String = uids
-    .iter()
-    .map(|&uid| format!("uids[]={}", uid))
-    .collect::<Vec<_>>()
-    .join("&");
-  let url = format!("{}?{}", API_URL, uids_query);
-  let request = client
-    .get(url)
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
-  handle_api_response(client.execute(request)?)
+  client.call(
+    &QueryRoomStatusBatch {
+      uids: uids.to_vec(),
+    },
+    Auth::None,
+  )
+}
+
+#[cfg(feature = "async")]
+pub async fn query_room_status_batch_async(
+  client: &AsyncClient,
+  uids: &[Uid],
+) -> crate::Result<RoomStatusBatchResponse> {
+  client
+    .call(
+      &QueryRoomStatusBatch {
+        uids: uids.to_vec(),
+      },
+      Auth::None,
+    )
+    .await
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -340,6 +450,52 @@ pub struct P2PData {
   pub m_servers: Option<Vec<String>>, // Assuming Vec<String> for simplicity
 }
 
+/// 直播间播放信息, 包含直播流地址
+#[derive(Debug, Clone)]
+pub struct GetLiveRoomPlayInfo {
+  pub room_id: i32,
+  pub protocols: Vec<String>,
+  pub formats: Vec<String>,
+  pub codecs: Vec<String>,
+  pub qn: i32,
+}
+
+impl GetLiveRoomPlayInfo {
+  pub fn new(room_id: i32, protocols: &[&str], formats: &[&str], codecs: &[&str], qn: i32) -> Self {
+    let to_vec = |values: &[&str]| values.iter().map(|v| v.to_string()).collect();
+    Self {
+      room_id,
+      protocols: to_vec(protocols),
+      formats: to_vec(formats),
+      codecs: to_vec(codecs),
+      qn,
+    }
+  }
+}
+
+impl Endpoint for GetLiveRoomPlayInfo {
+  type Response = LiveRoomPlayInfoResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
+
+  fn path(&self) -> String {
+    "/xlive/web-room/v2/index/getRoomPlayInfo".to_string()
+  }
+
+  fn query(&self) -> crate::Result<Params> {
+    Ok(
+      Params::new()
+        .with("room_id", self.room_id)
+        .with("protocol", self.protocols.join(","))
+        .with("format", self.formats.join(","))
+        .with("codec", self.codecs.join(","))
+        .with("qn", self.qn),
+    )
+  }
+}
+
 pub fn get_live_room_play_info(
   client: &Client,
   room_id: i32,
@@ -348,27 +504,44 @@ pub fn get_live_room_play_info(
   codecs: &[&str],
   qn: i32,
 ) -> crate::Result<LiveRoomPlayInfoResponse> {
-  const API_URL: &str = "https://api.live.bilibili.com/xlive/web-room/v2/index/getRoomPlayInfo";
-  let protocols_str = protocols.join(",");
-  let formats_str = formats.join(",");
-  let codecs_str = codecs.join(",");
-  let url = format!(
-    "{}?room_id={}&protocol={}&format={}&codec={}&qn={}",
-    API_URL, room_id, protocols_str, formats_str, codecs_str, qn
-  );
-  let request = client
-    .get(url)
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
-  handle_api_response(client.execute(request)?)
+  let endpoint = GetLiveRoomPlayInfo::new(room_id, protocols, formats, codecs, qn);
+  client.call(&endpoint, Auth::None)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_live_room_play_info_async(
+  client: &AsyncClient,
+  room_id: i32,
+  protocols: &[&str],
+  formats: &[&str],
+  codecs: &[&str],
+  qn: i32,
+) -> crate::Result<LiveRoomPlayInfoResponse> {
+  let endpoint = GetLiveRoomPlayInfo::new(room_id, protocols, formats, codecs, qn);
+  client.call(&endpoint, Auth::None).await
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
-  use crate::utils::assert_error_code;
+  use crate::utils::{assert_error_code, cassette, mock_server::MockServer};
   #[test]
   fn test_get_live_room_info() {
+    let agent = cassette::client("live_room_info");
+    let result = get_live_room_info(&agent, 21887355);
+    assert!(result.is_ok());
+    // Success: a valid live room
+    assert!(get_live_room_info(&agent, 1029).is_ok());
+
+    // Failure: non-existent live room
+    const INVALID_ROOM_ID: i32 = 1231232412;
+    let result = get_live_room_info(&agent, INVALID_ROOM_ID);
+    assert_error_code(result, GENERAL_ERROR);
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_get_live_room_info_live() {
     let agent = Client::new();
     let result = get_live_room_info(&agent, 21887355);
     assert!(result.is_ok());
@@ -381,8 +554,47 @@ mod tests {
     assert_error_code(result, GENERAL_ERROR);
   }
 
+  #[cfg(feature = "async")]
+  #[tokio::test]
+  async fn test_get_live_room_info_async() {
+    let agent = cassette::async_client("live_room_info_async");
+    // Success: a valid live room
+    assert!(get_live_room_info_async(&agent, 1029).await.is_ok());
+
+    // Failure: non-existent live room
+    const INVALID_ROOM_ID: i32 = 1231232412;
+    let result = get_live_room_info_async(&agent, INVALID_ROOM_ID).await;
+    assert_error_code(result, GENERAL_ERROR);
+  }
+
+  #[cfg(feature = "async")]
+  #[tokio::test]
+  #[ignore = "hits the real API"]
+  async fn test_get_live_room_info_async_live() {
+    let agent = AsyncClient::new();
+    // Success: a valid live room
+    assert!(get_live_room_info_async(&agent, 1029).await.is_ok());
+
+    // Failure: non-existent live room
+    const INVALID_ROOM_ID: i32 = 1231232412;
+    let result = get_live_room_info_async(&agent, INVALID_ROOM_ID).await;
+    assert_error_code(result, GENERAL_ERROR);
+  }
+
   #[test]
   fn test_get_room_init_info() {
+    let agent = cassette::client("live_room_init");
+    // Success: a valid live room
+    assert!(get_room_init_info(&agent, 1029).is_ok());
+    // Failure: non-existent live room
+    const INVALID_ROOM_ID: i32 = 1231232412;
+    let result = get_room_init_info(&agent, INVALID_ROOM_ID);
+    assert_error_code(result, ROOM_INIT_NOT_EXIST);
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_get_room_init_info_live() {
     let agent = Client::new();
     // Success: a valid live room
     assert!(get_room_init_info(&agent, 1029).is_ok());
@@ -392,11 +604,52 @@ mod tests {
     assert_error_code(result, ROOM_INIT_NOT_EXIST);
   }
 
+  #[test]
+  fn test_get_room_init_info_mock_host() {
+    const ROOM_INIT: &str = r#"{
+      "code": 0,
+      "msg": "ok",
+      "message": "ok",
+      "data": {
+        "room_id": 5440, "short_id": 1029, "uid": 9617619, "need_p2p": 0,
+        "is_hidden": false, "is_locked": false, "is_portrait": false, "live_status": 1,
+        "hidden_till": 0, "lock_till": 0, "encrypted": false, "pwd_verified": false,
+        "live_time": 1703476423, "room_shield": 0, "is_sp": 0, "special_type": 0
+      }
+    }"#;
+    let server = MockServer::start(&[("/room/v1/Room/room_init", ROOM_INIT)]);
+    let agent = Client::builder()
+      .host(Host::ApiLive, server.base_url())
+      .build();
+
+    let result = get_room_init_info(&agent, 1029).unwrap();
+    assert_eq!(5440, result.data.room_id);
+    assert_eq!(1029, result.data.short_id);
+
+    let received = server.received();
+    assert_eq!(1, received.len());
+    assert_eq!("/room/v1/Room/room_init?id=1029", received[0].url);
+  }
+
   #[test]
   fn test_get_streamer_info() {
     const VALID_UID: Uid = 697737710;
     const INVALID_UID: Uid = 0;
 
+    let agent = cassette::client("live_streamer_info");
+    // Success: a valid uid
+    assert!(get_streamer_info(&agent, VALID_UID).is_ok());
+    // Failure: non-existent uid
+    let result = get_streamer_info(&agent, INVALID_UID);
+    assert_error_code(result, GENERAL_ERROR);
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_get_streamer_info_live() {
+    const VALID_UID: Uid = 697737710;
+    const INVALID_UID: Uid = 0;
+
     let agent = Client::new();
     // Success: a valid uid
     assert!(get_streamer_info(&agent, VALID_UID).is_ok());
@@ -411,6 +664,26 @@ mod tests {
     const UID2: Uid = 540564177;
     const INVALID_UID: Uid = 44444444;
 
+    let agent = cassette::client("live_room_status_batch");
+    let uids = [UID1, UID2];
+    let invalid_uids = [INVALID_UID];
+    // Success: a valid uid
+    assert!(query_room_status_batch(&agent, &uids).is_ok());
+    // Failure: non-existent uid
+    let result = query_room_status_batch(&agent, &invalid_uids);
+    // ! Currently when we only supply invalid uids to the batch query API, we get back an empty array as the data
+    // ! field, which will lead to a deserialize error. Idealy we should identify this and either serialize as empty
+    // ! hashmap or rase an API error.
+    assert!(matches!(result, Err(crate::Error::Deserialize(_))));
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_query_room_status_batch_live() {
+    const UID1: Uid = 697737710;
+    const UID2: Uid = 540564177;
+    const INVALID_UID: Uid = 44444444;
+
     let agent = Client::new();
     let uids = [UID1, UID2];
     let invalid_uids = [INVALID_UID];
@@ -427,6 +700,48 @@ mod tests {
   // TODO: Refactor play info api
   #[test]
   fn test_get_live_room_play_info() {
+    let agent = cassette::client("live_room_play_info");
+    println!(
+      "{:?}",
+      get_live_room_play_info(
+        &agent,
+        23058,
+        &["0", "1"],
+        &["0", "1", "2"],
+        &["0", "1"],
+        1000
+      )
+      .unwrap()
+    );
+    // 直播间不存在
+    println!(
+      "{:?}",
+      get_live_room_play_info(
+        &agent,
+        44444444,
+        &["0", "1"],
+        &["0", "1", "2"],
+        &["0", "1"],
+        1000
+      )
+    );
+    println!(
+      "{:?}",
+      get_live_room_play_info(
+        &agent,
+        23058,
+        &["arbitrary_parameter"],
+        &["abcd"],
+        &["another_arong"],
+        1000
+      )
+      .unwrap()
+    );
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_get_live_room_play_info_live() {
     let agent = Client::new();
     println!(
       "{:?}",
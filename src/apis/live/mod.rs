@@ -1,5 +1,8 @@
 //! https://github.com/SocialSisterYi/bilibili-API-collect/tree/master/docs/live
 
+#[cfg(feature = "danmaku")]
+pub mod danmaku;
+pub mod event;
 pub mod info;
 pub mod msg;
 pub mod user;
@@ -2,12 +2,14 @@
 
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use reqwest::Method;
 use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
-use crate::client::Client;
-use crate::Error;
-use crate::{credential::Credential, error::ApiError, utils::handle_api_response};
+#[cfg(feature = "async")]
+use crate::client::AsyncClient;
+use crate::client::{AuthKind, Body, Client, Endpoint, Host, Params, RateLimitGroup};
+use crate::{credential::Credential, error::ApiError};
 
 pub const DEFAULT_FONTSIZE: i32 = 25;
 pub const WHITE: i32 = 0xffffff;
@@ -54,12 +56,6 @@ pub struct LiveMessageConfig {
   /// @用户MID
   #[serde(skip_serializing_if = "Option::is_none")]
   reply_mid: Option<i64>,
-  /// 当前时间戳，由被调用方库函数提供
-  pub(crate) rnd: String,
-  /// CSRF: bili_jct
-  pub(crate) csrf: String,
-  /// CSRF token: bili_jct
-  pub(crate) csrf_token: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -96,9 +92,6 @@ impl LiveMessageConfig {
       mode: LiveMessageMode::Float,
       bubble: 0,
       reply_mid: None,
-      rnd: String::new(),
-      csrf: String::new(),
-      csrf_token: String::new(),
     }
   }
 
@@ -111,9 +104,6 @@ impl LiveMessageConfig {
       mode: LiveMessageMode::Float,
       bubble: 0,
       reply_mid: None,
-      rnd: String::new(),
-      csrf: String::new(),
-      csrf_token: String::new(),
     }
   }
 
@@ -126,9 +116,6 @@ impl LiveMessageConfig {
       mode: LiveMessageMode::Float,
       bubble: 0,
       reply_mid: Some(reply_mid),
-      rnd: String::new(),
-      csrf: String::new(),
-      csrf_token: String::new(),
     }
   }
 
@@ -158,48 +145,66 @@ impl LiveMessageConfig {
   }
 }
 
-pub fn send_live_message(
-  client: &Client,
-  mut config: LiveMessageConfig,
-  credential: &Credential,
-) -> crate::Result<SendLiveMessageResponse> {
-  const API_URL: &str = "https://api.live.bilibili.com/msg/send";
+/// 发送直播弹幕. `rnd`为每次尝试时的毫秒时间戳.
+#[derive(Debug, Clone)]
+pub struct SendLiveMessage {
+  pub config: LiveMessageConfig,
+}
+
+impl Endpoint for SendLiveMessage {
+  type Response = SendLiveMessageResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
 
-  let mut result = None;
-  for _ in 0..=client.live_msg_config.max_retry {
+  fn path(&self) -> String {
+    "/msg/send".to_string()
+  }
+
+  fn method(&self) -> Method {
+    Method::POST
+  }
+
+  fn body(&self) -> crate::Result<Body> {
     let rnd = SystemTime::now()
       .duration_since(UNIX_EPOCH)
       .unwrap()
-      .as_millis()
-      .to_string();
-
-    config.rnd = rnd;
-    config.csrf = credential.bili_jct.clone();
-    config.csrf_token = credential.bili_jct.clone();
-
-    let request = client
-      .post(API_URL)
-      .header("cookie", credential.to_cookie_str())
-      .form(&config)
-      .header("User-Agent", crate::apis::USER_AGENT)
-      .build()?;
-
-    // B站直播弹幕API限制过快发送弹幕, 在此处做限流
-    client.block_till_ready();
-
-    result = Some(handle_api_response(client.execute(request)?));
-
-    if let Some(Err(Error::Api(e))) = &result {
-      if e.message().contains("您发送弹幕的频率过快") {
-        std::thread::sleep(client.live_msg_config.retry_after_rate_limit);
-        continue;
-      }
-    }
+      .as_millis();
+    let mut form = Params::from_serialize(&self.config)?;
+    form.push("rnd", rnd);
+    Ok(Body::Form(form))
+  }
 
-    break;
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
   }
 
-  result.unwrap()
+  fn csrf(&self) -> &'static [&'static str] {
+    &["csrf", "csrf_token"]
+  }
+
+  /// B站直播弹幕API限制过快发送弹幕, 在此处做限流, 发送过快时按照client.live_msg_retry_policy重试
+  fn rate_limit_group(&self) -> RateLimitGroup {
+    RateLimitGroup::LiveDanmaku
+  }
+}
+
+pub fn send_live_message(
+  client: &Client,
+  config: LiveMessageConfig,
+  credential: &Credential,
+) -> crate::Result<SendLiveMessageResponse> {
+  client.call(&SendLiveMessage { config }, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn send_live_message_async(
+  client: &AsyncClient,
+  config: LiveMessageConfig,
+  credential: &Credential,
+) -> crate::Result<SendLiveMessageResponse> {
+  client.call(&SendLiveMessage { config }, credential).await
 }
 
 /// 获取当前API错误对应的禁言粉丝牌等级, 若当前错误不是粉丝牌等级禁言则返回None
@@ -230,11 +235,46 @@ mod tests {
     client::Client,
     credential::extract_credential::{get_credential_for_test_or_abort, get_fake_credential},
     error::{ApiError, REQUEST_ERROR},
-    utils::assert_error_code,
+    utils::{assert_error_code, cassette},
   };
 
   #[test]
   pub fn test_send_msg() {
+    let agent = cassette::client("live_msg_send");
+    let credential = cassette::credential();
+    let config = LiveMessageConfig::with_roomid_and_msg(1029, "加油".to_string());
+
+    // 正常发送弹幕成功
+    let result = send_live_message(&agent, config, &credential);
+    assert!(result.is_ok());
+
+    // 用户登录错误
+    let invalid_cred = get_fake_credential();
+    let config = LiveMessageConfig::with_roomid_and_msg(1029, "加油".to_string());
+    assert_error_code(
+      send_live_message(&agent, config, &invalid_cred),
+      REQUEST_ERROR,
+    );
+
+    std::thread::sleep(Duration::from_millis(500));
+
+    // 参数错误(fontsize为0)
+    let mut config = LiveMessageConfig::with_roomid_and_msg(1029, "加油".to_string());
+    config.fontsize(0);
+    let result = send_live_message(&agent, config, &credential);
+    assert_error_code(result, REQUEST_ERROR);
+
+    std::thread::sleep(Duration::from_millis(500));
+
+    let config = LiveMessageConfig::with_roomid_and_msg_reply(1029, "加油".to_string(), 12734361);
+    // @弹幕
+    let result = send_live_message(&agent, config, &credential);
+    assert!(result.is_ok());
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  pub fn test_send_msg_live() {
     let agent = Client::new();
     let credential = get_credential_for_test_or_abort();
     let config = LiveMessageConfig::with_roomid_and_msg(1029, "加油".to_string());
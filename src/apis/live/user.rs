@@ -1,10 +1,13 @@
 //! Please refer to `https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/live/user.md`
 //! for API documentation.
 
+use reqwest::Method;
 use serde::{Deserialize, Serialize};
 
-use crate::client::Client;
-use crate::{credential::Credential, utils::handle_api_response};
+#[cfg(feature = "async")]
+use crate::client::AsyncClient;
+use crate::client::{AuthKind, Body, Client, Endpoint, Host, Page, Paginated, Paginator, Params};
+use crate::credential::Credential;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 
@@ -48,63 +51,216 @@ pub struct PageInfo {
   pub cur_page: i32,
 }
 
+/// 我的粉丝勋章, `page`从1开始
+#[derive(Debug, Clone, Copy)]
+pub struct GetMedalForUser {
+  pub page: i32,
+  pub page_size: i32,
+}
+
+impl Endpoint for GetMedalForUser {
+  type Response = GetMedalForUserResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
+
+  fn path(&self) -> String {
+    "/xlive/app-ucenter/v1/user/GetMyMedals".to_string()
+  }
+
+  fn query(&self) -> crate::Result<Params> {
+    Ok(
+      Params::new()
+        .with("page", self.page)
+        .with("page_size", self.page_size),
+    )
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+}
+
 pub fn get_medal_for_user(
   client: &Client,
   page_size: i32,
   num_page: i32,
   credential: &Credential,
 ) -> crate::Result<GetMedalForUserResponse> {
-  const API_URL: &str = "https://api.live.bilibili.com/xlive/app-ucenter/v1/user/GetMyMedals";
-  let url = format!("{}?page={}&page_size={}", API_URL, num_page, page_size);
-  let request = client
-    .get(url)
-    .header("cookie", credential.to_cookie_str())
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
+  client.call(
+    &GetMedalForUser {
+      page: num_page,
+      page_size,
+    },
+    credential,
+  )
+}
+
+#[cfg(feature = "async")]
+pub async fn get_medal_for_user_async(
+  client: &AsyncClient,
+  page_size: i32,
+  num_page: i32,
+  credential: &Credential,
+) -> crate::Result<GetMedalForUserResponse> {
+  client
+    .call(
+      &GetMedalForUser {
+        page: num_page,
+        page_size,
+      },
+      credential,
+    )
+    .await
+}
+
+impl Default for GetMedalForUser {
+  /// 第一页, 每页10个
+  fn default() -> Self {
+    Self {
+      page: 1,
+      page_size: 10,
+    }
+  }
+}
+
+impl Paginated for GetMedalForUser {
+  type Item = MedalItem;
+  type Cursor = i32;
+
+  fn at(&self, page: i32) -> Self {
+    Self { page, ..*self }
+  }
 
-  handle_api_response(client.execute(request)?)
+  fn page(&self, response: GetMedalForUserResponse) -> Page<MedalItem, i32> {
+    let data = response.data;
+    Page::numbered(
+      data.items,
+      data.page_info.cur_page,
+      data.page_info.total_page,
+    )
+  }
+}
+
+impl Client {
+  /// 我的所有粉丝勋章, 逐页获取
+  ///
+  /// ```no_run
+  /// # fn main() -> bili_api_rs::Result<()> {
+  /// use bili_api_rs::{credential::Credential, Client};
+  ///
+  /// let credential = Credential::from_env()?;
+  /// let medals: Vec<_> = Client::new().medals(&credential).collect::<Result<_, _>>()?;
+  /// # Ok(())
+  /// # }
+  /// ```
+  pub fn medals<'a>(&'a self, credential: &'a Credential) -> Paginator<'a, GetMedalForUser> {
+    self.paginate(GetMedalForUser::default(), credential)
+  }
+}
+
+#[cfg(feature = "async")]
+impl AsyncClient {
+  /// Async version of [Client::medals], as a [Stream](futures_util::Stream)
+  pub fn medals<'a>(
+    &'a self,
+    credential: &'a Credential,
+  ) -> impl futures_util::Stream<Item = crate::Result<MedalItem>> + 'a {
+    self.paginate(GetMedalForUser::default(), credential)
+  }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct WearMedalResponse {}
 
+/// 佩戴粉丝勋章
+#[derive(Debug, Clone)]
+pub struct WearMedal {
+  pub medal_id: i32,
+}
+
+impl Endpoint for WearMedal {
+  type Response = WearMedalResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
+
+  fn path(&self) -> String {
+    "/xlive/web-room/v1/fansMedal/wear".to_string()
+  }
+
+  fn method(&self) -> Method {
+    Method::POST
+  }
+
+  fn body(&self) -> crate::Result<Body> {
+    Ok(Body::Form(Params::new().with("medal_id", self.medal_id)))
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+
+  fn csrf(&self) -> &'static [&'static str] {
+    &["csrf", "csrf_token"]
+  }
+}
+
 pub fn wear_medal(
   client: &Client,
   medal_id: i32,
   credential: &Credential,
 ) -> crate::Result<WearMedalResponse> {
-  const API_URL: &str = "https://api.live.bilibili.com/xlive/web-room/v1/fansMedal/wear";
-  let params = [
-    ("medal_id", &*medal_id.to_string()),
-    ("csrf", &credential.bili_jct),
-    ("csrf_token", &credential.bili_jct),
-  ];
-
-  let request = client
-    .post(API_URL)
-    .header("cookie", credential.to_cookie_str())
-    .form(&params)
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
+  client.call(&WearMedal { medal_id }, credential)
+}
 
-  handle_api_response(client.execute(request)?)
+#[cfg(feature = "async")]
+pub async fn wear_medal_async(
+  client: &AsyncClient,
+  medal_id: i32,
+  credential: &Credential,
+) -> crate::Result<WearMedalResponse> {
+  client.call(&WearMedal { medal_id }, credential).await
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct LiveCheckinResponse;
 
+/// 直播区签到
+#[derive(Debug, Clone, Copy)]
+pub struct LiveCheckin;
+
+impl Endpoint for LiveCheckin {
+  type Response = LiveCheckinResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
+
+  fn path(&self) -> String {
+    "/xlive/web-ucenter/v1/sign/DoSign".to_string()
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+}
+
 pub fn live_checkin(
   client: &Client,
   credential: &Credential,
 ) -> crate::Result<LiveCheckinResponse> {
-  const API_URL: &str = "https://api.live.bilibili.com/xlive/web-ucenter/v1/sign/DoSign";
-  let request = client
-    .get(API_URL)
-    .header("cookie", credential.to_cookie_str())
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
+  client.call(&LiveCheckin, credential)
+}
 
-  handle_api_response(client.execute(request)?)
+#[cfg(feature = "async")]
+pub async fn live_checkin_async(
+  client: &AsyncClient,
+  credential: &Credential,
+) -> crate::Result<LiveCheckinResponse> {
+  client.call(&LiveCheckin, credential).await
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -129,17 +285,39 @@ pub struct MonthlyLiveCheckinInfoData {
   pub sign_bonus_days_list: Vec<i32>,
 }
 
+/// 本月直播区签到信息
+#[derive(Debug, Clone, Copy)]
+pub struct GetMonthlyLiveCheckinInfo;
+
+impl Endpoint for GetMonthlyLiveCheckinInfo {
+  type Response = MonthlyLiveCheckinInfoResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
+
+  fn path(&self) -> String {
+    "/xlive/web-ucenter/v1/sign/WebGetSignInfo".to_string()
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+}
+
 pub fn get_monthly_live_checkin_info(
   client: &Client,
   credential: &Credential,
 ) -> crate::Result<MonthlyLiveCheckinInfoResponse> {
-  const API_URL: &str = "https://api.live.bilibili.com/xlive/web-ucenter/v1/sign/WebGetSignInfo";
-  let request = client
-    .get(API_URL)
-    .header("cookie", credential.to_cookie_str())
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
-  handle_api_response(client.execute(request)?)
+  client.call(&GetMonthlyLiveCheckinInfo, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_monthly_live_checkin_info_async(
+  client: &AsyncClient,
+  credential: &Credential,
+) -> crate::Result<MonthlyLiveCheckinInfoResponse> {
+  client.call(&GetMonthlyLiveCheckinInfo, credential).await
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -157,17 +335,39 @@ pub struct LastMonthLiveCheckInData {
   pub sign_bonus_days_list: Vec<i32>,
 }
 
+/// 上月直播区签到信息
+#[derive(Debug, Clone, Copy)]
+pub struct GetLastMonthLiveCheckinInfo;
+
+impl Endpoint for GetLastMonthLiveCheckinInfo {
+  type Response = LastMonthLiveCheckInInfoResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
+
+  fn path(&self) -> String {
+    "/sign/getLastMonthSignDays".to_string()
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+}
+
 pub fn get_last_month_live_checkin_info(
   client: &Client,
   credential: &Credential,
 ) -> crate::Result<LastMonthLiveCheckInInfoResponse> {
-  const API_URL: &str = "https://api.live.bilibili.com/sign/getLastMonthSignDays";
-  let request = client
-    .get(API_URL)
-    .header("cookie", credential.to_cookie_str())
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
-  handle_api_response(client.execute(request)?)
+  client.call(&GetLastMonthLiveCheckinInfo, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_last_month_live_checkin_info_async(
+  client: &AsyncClient,
+  credential: &Credential,
+) -> crate::Result<LastMonthLiveCheckInInfoResponse> {
+  client.call(&GetLastMonthLiveCheckinInfo, credential).await
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -196,19 +396,49 @@ pub struct UserDanmuProperty {
   pub room_id: i32,
 }
 
+/// 用户在直播间内的信息, e.g., 弹幕长度限制, 佩戴的粉丝勋章
+#[derive(Debug, Clone)]
+pub struct GetLiveInfoByUser {
+  pub room_id: i32,
+}
+
+impl Endpoint for GetLiveInfoByUser {
+  type Response = GetInfoByUserResponse;
+
+  fn host(&self) -> Host {
+    Host::ApiLive
+  }
+
+  fn path(&self) -> String {
+    "/xlive/web-room/v1/index/getInfoByUser".to_string()
+  }
+
+  fn query(&self) -> crate::Result<Params> {
+    Ok(Params::new().with("room_id", self.room_id))
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+}
+
 pub fn get_live_info_by_user(
   client: &Client,
   room_id: i32,
   credential: &Credential,
 ) -> crate::Result<GetInfoByUserResponse> {
-  const API_URL: &str = "https://api.live.bilibili.com/xlive/web-room/v1/index/getInfoByUser";
-  let url = format!("{}?room_id={}", API_URL, room_id);
-  let request = client
-    .get(url)
-    .header("cookie", credential.to_cookie_str())
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
-  handle_api_response(client.execute(request)?)
+  client.call(&GetLiveInfoByUser { room_id }, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_live_info_by_user_async(
+  client: &AsyncClient,
+  room_id: i32,
+  credential: &Credential,
+) -> crate::Result<GetInfoByUserResponse> {
+  client
+    .call(&GetLiveInfoByUser { room_id }, credential)
+    .await
 }
 
 #[cfg(test)]
@@ -217,11 +447,26 @@ mod tests {
   use crate::{
     credential::extract_credential::get_credential_for_test_or_abort,
     error::{REQUEST_ERROR, ROOM_NOT_EXIST},
-    utils::assert_error_code,
+    utils::{assert_error_code, cassette, mock_server::MockServer},
   };
 
   #[test]
   fn test_get_medal_for_user() {
+    let agent = cassette::client("live_medal");
+    let credential = cassette::credential();
+    let invalid_credential = Credential::new("123".to_string(), "456".to_string());
+    // Success scenario
+    assert!(get_medal_for_user(&agent, 10, 1, &credential).is_ok());
+    // Failure scenario
+    assert_error_code(
+      get_medal_for_user(&agent, 10, 1, &invalid_credential),
+      REQUEST_ERROR,
+    );
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_get_medal_for_user_live() {
     let agent = Client::new();
     let credential = get_credential_for_test_or_abort();
     let invalid_credential = Credential::new("123".to_string(), "456".to_string());
@@ -234,8 +479,80 @@ mod tests {
     );
   }
 
+  #[test]
+  fn test_medals() {
+    use crate::client::{HttpRequest, HttpResponse};
+
+    fn medal_page(request: HttpRequest) -> crate::Result<HttpResponse> {
+      let page: i32 = request
+        .url
+        .query_pairs()
+        .find(|(k, _)| k == "page")
+        .unwrap()
+        .1
+        .parse()
+        .unwrap();
+      let body = format!(
+        r#"{{"code":0,"message":"0","data":{{"count":2,"items":[{}],"page_info":{{"total_page":2,"cur_page":{}}}}}}}"#,
+        serde_json::to_string(&MedalItem {
+          medal_id: page,
+          ..Default::default()
+        })
+        .unwrap(),
+        page
+      );
+      Ok(HttpResponse::new(reqwest::StatusCode::OK, body))
+    }
+
+    let agent = Client::builder().transport(medal_page).build();
+    let credential = Credential::new("sess".to_string(), "jct".to_string());
+    let ids: Vec<_> = agent
+      .medals(&credential)
+      .map(|medal| medal.unwrap().medal_id)
+      .collect();
+    assert_eq!(vec![1, 2], ids);
+  }
+
+  #[test]
+  fn test_wear_medal_mock_host() {
+    let server = MockServer::start(&[(
+      "/xlive/web-room/v1/fansMedal/wear",
+      r#"{"code":0,"message":"0","ttl":1,"data":{}}"#,
+    )]);
+    let agent = Client::builder()
+      .host(Host::ApiLive, server.base_url())
+      .build();
+    let credential = Credential::new("sess".to_string(), "jct".to_string());
+
+    assert!(wear_medal(&agent, 42, &credential).is_ok());
+
+    let received = server.received();
+    assert_eq!(1, received.len());
+    assert_eq!("POST", received[0].method);
+    assert_eq!(
+      Some("SESSDATA=sess; bili_jct=jct"),
+      received[0].header("cookie")
+    );
+    assert_eq!("medal_id=42&csrf=jct&csrf_token=jct", received[0].body);
+  }
+
   #[test]
   fn test_get_monthly_live_checkin_info() {
+    let agent = cassette::client("live_checkin_monthly");
+    let credential = cassette::credential();
+    let invalid_credential = Credential::new("123".to_string(), "456".to_string());
+    // Success scenario
+    assert!(get_monthly_live_checkin_info(&agent, &credential).is_ok());
+    // Failure scenario
+    assert_error_code(
+      get_monthly_live_checkin_info(&agent, &invalid_credential),
+      REQUEST_ERROR,
+    );
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_get_monthly_live_checkin_info_live() {
     let agent = Client::new();
     let credential = get_credential_for_test_or_abort();
     let invalid_credential = Credential::new("123".to_string(), "456".to_string());
@@ -250,6 +567,21 @@ mod tests {
 
   #[test]
   fn test_get_last_month_live_checkin_info() {
+    let agent = cassette::client("live_checkin_last_month");
+    let credential = cassette::credential();
+    let invalid_credential = Credential::new("123".to_string(), "456".to_string());
+    // Success scenario
+    assert!(get_last_month_live_checkin_info(&agent, &credential).is_ok());
+    // Failure scenario
+    assert_error_code(
+      get_last_month_live_checkin_info(&agent, &invalid_credential),
+      REQUEST_ERROR,
+    );
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_get_last_month_live_checkin_info_live() {
     let agent = Client::new();
     let credential = get_credential_for_test_or_abort();
     let invalid_credential = Credential::new("123".to_string(), "456".to_string());
@@ -264,6 +596,25 @@ mod tests {
 
   #[test]
   fn test_get_live_info_by_user() {
+    let agent = cassette::client("live_info_by_user");
+    let credential = cassette::credential();
+    let invalid_credential = Credential::new("123".to_string(), "456".to_string());
+    // Success scenario
+    assert!(get_live_info_by_user(&agent, 1029, &credential).is_ok());
+    // Failure scenario
+    assert_error_code(
+      get_live_info_by_user(&agent, 1029, &invalid_credential),
+      REQUEST_ERROR,
+    );
+    assert_error_code(
+      get_live_info_by_user(&agent, 44444444, &credential),
+      ROOM_NOT_EXIST,
+    );
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_get_live_info_by_user_live() {
     let agent = Client::new();
     let credential = get_credential_for_test_or_abort();
     let invalid_credential = Credential::new("123".to_string(), "456".to_string());
@@ -0,0 +1,636 @@
+//! https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/login/cookie_refresh.md
+//!
+//! Web端Cookie刷新:
+//! 1. [get_cookie_info] 检查是否需要刷新
+//! 2. 以RSA-OAEP加密`refresh_{timestamp}`得到[correspond_path]
+//! 3. [get_refresh_csrf] 获取`refresh_csrf`
+//! 4. [refresh_cookie] 以`refresh_token`换取新的Cookie及`refresh_token`
+//! 5. [confirm_refresh] 使旧的`refresh_token`失效
+//!
+//! [refresh_credential]完成2~5步, 通常使用[Credential::refresh]即可.
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "async")]
+use crate::AsyncClient;
+use crate::{
+  client::{AuthKind, Body, Endpoint, Host, HttpResponse, Params},
+  credential::Credential,
+  utils::{handle_api_response, set_cookies, snippet},
+  Client, Error,
+};
+
+/// 生成`correspondPath`所用的公钥
+const CORRESPOND_PUBLIC_KEY: &str = "-----BEGIN PUBLIC KEY-----
+MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDLgd2OAkcGVtoE3ThUREbio0Eg
+Uc/prcajMKXvkCKFCWhJYJcLkcM2DKKcSeFpD/j6Boy538YXnR6VhcuUJOhH2x71
+nzPjfdTcqMz7djHum0qSZA0AyCBDABUqCrfNgCiJ00Ra7GmRj+YCK1NJEuewlb40
+JNrRuoEUXpabUzGB8QIDAQAB
+-----END PUBLIC KEY-----";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CookieInfoResponse {
+  pub data: CookieInfoData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct CookieInfoData {
+  /// 是否应该刷新Cookie
+  pub refresh: bool,
+  /// 当前毫秒时间戳
+  pub timestamp: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RefreshCookieResponse {
+  pub data: RefreshCookieData,
+  /// `Set-Cookie`下发的新Cookie
+  #[serde(skip)]
+  pub cookies: Vec<(String, String)>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RefreshCookieData {
+  pub status: i32,
+  pub message: String,
+  /// 新的refresh_token
+  pub refresh_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ConfirmRefreshResponse {}
+
+/// 检查是否需要刷新Cookie
+#[derive(Debug, Clone, Copy)]
+pub struct GetCookieInfo;
+
+impl Endpoint for GetCookieInfo {
+  type Response = CookieInfoResponse;
+
+  fn host(&self) -> Host {
+    Host::Passport
+  }
+
+  fn path(&self) -> String {
+    "/x/passport-login/web/cookie/info".to_string()
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+
+  fn auto_refresh(&self) -> bool {
+    false
+  }
+
+  fn csrf(&self) -> &'static [&'static str] {
+    &["csrf"]
+  }
+}
+
+pub fn get_cookie_info(
+  client: &Client,
+  credential: &Credential,
+) -> crate::Result<CookieInfoResponse> {
+  client.call(&GetCookieInfo, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_cookie_info_async(
+  client: &AsyncClient,
+  credential: &Credential,
+) -> crate::Result<CookieInfoResponse> {
+  client.call(&GetCookieInfo, credential).await
+}
+
+/// `refresh_{timestamp}`经RSA-OAEP(SHA-256)加密后的十六进制字符串, `timestamp`为毫秒时间戳.
+/// OAEP填充带有随机数, 每次调用的结果都不同.
+pub fn correspond_path(timestamp: i64) -> String {
+  use rsa::{pkcs8::DecodePublicKey, rand_core::OsRng, Oaep, RsaPublicKey};
+
+  let key = RsaPublicKey::from_public_key_pem(CORRESPOND_PUBLIC_KEY).expect("Valid public key");
+  let message = format!("refresh_{}", timestamp);
+  let encrypted = key
+    .encrypt(&mut OsRng, Oaep::new::<sha2::Sha256>(), message.as_bytes())
+    .expect("Message fits in the key");
+  encrypted.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// 从`https://www.bilibili.com/correspond/1/{correspond_path}`页面中获取`refresh_csrf`
+#[derive(Debug, Clone)]
+pub struct GetRefreshCsrf {
+  pub correspond_path: String,
+}
+
+impl Endpoint for GetRefreshCsrf {
+  type Response = String;
+
+  fn host(&self) -> Host {
+    Host::Www
+  }
+
+  fn path(&self) -> String {
+    format!("/correspond/1/{}", self.correspond_path)
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+
+  fn auto_refresh(&self) -> bool {
+    false
+  }
+
+  /// 返回的是HTML页面
+  fn parse(&self, response: HttpResponse) -> crate::Result<String> {
+    parse_refresh_csrf(response.status(), &response.text())
+  }
+}
+
+pub fn get_refresh_csrf(
+  client: &Client,
+  credential: &Credential,
+  correspond_path: &str,
+) -> crate::Result<String> {
+  let endpoint = GetRefreshCsrf {
+    correspond_path: correspond_path.to_string(),
+  };
+  client.call(&endpoint, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_refresh_csrf_async(
+  client: &AsyncClient,
+  credential: &Credential,
+  correspond_path: &str,
+) -> crate::Result<String> {
+  let endpoint = GetRefreshCsrf {
+    correspond_path: correspond_path.to_string(),
+  };
+  client.call(&endpoint, credential).await
+}
+
+/// 以`refresh_token`换取新的Cookie及refresh_token
+#[derive(Debug, Clone)]
+pub struct RefreshCookie {
+  pub refresh_csrf: String,
+  pub refresh_token: String,
+}
+
+impl Endpoint for RefreshCookie {
+  type Response = RefreshCookieResponse;
+
+  fn host(&self) -> Host {
+    Host::Passport
+  }
+
+  fn path(&self) -> String {
+    "/x/passport-login/web/cookie/refresh".to_string()
+  }
+
+  fn method(&self) -> Method {
+    Method::POST
+  }
+
+  fn body(&self) -> crate::Result<Body> {
+    Ok(Body::Form(
+      Params::new()
+        .with("csrf", "")
+        .with("refresh_csrf", &self.refresh_csrf)
+        .with("source", "main_web")
+        .with("refresh_token", &self.refresh_token),
+    ))
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+
+  fn auto_refresh(&self) -> bool {
+    false
+  }
+
+  fn retry(&self) -> bool {
+    false
+  }
+
+  fn csrf(&self) -> &'static [&'static str] {
+    &["csrf"]
+  }
+
+  fn parse(&self, response: HttpResponse) -> crate::Result<Self::Response> {
+    let cookies = set_cookies(response.headers());
+    let mut response: RefreshCookieResponse = handle_api_response(response)?;
+    response.cookies = cookies;
+    Ok(response)
+  }
+}
+
+/// 以`refresh_token`换取新的凭据. 返回的凭据包含新的Cookie及refresh_token, 其余Cookie沿用`credential`.
+pub fn refresh_cookie(
+  client: &Client,
+  credential: &Credential,
+  refresh_csrf: &str,
+) -> crate::Result<Credential> {
+  let endpoint = RefreshCookie {
+    refresh_csrf: refresh_csrf.to_string(),
+    refresh_token: require_refresh_token(credential)?.to_string(),
+  };
+  let response = client.call(&endpoint, credential)?;
+  Ok(refreshed_credential(credential, response))
+}
+
+#[cfg(feature = "async")]
+pub async fn refresh_cookie_async(
+  client: &AsyncClient,
+  credential: &Credential,
+  refresh_csrf: &str,
+) -> crate::Result<Credential> {
+  let endpoint = RefreshCookie {
+    refresh_csrf: refresh_csrf.to_string(),
+    refresh_token: require_refresh_token(credential)?.to_string(),
+  };
+  let response = client.call(&endpoint, credential).await?;
+  Ok(refreshed_credential(credential, response))
+}
+
+/// 使用刷新后的凭据确认刷新, 使旧的`refresh_token`失效
+#[derive(Debug, Clone)]
+pub struct ConfirmRefresh {
+  /// 旧的`refresh_token`
+  pub refresh_token: String,
+}
+
+impl Endpoint for ConfirmRefresh {
+  type Response = ConfirmRefreshResponse;
+
+  fn host(&self) -> Host {
+    Host::Passport
+  }
+
+  fn path(&self) -> String {
+    "/x/passport-login/web/confirm/refresh".to_string()
+  }
+
+  fn method(&self) -> Method {
+    Method::POST
+  }
+
+  fn body(&self) -> crate::Result<Body> {
+    Ok(Body::Form(
+      Params::new()
+        .with("csrf", "")
+        .with("refresh_token", &self.refresh_token),
+    ))
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+
+  fn auto_refresh(&self) -> bool {
+    false
+  }
+
+  fn retry(&self) -> bool {
+    false
+  }
+
+  fn csrf(&self) -> &'static [&'static str] {
+    &["csrf"]
+  }
+}
+
+pub fn confirm_refresh(
+  client: &Client,
+  credential: &Credential,
+  old_refresh_token: &str,
+) -> crate::Result<ConfirmRefreshResponse> {
+  let endpoint = ConfirmRefresh {
+    refresh_token: old_refresh_token.to_string(),
+  };
+  client.call(&endpoint, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn confirm_refresh_async(
+  client: &AsyncClient,
+  credential: &Credential,
+  old_refresh_token: &str,
+) -> crate::Result<ConfirmRefreshResponse> {
+  let endpoint = ConfirmRefresh {
+    refresh_token: old_refresh_token.to_string(),
+  };
+  client.call(&endpoint, credential).await
+}
+
+/// 执行刷新流程并返回刷新后的凭据, `timestamp`为毫秒时间戳, 可取自[CookieInfoData::timestamp].
+///
+/// 新的Cookie一经下发即生效, 因此确认刷新失败时不视为错误, 旧的`refresh_token`只是暂时不会失效.
+/// 失败会通过[Middleware](crate::client::Middleware)可见, 启用`tracing` feature时另记录一条warn日志.
+pub fn refresh_credential(
+  client: &Client,
+  credential: &Credential,
+  timestamp: i64,
+) -> crate::Result<Credential> {
+  let old_refresh_token = require_refresh_token(credential)?;
+  let refresh_csrf = get_refresh_csrf(client, credential, &correspond_path(timestamp))?;
+  let refreshed = refresh_cookie(client, credential, &refresh_csrf)?;
+  if let Err(e) = confirm_refresh(client, &refreshed, old_refresh_token) {
+    confirm_failed(&e);
+  }
+  Ok(refreshed)
+}
+
+/// Async version of [refresh_credential]
+#[cfg(feature = "async")]
+pub async fn refresh_credential_async(
+  client: &AsyncClient,
+  credential: &Credential,
+  timestamp: i64,
+) -> crate::Result<Credential> {
+  let old_refresh_token = require_refresh_token(credential)?;
+  let refresh_csrf =
+    get_refresh_csrf_async(client, credential, &correspond_path(timestamp)).await?;
+  let refreshed = refresh_cookie_async(client, credential, &refresh_csrf).await?;
+  if let Err(e) = confirm_refresh_async(client, &refreshed, old_refresh_token).await {
+    confirm_failed(&e);
+  }
+  Ok(refreshed)
+}
+
+/// The refreshed credential is returned all the same, as the old one may already be invalid
+fn confirm_failed(err: &Error) {
+  #[cfg(feature = "tracing")]
+  tracing::warn!(error = %err, "failed to confirm cookie refresh, the old session stays valid");
+  #[cfg(not(feature = "tracing"))]
+  let _ = err;
+}
+
+fn require_refresh_token(credential: &Credential) -> crate::Result<&str> {
+  credential
+    .refresh_token()
+    .filter(|token| !token.is_empty())
+    .ok_or_else(|| Error::Credential("missing refresh_token (ac_time_value)".to_string()))
+}
+
+fn parse_refresh_csrf(status: reqwest::StatusCode, body: &str) -> crate::Result<String> {
+  if !status.is_success() {
+    return Err(Error::Status(status, snippet(body)));
+  }
+  static RE: OnceLock<Regex> = OnceLock::new();
+  let re = RE.get_or_init(|| Regex::new(r#"<div id="1-name">\s*([^<\s]+)\s*</div>"#).unwrap());
+  re.captures(body)
+    .map(|captures| captures[1].to_string())
+    .ok_or_else(|| Error::Credential("refresh_csrf not found in correspond page".to_string()))
+}
+
+fn refreshed_credential(credential: &Credential, response: RefreshCookieResponse) -> Credential {
+  let mut refreshed = credential.clone();
+  for (name, value) in response.cookies {
+    refreshed.set_cookie(&name, value);
+  }
+  refreshed.ac_time_value = Some(response.data.refresh_token);
+  refreshed
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+
+  use reqwest::StatusCode;
+
+  use super::*;
+  use crate::{
+    client::HttpRequest,
+    utils::{assert_error_code, mock_server::MockServer, now_millis},
+  };
+
+  const COOKIE_REFRESH_ROUTES: [(&str, &str); 3] = [
+    (
+      "/correspond/1/*",
+      r#"<html><body><div id="1-name">b0cc8411ded2f9db2cff2edb3123acac</div></body></html>"#,
+    ),
+    (
+      "/x/passport-login/web/cookie/refresh",
+      r#"{"code":0,"message":"0","ttl":1,"data":{"status":0,"message":"","refresh_token":"new_token"}}"#,
+    ),
+    (
+      "/x/passport-login/web/confirm/refresh",
+      r#"{"code":0,"message":"0","ttl":1}"#,
+    ),
+  ];
+
+  const COOKIE_REFRESH_HEADERS: [(&str, &str, &str); 2] = [
+    (
+      "/x/passport-login/web/cookie/refresh",
+      "Set-Cookie",
+      "SESSDATA=new_sess; Path=/; Domain=bilibili.com; HttpOnly",
+    ),
+    (
+      "/x/passport-login/web/cookie/refresh",
+      "Set-Cookie",
+      "bili_jct=new_jct; Path=/; Domain=bilibili.com",
+    ),
+  ];
+
+  fn stale_credential() -> Credential {
+    let mut credential = Credential::new("sess".to_string(), "jct".to_string());
+    credential.set_cookie("ac_time_value", "old_token");
+    credential.set_cookie("buvid3", "XYZ-infoc");
+    credential
+  }
+
+  #[test]
+  fn test_correspond_path() {
+    let path = correspond_path(1684466082239);
+    // 1024-bit key
+    assert_eq!(256, path.len());
+    assert!(path.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_ne!(path, correspond_path(1684466082239));
+  }
+
+  #[test]
+  fn test_get_cookie_info() {
+    let server = MockServer::start(&[(
+      "/x/passport-login/web/cookie/info",
+      r#"{"code":0,"message":"0","ttl":1,"data":{"refresh":true,"timestamp":1684466082239}}"#,
+    )]);
+    let client = Client::builder()
+      .host(Host::Passport, server.base_url())
+      .build();
+    let info = get_cookie_info(&client, &stale_credential()).unwrap().data;
+    assert!(info.refresh);
+    assert_eq!(1684466082239, info.timestamp);
+    assert_eq!(
+      "/x/passport-login/web/cookie/info?csrf=jct",
+      server.received()[0].url
+    );
+  }
+
+  #[test]
+  fn test_refresh_credential() {
+    let server = MockServer::start_with_headers(&COOKIE_REFRESH_ROUTES, &COOKIE_REFRESH_HEADERS);
+    let client = Client::builder()
+      .host(Host::Passport, server.base_url())
+      .host(Host::Www, server.base_url())
+      .build();
+
+    let refreshed = refresh_credential(&client, &stale_credential(), now_millis()).unwrap();
+    assert_eq!("new_sess", refreshed.sessdata);
+    assert_eq!("new_jct", refreshed.bili_jct);
+    assert_eq!(Some("new_token"), refreshed.refresh_token());
+    assert_eq!(Some("XYZ-infoc"), refreshed.buvid3.as_deref());
+
+    let received = server.received();
+    assert_eq!(3, received.len());
+    assert!(received[0].url.starts_with("/correspond/1/"));
+    assert_eq!(
+      "csrf=jct&refresh_csrf=b0cc8411ded2f9db2cff2edb3123acac&source=main_web&refresh_token=old_token",
+      received[1].body
+    );
+    // Confirmed with the new cookies and the old token
+    assert_eq!("csrf=new_jct&refresh_token=old_token", received[2].body);
+    assert!(received[2]
+      .header("cookie")
+      .unwrap()
+      .starts_with("SESSDATA=new_sess; bili_jct=new_jct"));
+  }
+
+  #[test]
+  fn test_refresh_credential_failure() {
+    let server = MockServer::start(&[
+      COOKIE_REFRESH_ROUTES[0],
+      (
+        "/x/passport-login/web/cookie/refresh",
+        r#"{"code":86095,"message":"refresh_csrf 错误或 refresh_token 与 cookie 不匹配","ttl":1}"#,
+      ),
+    ]);
+    let client = Client::builder()
+      .host(Host::Passport, server.base_url())
+      .host(Host::Www, server.base_url())
+      .build();
+    assert_error_code(
+      refresh_credential(&client, &stale_credential(), now_millis()),
+      86095,
+    );
+
+    let no_token = Credential::new("sess".to_string(), "jct".to_string());
+    assert!(matches!(
+      refresh_credential(&client, &no_token, now_millis()),
+      Err(Error::Credential(_))
+    ));
+  }
+
+  #[test]
+  fn test_refresh_cookie_not_retried() {
+    let paths = Arc::new(Mutex::new(Vec::new()));
+    let received = Arc::clone(&paths);
+    let client = Client::builder()
+      .transport(move |request: HttpRequest| {
+        let path = request.url.path().to_string();
+        received.lock().unwrap().push(path.clone());
+        Ok(match path.as_str() {
+          "/x/passport-login/web/cookie/refresh" => {
+            HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "")
+          }
+          _ => HttpResponse::new(StatusCode::OK, COOKIE_REFRESH_ROUTES[0].1),
+        })
+      })
+      .build();
+
+    // The token may have been rotated before the server failed
+    assert!(matches!(
+      refresh_credential(&client, &stale_credential(), now_millis()),
+      Err(Error::Status(StatusCode::INTERNAL_SERVER_ERROR, _))
+    ));
+    let paths = paths.lock().unwrap();
+    let refreshes = paths
+      .iter()
+      .filter(|path| path.ends_with("/cookie/refresh"));
+    assert_eq!(1, refreshes.count());
+  }
+
+  #[test]
+  fn test_auto_refresh_credential() {
+    let mut routes = COOKIE_REFRESH_ROUTES.to_vec();
+    routes.push((
+      "/xlive/web-ucenter/v1/sign/DoSign",
+      r#"{"code":-101,"message":"账号未登录","ttl":1}"#,
+    ));
+    let server = MockServer::start_with_headers(&routes, &COOKIE_REFRESH_HEADERS);
+    let saved = std::sync::Arc::new(std::sync::Mutex::new(None));
+    let client = {
+      let saved = saved.clone();
+      Client::builder()
+        .host(Host::Passport, server.base_url())
+        .host(Host::Www, server.base_url())
+        .host(Host::ApiLive, server.base_url())
+        .auto_refresh_credential(move |c| *saved.lock().unwrap() = Some(c.clone()))
+        .build()
+    };
+
+    let stale = stale_credential();
+    assert_error_code(crate::apis::live::user::live_checkin(&client, &stale), -101);
+
+    let received = server.received();
+    let paths: Vec<_> = received
+      .iter()
+      .map(|r| r.url.split('/').take(3).collect::<Vec<_>>().join("/"))
+      .collect();
+    assert_eq!(
+      vec![
+        "/xlive/web-ucenter",
+        "/correspond/1",
+        "/x/passport-login",
+        "/x/passport-login",
+        "/xlive/web-ucenter"
+      ],
+      paths
+    );
+    // Retried with the refreshed credential
+    assert!(received[4]
+      .header("cookie")
+      .unwrap()
+      .starts_with("SESSDATA=new_sess; bili_jct=new_jct"));
+    assert_eq!("new_sess", client.credential(&stale).sessdata);
+    assert_eq!(
+      Some("new_token"),
+      saved.lock().unwrap().as_ref().unwrap().refresh_token()
+    );
+  }
+
+  #[cfg(feature = "async")]
+  #[tokio::test]
+  async fn test_auto_refresh_credential_async() {
+    let mut routes = COOKIE_REFRESH_ROUTES.to_vec();
+    routes.push((
+      "/xlive/web-ucenter/v1/sign/DoSign",
+      r#"{"code":-101,"message":"账号未登录","ttl":1}"#,
+    ));
+    let server = MockServer::start_with_headers(&routes, &COOKIE_REFRESH_HEADERS);
+    let client = crate::AsyncClient::builder()
+      .host(Host::Passport, server.base_url())
+      .host(Host::Www, server.base_url())
+      .host(Host::ApiLive, server.base_url())
+      .auto_refresh_credential(|_| {})
+      .build_async();
+
+    // API futures stay Send with the credential wrapper
+    let stale = stale_credential();
+    let task = {
+      let client = client.clone();
+      let stale = stale.clone();
+      tokio::spawn(
+        async move { crate::apis::live::user::live_checkin_async(&client, &stale).await },
+      )
+    };
+    assert_error_code(task.await.unwrap(), -101);
+    assert_eq!(5, server.received().len());
+    assert_eq!("new_sess", client.credential(&stale).sessdata);
+  }
+}
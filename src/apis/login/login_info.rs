@@ -2,7 +2,13 @@
 
 use serde::Deserialize;
 
-use crate::{credential::Credential, utils::handle_api_response, Client};
+#[cfg(feature = "async")]
+use crate::AsyncClient;
+use crate::{
+  client::{AuthKind, Endpoint, Host},
+  credential::Credential,
+  Client,
+};
 
 #[derive(Deserialize, Debug)]
 pub struct NavResponse {
@@ -115,7 +121,7 @@ pub struct Wallet {
   pub coupon_due_time: f64, // Placeholder, as no details are provided
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 pub struct WbiImg {
   #[serde(rename = "img_url")]
   pub img_url: String,
@@ -123,15 +129,36 @@ pub struct WbiImg {
   pub sub_url: String,
 }
 
+/// 导航栏用户信息, 其中包含WBI签名所用的`wbi_img`
+#[derive(Debug, Clone, Copy)]
+pub struct FetchNavInfo;
+
+impl Endpoint for FetchNavInfo {
+  type Response = NavResponse;
+
+  fn host(&self) -> Host {
+    Host::Api
+  }
+
+  fn path(&self) -> String {
+    "/x/web-interface/nav".to_string()
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+}
+
 pub fn fetch_nav_info(client: &Client, credential: &Credential) -> crate::Result<NavResponse> {
-  const API_URL: &str = "https://api.bilibili.com/x/web-interface/nav";
-
-  let request = client
-    .get(API_URL)
-    .header("cookie", credential.to_cookie_str())
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
-  handle_api_response(client.execute(request)?)
+  client.call(&FetchNavInfo, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn fetch_nav_info_async(
+  client: &AsyncClient,
+  credential: &Credential,
+) -> crate::Result<NavResponse> {
+  client.call(&FetchNavInfo, credential).await
 }
 
 #[cfg(test)]
@@ -139,9 +166,46 @@ mod tests {
   use std::path::Path;
 
   use super::*;
-  use crate::credential::extract_credential::get_credential_for_test_or_abort;
+  use crate::{credential::extract_credential::get_credential_for_test_or_abort, utils::cassette};
   #[test]
   fn test_fetch_nav_info() {
+    let agent = cassette::client("nav");
+    let cred = cassette::credential();
+
+    let result = fetch_nav_info(&agent, &cred);
+
+    assert!(result.is_ok());
+
+    let result = result.unwrap();
+    let webimg = result.data.wbi_img;
+    let img_url = webimg.img_url;
+    let sub_url = webimg.sub_url;
+
+    println!("{}", img_url);
+    println!("{}", sub_url);
+
+    let img_url = url::Url::parse(&img_url).unwrap();
+    let sub_url = url::Url::parse(&sub_url).unwrap();
+
+    println!("{:?}\n{:?}", img_url, sub_url);
+
+    let img_key = Path::new(img_url.path())
+      .file_stem()
+      .unwrap()
+      .to_str()
+      .unwrap();
+    let sub_key = Path::new(sub_url.path())
+      .file_stem()
+      .unwrap()
+      .to_str()
+      .unwrap();
+
+    println!("{}\n{}", img_key, sub_key);
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_fetch_nav_info_live() {
     let agent = Client::new();
     let cred = get_credential_for_test_or_abort();
 
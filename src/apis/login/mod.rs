@@ -1 +1,5 @@
+pub mod cookie_refresh;
 pub mod login_info;
+pub mod oauth2;
+pub mod qr_login;
+pub mod tv_qr_login;
@@ -0,0 +1,335 @@
+//! APP端access_key相关API, 均需要APP签名
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "async")]
+use crate::AsyncClient;
+use crate::{
+  apis::misc::sign::app::AppKey,
+  client::{AuthKind, Body, Endpoint, Host, Params, Signing},
+  credential::{AppCredential, Credential},
+  utils::now_millis,
+  Client, Error,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AppTokenInfoResponse {
+  pub data: AppTokenInfoData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AppTokenInfoData {
+  pub mid: i64,
+  pub access_token: String,
+  /// 剩余有效时间(秒)
+  pub expires_in: i64,
+  /// 是否应该刷新
+  #[serde(default)]
+  pub refresh: bool,
+}
+
+/// 登录或刷新后下发的access_key
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AppTokenInfo {
+  pub mid: i64,
+  pub access_token: String,
+  pub refresh_token: String,
+  /// 有效时间(秒)
+  pub expires_in: i64,
+}
+
+impl AppTokenInfo {
+  /// The credential of this access_key, issued with `app_key` (None for the appkey of the
+  /// Client)
+  pub fn to_credential(&self, app_key: Option<AppKey>) -> AppCredential {
+    AppCredential {
+      access_key: self.access_token.clone(),
+      refresh_token: Some(self.refresh_token.clone()),
+      mid: Some(self.mid),
+      expires_at: Some(now_millis() / 1000 + self.expires_in),
+      app_key,
+    }
+  }
+}
+
+/// 与access_key一同下发的Web端Cookie
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AppCookieInfo {
+  pub cookies: Vec<AppCookie>,
+  #[serde(default)]
+  pub domains: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AppCookie {
+  pub name: String,
+  pub value: String,
+  #[serde(default)]
+  pub http_only: i32,
+  /// 过期的Unix时间戳(秒)
+  #[serde(default)]
+  pub expires: i64,
+  #[serde(default)]
+  pub secure: i32,
+}
+
+impl AppCookieInfo {
+  /// The web credential made of these cookies
+  pub fn to_credential(&self) -> Credential {
+    let mut credential = Credential::default();
+    for cookie in &self.cookies {
+      credential.set_cookie(&cookie.name, cookie.value.clone());
+    }
+    credential
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RefreshAppTokenResponse {
+  pub data: RefreshAppTokenData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RefreshAppTokenData {
+  pub token_info: AppTokenInfo,
+  #[serde(default)]
+  pub cookie_info: Option<AppCookieInfo>,
+}
+
+/// 查询access_key的状态, access_key无效时返回-101
+#[derive(Debug, Clone, Default)]
+pub struct GetAppTokenInfo {
+  /// 签发access_key时的appkey, 参见[AppCredential::app_key]
+  pub app_key: Option<AppKey>,
+}
+
+impl Endpoint for GetAppTokenInfo {
+  type Response = AppTokenInfoResponse;
+
+  fn host(&self) -> Host {
+    Host::Passport
+  }
+
+  fn path(&self) -> String {
+    "/x/passport-login/oauth2/info".to_string()
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::App
+  }
+
+  fn signing(&self) -> Signing {
+    Signing::App(self.app_key.clone())
+  }
+}
+
+pub fn get_app_token_info(
+  client: &Client,
+  credential: &AppCredential,
+) -> crate::Result<AppTokenInfoResponse> {
+  let endpoint = GetAppTokenInfo {
+    app_key: credential.app_key.clone(),
+  };
+  client.call(&endpoint, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_app_token_info_async(
+  client: &AsyncClient,
+  credential: &AppCredential,
+) -> crate::Result<AppTokenInfoResponse> {
+  let endpoint = GetAppTokenInfo {
+    app_key: credential.app_key.clone(),
+  };
+  client.call(&endpoint, credential).await
+}
+
+/// 以refresh_token换取新的access_key, 旧的access_key随即失效.
+/// 须使用签发access_key时的appkey, e.g., TV端扫码登录得到的凭据须使用[AppKey::tv].
+#[derive(Debug, Clone)]
+pub struct RefreshAppToken {
+  pub refresh_token: String,
+  /// 签发access_key时的appkey, 参见[AppCredential::app_key]
+  pub app_key: Option<AppKey>,
+}
+
+impl Endpoint for RefreshAppToken {
+  type Response = RefreshAppTokenResponse;
+
+  fn host(&self) -> Host {
+    Host::Passport
+  }
+
+  fn path(&self) -> String {
+    "/x/passport-login/oauth2/refresh_token".to_string()
+  }
+
+  fn method(&self) -> Method {
+    Method::POST
+  }
+
+  fn body(&self) -> crate::Result<Body> {
+    Ok(Body::Form(
+      Params::new().with("refresh_token", &self.refresh_token),
+    ))
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::App
+  }
+
+  fn signing(&self) -> Signing {
+    Signing::App(self.app_key.clone())
+  }
+}
+
+pub fn refresh_app_token(
+  client: &Client,
+  credential: &AppCredential,
+) -> crate::Result<RefreshAppTokenResponse> {
+  let endpoint = RefreshAppToken {
+    refresh_token: require_refresh_token(credential)?.to_string(),
+    app_key: credential.app_key.clone(),
+  };
+  client.call(&endpoint, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn refresh_app_token_async(
+  client: &AsyncClient,
+  credential: &AppCredential,
+) -> crate::Result<RefreshAppTokenResponse> {
+  let endpoint = RefreshAppToken {
+    refresh_token: require_refresh_token(credential)?.to_string(),
+    app_key: credential.app_key.clone(),
+  };
+  client.call(&endpoint, credential).await
+}
+
+fn require_refresh_token(credential: &AppCredential) -> crate::Result<&str> {
+  credential
+    .refresh_token
+    .as_deref()
+    .filter(|token| !token.is_empty())
+    .ok_or_else(|| Error::Credential("missing refresh_token".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::utils::{assert_error_code, mock_server::MockServer};
+
+  #[test]
+  fn test_get_app_token_info() {
+    let server = MockServer::start(&[(
+      "/x/passport-login/oauth2/info",
+      r#"{"code":0,"message":"0","ttl":1,"data":{"mid":12734361,"access_token":"token","expires_in":15551999,"refresh":false}}"#,
+    )]);
+    let client = Client::builder()
+      .host(Host::Passport, server.base_url())
+      .build();
+    let info = get_app_token_info(&client, &AppCredential::new("token".to_string()))
+      .unwrap()
+      .data;
+    assert_eq!(12734361, info.mid);
+    assert_eq!(15551999, info.expires_in);
+
+    let url = &server.received()[0].url;
+    assert!(
+      url.starts_with("/x/passport-login/oauth2/info?access_key=token&appkey=1d8b6e7d45233436&ts=")
+    );
+    assert!(url.contains("&sign="));
+  }
+
+  #[test]
+  fn test_get_app_token_info_invalid() {
+    let server = MockServer::start(&[(
+      "/x/passport-login/oauth2/info",
+      r#"{"code":-101,"message":"账号未登录","ttl":1}"#,
+    )]);
+    let client = Client::builder()
+      .host(Host::Passport, server.base_url())
+      .build();
+    assert_error_code(
+      get_app_token_info(&client, &AppCredential::new("expired".to_string())),
+      -101,
+    );
+  }
+
+  #[test]
+  fn test_refresh_app_token() {
+    let server = MockServer::start(&[(
+      "/x/passport-login/oauth2/refresh_token",
+      r#"{"code":0,"message":"0","ttl":1,"data":{"status":0,"message":"","is_new":false,
+        "token_info":{"mid":12734361,"access_token":"new_token","refresh_token":"new_refresh","expires_in":15552000},
+        "cookie_info":{"cookies":[{"name":"SESSDATA","value":"sess","http_only":1,"expires":1718105981,"secure":0},
+          {"name":"bili_jct","value":"jct","http_only":0,"expires":1718105981,"secure":0}],
+          "domains":[".bilibili.com"]}}}"#,
+    )]);
+    let client = Client::builder()
+      .host(Host::Passport, server.base_url())
+      .build();
+    let mut credential = AppCredential::new("old_token".to_string());
+    credential.refresh_token = Some("old_refresh".to_string());
+
+    let data = refresh_app_token(&client, &credential).unwrap().data;
+    let refreshed = data.token_info.to_credential(None);
+    assert_eq!("new_token", refreshed.access_key);
+    assert_eq!(Some("new_refresh"), refreshed.refresh_token.as_deref());
+    assert_eq!(Some(12734361), refreshed.mid);
+    let web = data.cookie_info.unwrap().to_credential();
+    assert_eq!("sess", web.sessdata);
+    assert_eq!("jct", web.bili_jct);
+
+    let request = &server.received()[0];
+    assert_eq!("POST", request.method);
+    assert!(request
+      .body
+      .starts_with("access_key=old_token&appkey=1d8b6e7d45233436&refresh_token=old_refresh&ts="));
+
+    assert!(matches!(
+      refresh_app_token(&client, &AppCredential::new("token".to_string())),
+      Err(Error::Credential(_))
+    ));
+  }
+
+  #[test]
+  fn test_refresh_app_token_with_app_key() {
+    let server = MockServer::start(&[
+      (
+        "/x/passport-login/oauth2/refresh_token",
+        r#"{"code":0,"message":"0","ttl":1,"data":{"token_info":{"mid":12734361,
+          "access_token":"new_token","refresh_token":"new_refresh","expires_in":15552000}}}"#,
+      ),
+      (
+        "/x/passport-login/oauth2/info",
+        r#"{"code":0,"message":"0","ttl":1,"data":{"mid":12734361,"access_token":"new_token","expires_in":15551999}}"#,
+      ),
+    ]);
+    let client = Client::builder()
+      .host(Host::Passport, server.base_url())
+      .build();
+    // Issued by TV QR login, while the client signs with the android appkey
+    let mut credential = AppTokenInfo {
+      mid: 12734361,
+      access_token: "old_token".to_string(),
+      refresh_token: "old_refresh".to_string(),
+      expires_in: 60,
+    }
+    .to_credential(Some(AppKey::tv()));
+
+    credential.refresh(&client).unwrap();
+    assert_eq!("new_token", credential.access_key);
+    assert_eq!(Some(AppKey::tv()), credential.app_key);
+    get_app_token_info(&client, &credential).unwrap();
+
+    let received = server.received();
+    assert!(received[0]
+      .body
+      .starts_with("access_key=old_token&appkey=4409e2ce8ffd12b8&refresh_token=old_refresh&ts="));
+    assert!(received[1].url.starts_with(
+      "/x/passport-login/oauth2/info?access_key=new_token&appkey=4409e2ce8ffd12b8&ts="
+    ));
+  }
+}
@@ -0,0 +1,298 @@
+//! https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/login/login_action/QR.md
+//!
+//! Web端扫码登录:
+//! 1. [generate_qr_login] 申请二维码URL及`qrcode_key`, 将URL展示为二维码
+//! 2. 使用哔哩哔哩客户端扫码并确认
+//! 3. 每隔1~2秒调用[poll_qr_login], 直到返回[QrLoginStatus::Success]或[QrLoginStatus::Expired]
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "async")]
+use crate::AsyncClient;
+use crate::{
+  client::{Auth, Endpoint, Host, HttpResponse, Params},
+  credential::Credential,
+  error::ApiError,
+  utils::{handle_api_response, set_cookies},
+  Client,
+};
+
+/// 二维码已失效
+pub const QR_EXPIRED: i32 = 86038;
+/// 二维码已扫码未确认
+pub const QR_SCANNED_UNCONFIRMED: i32 = 86090;
+/// 未扫码
+pub const QR_NOT_SCANNED: i32 = 86101;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GenerateQrLoginResponse {
+  pub data: QrLoginCode,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct QrLoginCode {
+  /// 二维码内容
+  pub url: String,
+  /// 扫码登录秘钥, 有效期180秒
+  pub qrcode_key: String,
+}
+
+#[cfg(feature = "qrcode")]
+impl QrLoginCode {
+  /// The QR code of [QrLoginCode::url] as rows of modules, `true` being dark
+  pub fn qr_matrix(&self) -> Vec<Vec<bool>> {
+    qr_matrix(&self.url)
+  }
+
+  /// Render the QR code with unicode half blocks, suitable for printing to a terminal
+  /// with a dark background.
+  pub fn render_terminal(&self) -> String {
+    render_terminal(&self.url)
+  }
+}
+
+#[cfg(feature = "qrcode")]
+pub(crate) fn qr_matrix(url: &str) -> Vec<Vec<bool>> {
+  let code = qrcode::QrCode::new(url.as_bytes()).expect("Login URL fits in a QR code");
+  let width = code.width();
+  code
+    .to_colors()
+    .chunks(width)
+    .map(|row| row.iter().map(|&c| c == qrcode::Color::Dark).collect())
+    .collect()
+}
+
+#[cfg(feature = "qrcode")]
+pub(crate) fn render_terminal(url: &str) -> String {
+  use qrcode::render::unicode::Dense1x2;
+
+  let code = qrcode::QrCode::new(url.as_bytes()).expect("Login URL fits in a QR code");
+  code
+    .render::<Dense1x2>()
+    .dark_color(Dense1x2::Light)
+    .light_color(Dense1x2::Dark)
+    .build()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PollQrLoginResponse {
+  pub data: PollQrLoginData,
+  /// 登录成功时`Set-Cookie`下发的Cookie
+  #[serde(skip)]
+  pub cookies: Vec<(String, String)>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PollQrLoginData {
+  /// 登录成功时为跨域登录URL, 其query中包含登录凭据
+  pub url: String,
+  /// 登录成功时为刷新凭据用的refresh_token
+  pub refresh_token: String,
+  pub timestamp: i64,
+  /// 扫码状态码
+  pub code: i32,
+  pub message: String,
+}
+
+/// 扫码登录状态
+#[derive(Debug, Clone)]
+pub enum QrLoginStatus {
+  /// 未扫码
+  NotScanned,
+  /// 已扫码, 等待在客户端上确认
+  ScannedUnconfirmed,
+  /// 二维码已失效, 需要重新申请
+  Expired,
+  /// 登录成功
+  Success(Credential),
+}
+
+/// 申请二维码
+#[derive(Debug, Clone, Copy)]
+pub struct GenerateQrLogin;
+
+impl Endpoint for GenerateQrLogin {
+  type Response = GenerateQrLoginResponse;
+
+  fn host(&self) -> Host {
+    Host::Passport
+  }
+
+  fn path(&self) -> String {
+    "/x/passport-login/web/qrcode/generate".to_string()
+  }
+}
+
+pub fn generate_qr_login(client: &Client) -> crate::Result<GenerateQrLoginResponse> {
+  client.call(&GenerateQrLogin, Auth::None)
+}
+
+#[cfg(feature = "async")]
+pub async fn generate_qr_login_async(
+  client: &AsyncClient,
+) -> crate::Result<GenerateQrLoginResponse> {
+  client.call(&GenerateQrLogin, Auth::None).await
+}
+
+/// 查询扫码状态
+#[derive(Debug, Clone)]
+pub struct PollQrLogin {
+  pub qrcode_key: String,
+}
+
+impl Endpoint for PollQrLogin {
+  type Response = PollQrLoginResponse;
+
+  fn host(&self) -> Host {
+    Host::Passport
+  }
+
+  fn path(&self) -> String {
+    "/x/passport-login/web/qrcode/poll".to_string()
+  }
+
+  fn query(&self) -> crate::Result<Params> {
+    Ok(Params::new().with("qrcode_key", &self.qrcode_key))
+  }
+
+  fn parse(&self, response: HttpResponse) -> crate::Result<Self::Response> {
+    let cookies = set_cookies(response.headers());
+    let mut response: PollQrLoginResponse = handle_api_response(response)?;
+    response.cookies = cookies;
+    Ok(response)
+  }
+}
+
+pub fn poll_qr_login(client: &Client, qrcode_key: &str) -> crate::Result<QrLoginStatus> {
+  let endpoint = PollQrLogin {
+    qrcode_key: qrcode_key.to_string(),
+  };
+  qr_login_status(client.call(&endpoint, Auth::None)?)
+}
+
+#[cfg(feature = "async")]
+pub async fn poll_qr_login_async(
+  client: &AsyncClient,
+  qrcode_key: &str,
+) -> crate::Result<QrLoginStatus> {
+  let endpoint = PollQrLogin {
+    qrcode_key: qrcode_key.to_string(),
+  };
+  qr_login_status(client.call(&endpoint, Auth::None).await?)
+}
+
+fn qr_login_status(response: PollQrLoginResponse) -> crate::Result<QrLoginStatus> {
+  let data = response.data;
+  match data.code {
+    0 => {
+      let mut credential = credential_from_login_url(&data.url);
+      for (name, value) in response.cookies {
+        credential.set_cookie(&name, value);
+      }
+      credential.ac_time_value = Some(data.refresh_token);
+      Ok(QrLoginStatus::Success(credential))
+    }
+    QR_NOT_SCANNED => Ok(QrLoginStatus::NotScanned),
+    QR_SCANNED_UNCONFIRMED => Ok(QrLoginStatus::ScannedUnconfirmed),
+    QR_EXPIRED => Ok(QrLoginStatus::Expired),
+    code => Err(ApiError::new(code, data.message).into()),
+  }
+}
+
+/// Cookies carried in the query string of the cross domain login URL
+fn credential_from_login_url(url: &str) -> Credential {
+  let mut credential = Credential::default();
+  if let Ok(url) = url::Url::parse(url) {
+    for (name, value) in url.query_pairs() {
+      if matches!(&*name, "Expires" | "gourl" | "first_domain") {
+        continue;
+      }
+      credential.set_cookie(&name, value);
+    }
+  }
+  credential
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::utils::mock_server::MockServer;
+
+  fn mock_client(server: &MockServer) -> Client {
+    Client::builder()
+      .host(Host::Passport, server.base_url())
+      .build()
+  }
+
+  #[test]
+  fn test_generate_qr_login() {
+    let server = MockServer::start(&[(
+      "/x/passport-login/web/qrcode/generate",
+      r#"{"code":0,"message":"0","ttl":1,"data":{
+        "url":"https://passport.bilibili.com/h5-app/passport/login/scan?navhide=1&qrcode_key=8ffb3cd8d1e4a8b2ab3b2c4cf0ba5d46",
+        "qrcode_key":"8ffb3cd8d1e4a8b2ab3b2c4cf0ba5d46"}}"#,
+    )]);
+    let code = generate_qr_login(&mock_client(&server)).unwrap().data;
+    assert_eq!("8ffb3cd8d1e4a8b2ab3b2c4cf0ba5d46", code.qrcode_key);
+  }
+
+  #[test]
+  fn test_poll_qr_login_pending_states() {
+    for (code, message) in [
+      (QR_NOT_SCANNED, "未扫码"),
+      (QR_SCANNED_UNCONFIRMED, "二维码已扫码未确认"),
+      (QR_EXPIRED, "二维码已失效"),
+    ] {
+      let body = format!(
+        r#"{{"code":0,"message":"0","ttl":1,"data":{{"url":"","refresh_token":"","timestamp":0,"code":{},"message":"{}"}}}}"#,
+        code, message
+      );
+      let server = MockServer::start(&[("/x/passport-login/web/qrcode/poll", &body)]);
+      let status = poll_qr_login(&mock_client(&server), "key").unwrap();
+      match (code, status) {
+        (QR_NOT_SCANNED, QrLoginStatus::NotScanned)
+        | (QR_SCANNED_UNCONFIRMED, QrLoginStatus::ScannedUnconfirmed)
+        | (QR_EXPIRED, QrLoginStatus::Expired) => {}
+        (code, status) => panic!("Unexpected status {:?} for {}", status, code),
+      }
+      assert_eq!(
+        "/x/passport-login/web/qrcode/poll?qrcode_key=key",
+        server.received()[0].url
+      );
+    }
+  }
+
+  #[test]
+  fn test_poll_qr_login_success() {
+    let server = MockServer::start(&[(
+      "/x/passport-login/web/qrcode/poll",
+      r#"{"code":0,"message":"0","ttl":1,"data":{
+        "url":"https://passport.biligame.com/x/passport-login/web/crossDomain?DedeUserID=12734361&DedeUserID__ckMd5=aaaa&Expires=1718105981&SESSDATA=abc%2C1718105981%2Cd9f2e%2A61&bili_jct=def&gourl=https%3A%2F%2Fwww.bilibili.com",
+        "refresh_token":"0123456789abcdef","timestamp":1702553981000,"code":0,"message":""}}"#,
+    )]);
+    let QrLoginStatus::Success(cred) = poll_qr_login(&mock_client(&server), "key").unwrap() else {
+      panic!("Expect login success");
+    };
+    assert_eq!("abc,1718105981,d9f2e*61", cred.sessdata);
+    assert_eq!("def", cred.bili_jct);
+    assert_eq!(Some("12734361"), cred.dede_user_id.as_deref());
+    assert_eq!(Some("0123456789abcdef"), cred.refresh_token());
+    assert_eq!(
+      Some("aaaa"),
+      cred.extra.get("DedeUserID__ckMd5").map(String::as_str)
+    );
+  }
+
+  #[cfg(feature = "qrcode")]
+  #[test]
+  fn test_render_qr_code() {
+    let code = QrLoginCode {
+      url: "https://passport.bilibili.com/h5-app/passport/login/scan?qrcode_key=abc".to_string(),
+      qrcode_key: "abc".to_string(),
+    };
+    let matrix = code.qr_matrix();
+    assert!(!matrix.is_empty());
+    assert!(matrix.iter().all(|row| row.len() == matrix.len()));
+    assert!(!code.render_terminal().is_empty());
+  }
+}
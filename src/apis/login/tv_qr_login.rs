@@ -0,0 +1,294 @@
+//! https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/login/login_action/QR.md
+//!
+//! TV端扫码登录, 登录成功后得到APP端的access_key, 无需浏览器:
+//! 1. [generate_tv_qr_login] 申请二维码URL及`auth_code`, 将URL展示为二维码
+//! 2. 使用哔哩哔哩客户端扫码并确认
+//! 3. 每隔1~2秒调用[poll_tv_qr_login], 直到返回[TvQrLoginStatus::Success]或[TvQrLoginStatus::Expired]
+//!
+//! 两个API固定使用[AppKey::tv]签名, 得到的凭据记录了该appkey, 查询及刷新时自动使用.
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "async")]
+use crate::AsyncClient;
+use crate::{
+  apis::{
+    login::{
+      oauth2::{AppCookieInfo, AppTokenInfo},
+      qr_login::{QR_EXPIRED, QR_SCANNED_UNCONFIRMED},
+    },
+    misc::sign::app::AppKey,
+  },
+  client::{Auth, Body, Endpoint, Host, Params, Signing},
+  credential::{AppCredential, Credential},
+  Client, Error,
+};
+
+/// TV端二维码未扫码
+pub const TV_QR_NOT_SCANNED: i32 = 86039;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GenerateTvQrLoginResponse {
+  pub data: TvQrLoginCode,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TvQrLoginCode {
+  /// 二维码内容
+  pub url: String,
+  /// 扫码登录秘钥, 有效期180秒
+  pub auth_code: String,
+}
+
+#[cfg(feature = "qrcode")]
+impl TvQrLoginCode {
+  /// The QR code of [TvQrLoginCode::url] as rows of modules, `true` being dark
+  pub fn qr_matrix(&self) -> Vec<Vec<bool>> {
+    crate::apis::login::qr_login::qr_matrix(&self.url)
+  }
+
+  /// Render the QR code with unicode half blocks, see [QrLoginCode::render_terminal](crate::apis::login::qr_login::QrLoginCode::render_terminal)
+  pub fn render_terminal(&self) -> String {
+    crate::apis::login::qr_login::render_terminal(&self.url)
+  }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PollTvQrLoginResponse {
+  pub data: PollTvQrLoginData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct PollTvQrLoginData {
+  #[serde(default)]
+  pub is_new: bool,
+  pub mid: i64,
+  pub access_token: String,
+  pub refresh_token: String,
+  /// 有效时间(秒)
+  pub expires_in: i64,
+  /// 同时下发的Web端Cookie
+  #[serde(default)]
+  pub cookie_info: Option<AppCookieInfo>,
+}
+
+impl PollTvQrLoginData {
+  pub fn to_credential(&self) -> AppCredential {
+    AppTokenInfo {
+      mid: self.mid,
+      access_token: self.access_token.clone(),
+      refresh_token: self.refresh_token.clone(),
+      expires_in: self.expires_in,
+    }
+    .to_credential(Some(AppKey::tv()))
+  }
+
+  /// The web credential issued together with the access_key, if any
+  pub fn to_web_credential(&self) -> Option<Credential> {
+    self.cookie_info.as_ref().map(AppCookieInfo::to_credential)
+  }
+}
+
+/// TV端扫码登录状态
+#[derive(Debug, Clone)]
+pub enum TvQrLoginStatus {
+  /// 未扫码
+  NotScanned,
+  /// 已扫码, 等待在客户端上确认
+  ScannedUnconfirmed,
+  /// 二维码已失效, 需要重新申请
+  Expired,
+  /// 登录成功
+  Success(Box<PollTvQrLoginData>),
+}
+
+/// 申请TV端二维码
+#[derive(Debug, Clone, Copy)]
+pub struct GenerateTvQrLogin;
+
+impl Endpoint for GenerateTvQrLogin {
+  type Response = GenerateTvQrLoginResponse;
+
+  fn host(&self) -> Host {
+    Host::Passport
+  }
+
+  fn path(&self) -> String {
+    "/x/passport-tv-login/qrcode/auth_code".to_string()
+  }
+
+  fn method(&self) -> Method {
+    Method::POST
+  }
+
+  fn body(&self) -> crate::Result<Body> {
+    Ok(Body::Form(Params::new().with("local_id", 0)))
+  }
+
+  fn signing(&self) -> Signing {
+    Signing::App(Some(AppKey::tv()))
+  }
+}
+
+pub fn generate_tv_qr_login(client: &Client) -> crate::Result<GenerateTvQrLoginResponse> {
+  client.call(&GenerateTvQrLogin, Auth::None)
+}
+
+#[cfg(feature = "async")]
+pub async fn generate_tv_qr_login_async(
+  client: &AsyncClient,
+) -> crate::Result<GenerateTvQrLoginResponse> {
+  client.call(&GenerateTvQrLogin, Auth::None).await
+}
+
+/// 查询TV端扫码状态, 未登录成功时返回对应的错误码, 参见[poll_tv_qr_login]
+#[derive(Debug, Clone)]
+pub struct PollTvQrLogin {
+  pub auth_code: String,
+}
+
+impl Endpoint for PollTvQrLogin {
+  type Response = PollTvQrLoginResponse;
+
+  fn host(&self) -> Host {
+    Host::Passport
+  }
+
+  fn path(&self) -> String {
+    "/x/passport-tv-login/qrcode/poll".to_string()
+  }
+
+  fn method(&self) -> Method {
+    Method::POST
+  }
+
+  fn body(&self) -> crate::Result<Body> {
+    Ok(Body::Form(
+      Params::new()
+        .with("auth_code", &self.auth_code)
+        .with("local_id", 0),
+    ))
+  }
+
+  fn signing(&self) -> Signing {
+    Signing::App(Some(AppKey::tv()))
+  }
+}
+
+pub fn poll_tv_qr_login(client: &Client, auth_code: &str) -> crate::Result<TvQrLoginStatus> {
+  let endpoint = PollTvQrLogin {
+    auth_code: auth_code.to_string(),
+  };
+  tv_qr_login_status(client.call(&endpoint, Auth::None))
+}
+
+#[cfg(feature = "async")]
+pub async fn poll_tv_qr_login_async(
+  client: &AsyncClient,
+  auth_code: &str,
+) -> crate::Result<TvQrLoginStatus> {
+  let endpoint = PollTvQrLogin {
+    auth_code: auth_code.to_string(),
+  };
+  tv_qr_login_status(client.call(&endpoint, Auth::None).await)
+}
+
+/// TV端的扫码状态由最外层的code表示
+fn tv_qr_login_status(
+  result: crate::Result<PollTvQrLoginResponse>,
+) -> crate::Result<TvQrLoginStatus> {
+  match result {
+    Ok(response) => Ok(TvQrLoginStatus::Success(Box::new(response.data))),
+    Err(Error::Api(e)) => match e.code() {
+      TV_QR_NOT_SCANNED => Ok(TvQrLoginStatus::NotScanned),
+      QR_SCANNED_UNCONFIRMED => Ok(TvQrLoginStatus::ScannedUnconfirmed),
+      QR_EXPIRED => Ok(TvQrLoginStatus::Expired),
+      _ => Err(Error::Api(e)),
+    },
+    Err(e) => Err(e),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::utils::{assert_error_code, mock_server::MockServer};
+
+  fn mock_client(server: &MockServer) -> Client {
+    Client::builder()
+      .host(Host::Passport, server.base_url())
+      .build()
+  }
+
+  #[test]
+  fn test_generate_tv_qr_login() {
+    let server = MockServer::start(&[(
+      "/x/passport-tv-login/qrcode/auth_code",
+      r#"{"code":0,"message":"0","ttl":1,"data":{
+        "url":"https://passport.bilibili.com/x/passport-tv-login/h5/qrcode/auth?auth_code=0a1b2c3d",
+        "auth_code":"0a1b2c3d"}}"#,
+    )]);
+    let code = generate_tv_qr_login(&mock_client(&server)).unwrap().data;
+    assert_eq!("0a1b2c3d", code.auth_code);
+
+    let request = &server.received()[0];
+    assert_eq!("POST", request.method);
+    assert!(request
+      .body
+      .starts_with("appkey=4409e2ce8ffd12b8&local_id=0&ts="));
+  }
+
+  #[test]
+  fn test_poll_tv_qr_login_pending_states() {
+    for (code, message) in [
+      (TV_QR_NOT_SCANNED, "二维码尚未确认"),
+      (QR_SCANNED_UNCONFIRMED, "二维码已扫码未确认"),
+      (QR_EXPIRED, "二维码已失效"),
+    ] {
+      let body = format!(r#"{{"code":{},"message":"{}","ttl":1}}"#, code, message);
+      let server = MockServer::start(&[("/x/passport-tv-login/qrcode/poll", &body)]);
+      let status = poll_tv_qr_login(&mock_client(&server), "0a1b2c3d").unwrap();
+      match (code, status) {
+        (TV_QR_NOT_SCANNED, TvQrLoginStatus::NotScanned)
+        | (QR_SCANNED_UNCONFIRMED, TvQrLoginStatus::ScannedUnconfirmed)
+        | (QR_EXPIRED, TvQrLoginStatus::Expired) => {}
+        (code, status) => panic!("Unexpected status {:?} for {}", status, code),
+      }
+    }
+
+    let server = MockServer::start(&[(
+      "/x/passport-tv-login/qrcode/poll",
+      r#"{"code":-3,"message":"API校验密匙错误","ttl":1}"#,
+    )]);
+    assert_error_code(poll_tv_qr_login(&mock_client(&server), "0a1b2c3d"), -3);
+  }
+
+  #[test]
+  fn test_poll_tv_qr_login_success() {
+    let server = MockServer::start(&[(
+      "/x/passport-tv-login/qrcode/poll",
+      r#"{"code":0,"message":"0","ttl":1,"data":{"is_new":false,"mid":12734361,
+        "access_token":"token","refresh_token":"refresh","expires_in":15552000,
+        "cookie_info":{"cookies":[{"name":"SESSDATA","value":"sess","http_only":1,"expires":1718105981,"secure":0},
+          {"name":"bili_jct","value":"jct","http_only":0,"expires":1718105981,"secure":0}],
+          "domains":[".bilibili.com"]}}}"#,
+    )]);
+    let TvQrLoginStatus::Success(data) =
+      poll_tv_qr_login(&mock_client(&server), "0a1b2c3d").unwrap()
+    else {
+      panic!("Expect login success");
+    };
+    let credential = data.to_credential();
+    assert_eq!("token", credential.access_key);
+    assert_eq!(Some("refresh"), credential.refresh_token.as_deref());
+    assert_eq!(Some(12734361), credential.mid);
+    assert!(credential.expires_at.unwrap() > 1702204169);
+    assert_eq!(Some(AppKey::tv()), credential.app_key);
+    assert_eq!("sess", data.to_web_credential().unwrap().sessdata);
+
+    assert!(server.received()[0]
+      .body
+      .starts_with("appkey=4409e2ce8ffd12b8&auth_code=0a1b2c3d&local_id=0&ts="));
+  }
+}
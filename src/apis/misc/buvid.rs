@@ -0,0 +1,387 @@
+//! https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/misc/buvid3_4.md
+//!
+//! 设备指纹`buvid3`/`buvid4`, 缺少时部分API会被风控拦截并返回-352.
+//! 1. [get_spi] 申请`buvid3`/`buvid4`, 或以[Buvid::generate]在本地生成
+//! 2. [activate_buvid] 上报设备信息以激活, 未激活的`buvid3`同样会触发风控
+//!
+//! [init_buvid]完成以上步骤并保存到Client中, 之后Client发出的每个请求都会带上这些Cookie.
+//! 也可以通过[ClientBuilder::auto_buvid](crate::client::ClientBuilder::auto_buvid)在第一次请求前自动完成.
+
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+#[cfg(feature = "async")]
+use crate::AsyncClient;
+use crate::{
+  client::{Auth, Body, Endpoint, Host},
+  utils::{now_millis, random_u64},
+  Client,
+};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SpiResponse {
+  pub data: SpiData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SpiData {
+  /// buvid3
+  pub b_3: String,
+  /// buvid4
+  pub b_4: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ActivateBuvidResponse {}
+
+/// 设备指纹Cookie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Buvid {
+  pub buvid3: String,
+  pub buvid4: String,
+  /// 生成时的Unix时间戳(秒)
+  pub b_nut: i64,
+  /// `_uuid`
+  pub uuid: String,
+}
+
+impl Buvid {
+  /// The fingerprint issued by [get_spi], with `b_nut` and `_uuid` generated locally
+  pub fn from_spi(data: SpiData) -> Self {
+    Self {
+      buvid3: data.b_3,
+      buvid4: data.b_4,
+      b_nut: now_millis() / 1000,
+      uuid: generate_uuid(),
+    }
+  }
+
+  /// Generates a fingerprint locally in the formats used by the web player,
+  /// for when [get_spi] is unavailable
+  pub fn generate() -> Self {
+    Self {
+      buvid3: generate_uuid(),
+      buvid4: format!("{}-{:012}-{}", generate_uuid(), now_millis(), random_hex(8)),
+      b_nut: now_millis() / 1000,
+      uuid: generate_uuid(),
+    }
+  }
+
+  /// (name, value) of the cookies
+  pub fn cookies(&self) -> impl Iterator<Item = (&str, String)> {
+    [
+      ("buvid3", self.buvid3.clone()),
+      ("buvid4", self.buvid4.clone()),
+      ("b_nut", self.b_nut.to_string()),
+      ("_uuid", self.uuid.clone()),
+    ]
+    .into_iter()
+    .filter(|(_, value)| !value.is_empty())
+  }
+}
+
+/// `XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXXnnnnninfoc`, the format of both `buvid3` and `_uuid`
+fn generate_uuid() -> String {
+  let hex = random_hex(32).to_uppercase();
+  format!(
+    "{}-{}-{}-{}-{}{:05}infoc",
+    &hex[..8],
+    &hex[8..12],
+    &hex[12..16],
+    &hex[16..20],
+    &hex[20..],
+    now_millis() % 100000
+  )
+}
+
+fn random_hex(len: usize) -> String {
+  let mut hex = String::with_capacity(len + 16);
+  while hex.len() < len {
+    hex.push_str(&format!("{:016x}", random_u64()));
+  }
+  hex.truncate(len);
+  hex
+}
+
+/// The device info reported by [activate_buvid]. Only its shape is checked, so fixed
+/// values are reported.
+fn activation_payload() -> String {
+  let payload = json!({
+    "3064": 1,
+    "5062": now_millis().to_string(),
+    "03bf": "https://www.bilibili.com/",
+    "39c8": "333.1007.fp.risk",
+    "34f1": "",
+    "d402": "",
+    "654a": "",
+    "6e7c": "1920x1080",
+    "3c43": {
+      "2673": 0,
+      "5766": 24,
+      "6527": 0,
+      "7003": 1,
+      "807e": 1,
+      "b8ce": crate::apis::USER_AGENT,
+      "641c": 0,
+      "07a4": "zh-CN",
+      "1c57": 8,
+      "0bd0": 8,
+      "748e": [1920, 1080],
+      "d61f": [1920, 1040],
+      "fc9d": -480,
+      "6aa9": "Asia/Shanghai",
+      "75b8": 1,
+      "3b21": 1,
+      "8a1c": 0,
+      "d52f": "not available",
+      "adca": "Win32",
+      "80c9": [],
+      "13ab": "",
+      "bfe9": "",
+      "a3c1": [],
+      "6bc5": "",
+      "ed31": 0,
+      "72bd": 0,
+      "097b": 0,
+      "52cd": [0, 0, 0],
+      "a658": [],
+      "d02f": "124.04347527516074"
+    },
+    "54ef": "{}",
+    "8b94": "",
+    "df35": "",
+    "07a4": "zh-CN",
+    "5f45": null,
+    "db46": 0
+  });
+  json!({ "payload": payload.to_string() }).to_string()
+}
+
+/// 申请`buvid3`/`buvid4`
+#[derive(Debug, Clone, Copy)]
+pub struct GetSpi;
+
+impl Endpoint for GetSpi {
+  type Response = SpiResponse;
+
+  fn host(&self) -> Host {
+    Host::Api
+  }
+
+  fn path(&self) -> String {
+    "/x/frontend/finger/spi".to_string()
+  }
+}
+
+pub fn get_spi(client: &Client) -> crate::Result<SpiResponse> {
+  client.call(&GetSpi, Auth::None)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_spi_async(client: &AsyncClient) -> crate::Result<SpiResponse> {
+  client.call(&GetSpi, Auth::None).await
+}
+
+/// 激活`buvid`. 以Cookie的形式发送, 须先保存到Client中.
+#[derive(Debug, Clone, Copy)]
+pub struct ActivateBuvid;
+
+impl Endpoint for ActivateBuvid {
+  type Response = ActivateBuvidResponse;
+
+  fn host(&self) -> Host {
+    Host::Api
+  }
+
+  fn path(&self) -> String {
+    "/x/internal/gaia-gateway/ExClimbWuzhi".to_string()
+  }
+
+  fn method(&self) -> Method {
+    Method::POST
+  }
+
+  fn body(&self) -> crate::Result<Body> {
+    Ok(Body::Json(activation_payload()))
+  }
+}
+
+pub fn activate_buvid(client: &Client) -> crate::Result<ActivateBuvidResponse> {
+  client.call(&ActivateBuvid, Auth::None)
+}
+
+#[cfg(feature = "async")]
+pub async fn activate_buvid_async(client: &AsyncClient) -> crate::Result<ActivateBuvidResponse> {
+  client.call(&ActivateBuvid, Auth::None).await
+}
+
+/// 申请并激活设备指纹, 保存到`client`中. 已保存的指纹会被替换.
+pub fn init_buvid(client: &Client) -> crate::Result<Buvid> {
+  let buvid = Buvid::from_spi(get_spi(client)?.data);
+  client.set_buvid(buvid.clone());
+  activate_buvid(client)?;
+  Ok(buvid)
+}
+
+#[cfg(feature = "async")]
+pub async fn init_buvid_async(client: &AsyncClient) -> crate::Result<Buvid> {
+  let buvid = Buvid::from_spi(get_spi_async(client).await?.data);
+  client.set_buvid(buvid.clone());
+  activate_buvid_async(client).await?;
+  Ok(buvid)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    apis::live::info::get_room_init_info, credential::Credential, utils::mock_server::MockServer,
+  };
+
+  const SPI: &str = r#"{"code":0,"message":"ok","data":{
+    "b_3":"5B4A4F23-0D44-1B5B-2C51-6D5E2A1B3C4D12345infoc",
+    "b_4":"8B1F2E3D-4C5B-6A79-8877-665544332211-023121012-abcdefgh"}}"#;
+  const ACTIVATED: &str = r#"{"code":0,"message":"0","ttl":1,"data":{}}"#;
+  const ROOM_INIT: &str = r#"{"code":0,"msg":"ok","message":"ok","data":{"room_id":5050,
+    "short_id":0,"uid":1,"need_p2p":0,"is_hidden":false,"is_locked":false,"is_portrait":false,
+    "live_status":1,"hidden_till":0,"lock_till":0,"encrypted":false,"pwd_verified":false,
+    "live_time":0,"room_shield":0,"is_sp":0,"special_type":0}}"#;
+
+  #[test]
+  fn test_generate() {
+    let buvid = Buvid::generate();
+    assert_eq!(46, buvid.buvid3.len());
+    assert!(buvid.buvid3.ends_with("infoc"));
+    assert_eq!(
+      vec![8, 4, 4, 4, 22],
+      buvid.buvid3.split('-').map(str::len).collect::<Vec<_>>()
+    );
+    assert_ne!(buvid.buvid3, buvid.uuid);
+    assert!(buvid.b_nut > 1702204169);
+  }
+
+  #[test]
+  fn test_init_buvid() {
+    let server = MockServer::start(&[
+      ("/x/frontend/finger/spi", SPI),
+      ("/x/internal/gaia-gateway/ExClimbWuzhi", ACTIVATED),
+      ("/room/v1/Room/room_init", ROOM_INIT),
+    ]);
+    let client = Client::builder()
+      .host(Host::Api, server.base_url())
+      .host(Host::ApiLive, server.base_url())
+      .build();
+    assert_eq!(None, client.buvid());
+
+    let buvid = init_buvid(&client).unwrap();
+    assert_eq!(
+      "5B4A4F23-0D44-1B5B-2C51-6D5E2A1B3C4D12345infoc",
+      buvid.buvid3
+    );
+    assert_eq!(Some(buvid.clone()), client.buvid());
+
+    let received = server.received();
+    assert_eq!(None, received[0].header("cookie"));
+    let activation = &received[1];
+    assert_eq!("POST", activation.method);
+    assert!(activation.body.starts_with(r#"{"payload":"{\"#));
+    let cookie = activation.header("cookie").unwrap();
+    assert!(cookie.starts_with(
+      "buvid3=5B4A4F23-0D44-1B5B-2C51-6D5E2A1B3C4D12345infoc; \
+       buvid4=8B1F2E3D-4C5B-6A79-8877-665544332211-023121012-abcdefgh; b_nut="
+    ));
+    assert!(cookie.contains(&format!("_uuid={}", buvid.uuid)));
+
+    // Attached to later requests as well, unless the credential carries its own
+    get_room_init_info(&client, 5050).unwrap();
+    assert_eq!(Some(cookie), server.received()[2].header("cookie"));
+    let mut credential = Credential::new("sess".to_string(), "jct".to_string());
+    credential.set_cookie("buvid3", "mine");
+    client.call(&GetSpi, &credential).unwrap();
+    let cookie = server.received()[3].header("cookie").unwrap().to_string();
+    assert!(cookie.starts_with("SESSDATA=sess; bili_jct=jct; buvid3=mine; buvid4=8B1F2E3D"));
+  }
+
+  #[test]
+  fn test_auto_buvid() {
+    let server = MockServer::start(&[
+      ("/x/frontend/finger/spi", SPI),
+      ("/x/internal/gaia-gateway/ExClimbWuzhi", ACTIVATED),
+      ("/room/v1/Room/room_init", ROOM_INIT),
+    ]);
+    let client = Client::builder()
+      .host(Host::Api, server.base_url())
+      .host(Host::ApiLive, server.base_url())
+      .auto_buvid()
+      .build();
+    get_room_init_info(&client, 5050).unwrap();
+    get_room_init_info(&client, 5050).unwrap();
+
+    let received = server.received();
+    let urls: Vec<_> = received.iter().map(|r| r.url.as_str()).collect();
+    assert_eq!(
+      vec![
+        "/x/frontend/finger/spi",
+        "/x/internal/gaia-gateway/ExClimbWuzhi",
+        "/room/v1/Room/room_init?id=5050",
+        "/room/v1/Room/room_init?id=5050",
+      ],
+      urls
+    );
+    assert!(received[2]
+      .header("cookie")
+      .unwrap()
+      .starts_with("buvid3=5B4A4F23-0D44-1B5B-2C51-6D5E2A1B3C4D12345infoc;"));
+  }
+
+  #[test]
+  fn test_auto_buvid_falls_back_to_local() {
+    let server = MockServer::start(&[
+      (
+        "/x/frontend/finger/spi",
+        r#"{"code":-412,"message":"请求被拦截"}"#,
+      ),
+      ("/room/v1/Room/room_init", ROOM_INIT),
+    ]);
+    let client = Client::builder()
+      .host(Host::Api, server.base_url())
+      .host(Host::ApiLive, server.base_url())
+      .auto_buvid()
+      .build();
+    get_room_init_info(&client, 5050).unwrap();
+    let buvid = client.buvid().unwrap();
+    assert!(buvid.buvid3.ends_with("infoc"));
+    assert!(server.received()[1]
+      .header("cookie")
+      .unwrap()
+      .starts_with(&format!("buvid3={};", buvid.buvid3)));
+  }
+
+  #[cfg(feature = "async")]
+  #[tokio::test]
+  async fn test_auto_buvid_async() {
+    use crate::apis::live::info::get_room_init_info_async;
+
+    let server = MockServer::start(&[
+      ("/x/frontend/finger/spi", SPI),
+      ("/x/internal/gaia-gateway/ExClimbWuzhi", ACTIVATED),
+      ("/room/v1/Room/room_init", ROOM_INIT),
+    ]);
+    let client = AsyncClient::builder()
+      .host(Host::Api, server.base_url())
+      .host(Host::ApiLive, server.base_url())
+      .auto_buvid()
+      .build_async();
+    let handle = tokio::spawn(async move {
+      get_room_init_info_async(&client, 5050).await.unwrap();
+      client
+    });
+    let client = handle.await.unwrap();
+    assert_eq!(
+      "5B4A4F23-0D44-1B5B-2C51-6D5E2A1B3C4D12345infoc",
+      client.buvid().unwrap().buvid3
+    );
+    assert_eq!(3, server.received().len());
+  }
+}
@@ -1 +1,2 @@
+pub mod buvid;
 pub mod sign;
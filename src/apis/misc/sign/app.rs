@@ -0,0 +1,135 @@
+//! https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/misc/sign/APPKey.md
+//!
+//! APP端API签名: 在参数中加入`appkey`及`ts`, 按key排序并URL编码后拼接`appsec`, 取MD5作为`sign`.
+
+use std::{
+  collections::BTreeMap,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// APP端的appkey及对应的appsec
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppKey {
+  pub appkey: String,
+  pub appsec: String,
+}
+
+impl AppKey {
+  pub fn new(appkey: impl Into<String>, appsec: impl Into<String>) -> Self {
+    Self {
+      appkey: appkey.into(),
+      appsec: appsec.into(),
+    }
+  }
+
+  /// 粉版Android客户端, 与[USER_AGENT](crate::apis)一致
+  pub fn android() -> Self {
+    Self::new("1d8b6e7d45233436", "560c52ccd288fed045859ed18bffd973")
+  }
+
+  /// 云视听小电视, 用于TV端扫码登录
+  pub fn tv() -> Self {
+    Self::new("4409e2ce8ffd12b8", "59b43e04ad6965f34319062b478f83dd")
+  }
+}
+
+impl Default for AppKey {
+  fn default() -> Self {
+    Self::android()
+  }
+}
+
+/// Sign `params` with `app_key` at the current time, see [app_sign_with_ts]
+pub fn app_sign<K, V>(
+  params: impl IntoIterator<Item = (K, V)>,
+  app_key: &AppKey,
+) -> Vec<(String, String)>
+where
+  K: Into<String>,
+  V: Into<String>,
+{
+  let ts = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .expect("SystemTime before UNIX EPOCH")
+    .as_secs();
+  app_sign_with_ts(params, app_key, ts)
+}
+
+/// Returns `params` with `appkey` and `ts` added, sorted by key and followed by `sign`,
+/// ready to be sent as the query string or form body of an APP API request.
+/// An existing `ts` in `params` is kept.
+pub fn app_sign_with_ts<K, V>(
+  params: impl IntoIterator<Item = (K, V)>,
+  app_key: &AppKey,
+  ts: u64,
+) -> Vec<(String, String)>
+where
+  K: Into<String>,
+  V: Into<String>,
+{
+  let mut params: BTreeMap<String, String> = params
+    .into_iter()
+    .map(|(k, v)| (k.into(), v.into()))
+    .collect();
+  params.insert("appkey".to_string(), app_key.appkey.clone());
+  params
+    .entry("ts".to_string())
+    .or_insert_with(|| ts.to_string());
+
+  let sign = compute_app_signature(&params, &app_key.appsec);
+  let mut signed: Vec<_> = params.into_iter().collect();
+  signed.push(("sign".to_string(), sign));
+  signed
+}
+
+fn compute_app_signature(params: &BTreeMap<String, String>, appsec: &str) -> String {
+  let query = url::form_urlencoded::Serializer::new(String::new())
+    .extend_pairs(params)
+    .finish();
+  format!("{:02x}", md5::compute(query + appsec))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_app_sign() {
+    let signed = app_sign_with_ts(
+      [
+        ("id", "114514"),
+        ("str", "1919810"),
+        ("test", "いいよ，こいよ"),
+      ],
+      &AppKey::android(),
+      1702204169,
+    );
+    let keys: Vec<_> = signed.iter().map(|(k, _)| k.as_str()).collect();
+    assert_eq!(vec!["appkey", "id", "str", "test", "ts", "sign"], keys);
+
+    // md5 of "appkey=1d8b6e7d45233436&id=114514&str=1919810&test=%E3%81%84...&ts=1702204169"
+    // followed by the appsec, computed with Python's urllib.parse.urlencode and hashlib
+    assert_eq!(
+      (
+        "sign".to_string(),
+        "d54317b2dea8f9df3a14f02aeddc2b20".to_string()
+      ),
+      signed[5]
+    );
+  }
+
+  #[test]
+  fn test_app_sign_keeps_ts() {
+    let signed = app_sign_with_ts([("ts", "42")], &AppKey::tv(), 1702204169);
+    assert_eq!(("ts".to_string(), "42".to_string()), signed[1]);
+    assert_eq!(
+      (
+        "sign".to_string(),
+        "9f4f4b4f9bd934096a30733d0ece2396".to_string()
+      ),
+      signed[2]
+    );
+  }
+}
@@ -0,0 +1,351 @@
+//! https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/misc/sign/bili_ticket.md
+//!
+//! `bili_ticket`: 由`GenWebTicket`签发的Cookie, 有效期3天, 缺少时Web端API更容易触发风控.
+//! 申请时以固定密钥对时间戳做HMAC-SHA256签名. 下发的`nav`与导航栏API的`wbi_img`相同,
+//! 因此申请时也会一并更新Client缓存的WBI密钥.
+
+use hmac::{Hmac, Mac};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+#[cfg(feature = "async")]
+use crate::AsyncClient;
+use crate::{
+  apis::{login::login_info::WbiImg, misc::sign::wbi::mixin_key_from_wbi_img},
+  client::{Endpoint, Host, Params},
+  credential::Credential,
+  utils::now_millis,
+  Client,
+};
+
+const HMAC_KEY: &[u8] = b"XgwSnGZ1p";
+const KEY_ID: &str = "ec02";
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GenWebTicketResponse {
+  pub data: GenWebTicketData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GenWebTicketData {
+  pub ticket: String,
+  /// 签发时的Unix时间戳(秒)
+  pub created_at: i64,
+  /// 有效时间(秒)
+  pub ttl: i64,
+  pub nav: TicketNav,
+}
+
+/// WBI签名密钥的图片URL, 同[WbiImg]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TicketNav {
+  pub img: String,
+  pub sub: String,
+}
+
+impl TicketNav {
+  pub fn to_wbi_img(&self) -> WbiImg {
+    WbiImg {
+      img_url: self.img.clone(),
+      sub_url: self.sub.clone(),
+    }
+  }
+}
+
+/// `bili_ticket`及其过期时间
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BiliTicket {
+  pub ticket: String,
+  /// 过期的Unix时间戳(秒), 即Cookie `bili_ticket_expires`
+  pub expires_at: i64,
+}
+
+impl BiliTicket {
+  pub fn from_data(data: &GenWebTicketData) -> Self {
+    Self {
+      ticket: data.ticket.clone(),
+      expires_at: data.created_at + data.ttl,
+    }
+  }
+
+  /// Whether the ticket expires within `margin` seconds from `now` (Unix seconds)
+  pub fn expires_within(&self, now: i64, margin: i64) -> bool {
+    self.expires_at - margin <= now
+  }
+
+  /// (name, value) of the cookies
+  pub fn cookies(&self) -> impl Iterator<Item = (&str, String)> {
+    [
+      ("bili_ticket", self.ticket.clone()),
+      ("bili_ticket_expires", self.expires_at.to_string()),
+    ]
+    .into_iter()
+  }
+}
+
+/// HMAC-SHA256 of `ts{ts}` in lower hex, the `hexsign` of [gen_web_ticket]
+pub fn bili_ticket_hexsign(ts: i64) -> String {
+  let mut mac = Hmac::<Sha256>::new_from_slice(HMAC_KEY).expect("HMAC takes keys of any size");
+  mac.update(format!("ts{}", ts).as_bytes());
+  mac
+    .finalize()
+    .into_bytes()
+    .iter()
+    .map(|b| format!("{:02x}", b))
+    .collect()
+}
+
+/// 申请`bili_ticket`
+#[derive(Debug, Clone, Default)]
+pub struct GenWebTicket {
+  /// `bili_jct`, 未登录时为空
+  pub csrf: String,
+}
+
+impl Endpoint for GenWebTicket {
+  type Response = GenWebTicketResponse;
+
+  fn host(&self) -> Host {
+    Host::Api
+  }
+
+  fn path(&self) -> String {
+    "/bapis/bilibili.api.ticket.v1.Ticket/GenWebTicket".to_string()
+  }
+
+  fn method(&self) -> Method {
+    Method::POST
+  }
+
+  fn query(&self) -> crate::Result<Params> {
+    let ts = now_millis() / 1000;
+    Ok(
+      Params::new()
+        .with("key_id", KEY_ID)
+        .with("hexsign", bili_ticket_hexsign(ts))
+        .with("context[ts]", ts)
+        .with("csrf", &self.csrf),
+    )
+  }
+}
+
+fn gen_web_ticket_endpoint(credential: Option<&Credential>) -> GenWebTicket {
+  GenWebTicket {
+    csrf: credential.map(|c| c.bili_jct.clone()).unwrap_or_default(),
+  }
+}
+
+/// 申请`bili_ticket`. 未登录时`credential`可为空.
+pub fn gen_web_ticket(
+  client: &Client,
+  credential: Option<&Credential>,
+) -> crate::Result<GenWebTicketResponse> {
+  client.call(&gen_web_ticket_endpoint(credential), credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn gen_web_ticket_async(
+  client: &AsyncClient,
+  credential: Option<&Credential>,
+) -> crate::Result<GenWebTicketResponse> {
+  client
+    .call(&gen_web_ticket_endpoint(credential), credential)
+    .await
+}
+
+/// 申请`bili_ticket`并保存到`client`中, 之后Client发出的每个请求都会带上.
+/// 同时以下发的`nav`更新WBI密钥缓存.
+pub fn init_bili_ticket(client: &Client) -> crate::Result<BiliTicket> {
+  let data = gen_web_ticket(client, None)?.data;
+  client
+    .wbi_keys()
+    .set(mixin_key_from_wbi_img(&data.nav.to_wbi_img())?);
+  let ticket = BiliTicket::from_data(&data);
+  client.set_bili_ticket(ticket.clone());
+  Ok(ticket)
+}
+
+#[cfg(feature = "async")]
+pub async fn init_bili_ticket_async(client: &AsyncClient) -> crate::Result<BiliTicket> {
+  let data = gen_web_ticket_async(client, None).await?.data;
+  client
+    .wbi_keys()
+    .set(mixin_key_from_wbi_img(&data.nav.to_wbi_img())?);
+  let ticket = BiliTicket::from_data(&data);
+  client.set_bili_ticket(ticket.clone());
+  Ok(ticket)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{apis::live::info::get_room_init_info, utils::mock_server::MockServer};
+
+  const TICKET: &str = r#"{"code":0,"message":"OK","data":{"ticket":"eyJhbGciOiJIUzI1NiJ9.ticket",
+    "created_at":1702204169,"ttl":259200,"context":{},
+    "nav":{"img":"https://i0.hdslb.com/bfs/wbi/7cd084941338484aae1ad9425b84077c.png",
+      "sub":"https://i0.hdslb.com/bfs/wbi/4932caff0ff746eab6f01bf08b70ac45.png"}},"ttl":1}"#;
+  const ROOM_INIT: &str = r#"{"code":0,"msg":"ok","message":"ok","data":{"room_id":5050,
+    "short_id":0,"uid":1,"need_p2p":0,"is_hidden":false,"is_locked":false,"is_portrait":false,
+    "live_status":1,"hidden_till":0,"lock_till":0,"encrypted":false,"pwd_verified":false,
+    "live_time":0,"room_shield":0,"is_sp":0,"special_type":0}}"#;
+
+  #[test]
+  fn test_hexsign() {
+    assert_eq!(
+      "4899d09c73cc357c191a77c41562ea9a7b07e858e534040cf2a24bbadbecbbbf",
+      bili_ticket_hexsign(1702204169)
+    );
+  }
+
+  fn fresh_ticket() -> String {
+    TICKET.replace("1702204169", &(now_millis() / 1000).to_string())
+  }
+
+  #[test]
+  fn test_init_bili_ticket() {
+    let fresh = fresh_ticket();
+    let server = MockServer::start(&[
+      ("/bapis/bilibili.api.ticket.v1.Ticket/GenWebTicket", &fresh),
+      ("/room/v1/Room/room_init", ROOM_INIT),
+    ]);
+    let client = Client::builder()
+      .host(Host::Api, server.base_url())
+      .host(Host::ApiLive, server.base_url())
+      .build();
+    let ticket = init_bili_ticket(&client).unwrap();
+    assert!(ticket.expires_at > now_millis() / 1000 + 259000);
+    assert_eq!(Some(ticket.clone()), client.bili_ticket());
+    assert_eq!(
+      Some("ea1db124af3c7062474693fa704f4ff8".to_string()),
+      client.wbi_keys().get()
+    );
+
+    let request = &server.received()[0];
+    assert_eq!("POST", request.method);
+    let url = url::Url::parse(&format!("http://localhost{}", request.url)).unwrap();
+    let params: Vec<_> = url.query_pairs().into_owned().collect();
+    let ts: i64 = params[2].1.parse().unwrap();
+    assert_eq!(
+      vec![
+        ("key_id".to_string(), "ec02".to_string()),
+        ("hexsign".to_string(), bili_ticket_hexsign(ts)),
+        ("context[ts]".to_string(), ts.to_string()),
+        ("csrf".to_string(), String::new()),
+      ],
+      params
+    );
+
+    get_room_init_info(&client, 5050).unwrap();
+    assert_eq!(
+      Some(format!(
+        "bili_ticket=eyJhbGciOiJIUzI1NiJ9.ticket; bili_ticket_expires={}",
+        ticket.expires_at
+      )),
+      server.received()[1].header("cookie").map(str::to_string)
+    );
+  }
+
+  #[test]
+  fn test_auto_bili_ticket() {
+    let fresh = fresh_ticket();
+    let server = MockServer::start(&[
+      ("/bapis/bilibili.api.ticket.v1.Ticket/GenWebTicket", &fresh),
+      ("/room/v1/Room/room_init", ROOM_INIT),
+    ]);
+    let client = Client::builder()
+      .host(Host::Api, server.base_url())
+      .host(Host::ApiLive, server.base_url())
+      .auto_bili_ticket()
+      .build();
+    get_room_init_info(&client, 5050).unwrap();
+    get_room_init_info(&client, 5050).unwrap();
+
+    let received = server.received();
+    assert_eq!(3, received.len());
+    assert_eq!(None, received[0].header("cookie"));
+    for request in &received[1..] {
+      assert!(request
+        .header("cookie")
+        .unwrap()
+        .starts_with("bili_ticket=eyJhbGciOiJIUzI1NiJ9.ticket; "));
+    }
+  }
+
+  #[test]
+  fn test_init_bili_ticket_malformed_nav() {
+    let ticket = fresh_ticket().replace(
+      "https://i0.hdslb.com/bfs/wbi/4932caff0ff746eab6f01bf08b70ac45.png",
+      "https://i0.hdslb.com/bfs/wbi/中.png",
+    );
+    let server = MockServer::start(&[
+      ("/bapis/bilibili.api.ticket.v1.Ticket/GenWebTicket", &ticket),
+      ("/room/v1/Room/room_init", ROOM_INIT),
+    ]);
+    let client = Client::builder()
+      .host(Host::Api, server.base_url())
+      .host(Host::ApiLive, server.base_url())
+      .build();
+    assert!(matches!(
+      init_bili_ticket(&client),
+      Err(crate::Error::MalformedEnvelope(_))
+    ));
+    assert_eq!(None, client.bili_ticket());
+    assert_eq!(None, client.wbi_keys().get());
+
+    // Fetched automatically, it fails quietly instead of failing the request
+    let client = Client::builder()
+      .host(Host::Api, server.base_url())
+      .host(Host::ApiLive, server.base_url())
+      .auto_bili_ticket()
+      .build();
+    get_room_init_info(&client, 5050).unwrap();
+    assert_eq!(None, client.bili_ticket());
+  }
+
+  #[test]
+  fn test_auto_bili_ticket_skips_expired() {
+    // Issued long ago, so it is not attached and not fetched again right away
+    let server = MockServer::start(&[
+      ("/bapis/bilibili.api.ticket.v1.Ticket/GenWebTicket", TICKET),
+      ("/room/v1/Room/room_init", ROOM_INIT),
+    ]);
+    let client = Client::builder()
+      .host(Host::Api, server.base_url())
+      .host(Host::ApiLive, server.base_url())
+      .auto_bili_ticket()
+      .build();
+    get_room_init_info(&client, 5050).unwrap();
+    get_room_init_info(&client, 5050).unwrap();
+    assert!(client.bili_ticket().is_some());
+    let received = server.received();
+    assert_eq!(3, received.len());
+    assert_eq!(None, received[1].header("cookie"));
+    assert_eq!(None, received[2].header("cookie"));
+  }
+
+  #[cfg(feature = "async")]
+  #[tokio::test]
+  async fn test_auto_bili_ticket_async() {
+    use crate::apis::live::info::get_room_init_info_async;
+
+    let fresh = fresh_ticket();
+    let server = MockServer::start(&[
+      ("/bapis/bilibili.api.ticket.v1.Ticket/GenWebTicket", &fresh),
+      ("/room/v1/Room/room_init", ROOM_INIT),
+    ]);
+    let client = AsyncClient::builder()
+      .host(Host::Api, server.base_url())
+      .host(Host::ApiLive, server.base_url())
+      .auto_bili_ticket()
+      .build_async();
+    let handle = tokio::spawn(async move {
+      get_room_init_info_async(&client, 5050).await.unwrap();
+      client
+    });
+    let client = handle.await.unwrap();
+    assert!(client.bili_ticket().is_some());
+    assert_eq!(2, server.received().len());
+  }
+}
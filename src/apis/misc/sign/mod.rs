@@ -1 +1,3 @@
+pub mod app;
+pub mod bili_ticket;
 pub mod wbi;
@@ -1,9 +1,22 @@
-use std::{collections::BTreeMap, path::Path};
+//! https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/misc/sign/wbi.md
+//!
+//! WBI签名: 在参数中加入`wts`, 去掉值中的`!'()*`, 按key排序并URL编码后拼接mixin key,
+//! 取MD5作为`w_rid`. mixin key由导航栏API下发的`wbi_img`打乱得到, 由Client缓存.
 
+use std::{
+  collections::BTreeMap,
+  path::Path,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+#[cfg(feature = "async")]
+use crate::{apis::login::login_info::fetch_nav_info_async, AsyncClient};
 use crate::{
   apis::login::login_info::{fetch_nav_info, WbiImg},
   credential::Credential,
-  Client,
+  Client, Error,
 };
 
 #[derive(Debug)]
@@ -24,52 +37,204 @@ impl Wbi {
 
 /// Perform an API call to the https://api.bilibili.com/x/web-interface/nav endpoint and return both a [WbiImg]
 /// for caching and the computed [Wbi] for the current request parameters.
-pub fn do_wbi_signature(
+#[deprecated(
+  note = "fetches the nav info on every call, use `wbi_sign` with the key cached on the Client"
+)]
+pub fn do_wbi_signature<Q: Serialize + ?Sized>(
   client: &Client,
   credential: &Credential,
-  original_params: &BTreeMap<&str, &str>,
+  original_params: &Q,
 ) -> crate::Result<(Wbi, WbiImg)> {
   let nav_info = fetch_nav_info(client, credential)?;
   let wbi_img = nav_info.data.wbi_img;
 
-  let wbi = do_wbi_signature_with_wbi_img(&wbi_img, original_params);
+  let wbi = do_wbi_signature_with_wbi_img(&wbi_img, original_params)?;
+  Ok((wbi, wbi_img))
+}
+
+/// Async version of [do_wbi_signature]
+#[cfg(feature = "async")]
+#[deprecated(
+  note = "fetches the nav info on every call, use `wbi_sign_async` with the key cached on the AsyncClient"
+)]
+pub async fn do_wbi_signature_async<Q: Serialize + ?Sized>(
+  client: &AsyncClient,
+  credential: &Credential,
+  original_params: &Q,
+) -> crate::Result<(Wbi, WbiImg)> {
+  let nav_info = fetch_nav_info_async(client, credential).await?;
+  let wbi_img = nav_info.data.wbi_img;
+
+  let wbi = do_wbi_signature_with_wbi_img(&wbi_img, original_params)?;
   Ok((wbi, wbi_img))
 }
 
 /// Perform wbi signature for a given API request with a cached
 /// [WbiImg] object
-pub fn do_wbi_signature_with_wbi_img(
+pub fn do_wbi_signature_with_wbi_img<Q: Serialize + ?Sized>(
   wbi_img: &WbiImg,
-  original_params: &BTreeMap<&str, &str>,
-) -> Wbi {
-  let raw_wbi_key = extract_raw_wbi_key(wbi_img);
-  let mixin_key = gen_mixin_key(&raw_wbi_key);
+  original_params: &Q,
+) -> crate::Result<Wbi> {
+  let mixin_key = mixin_key_from_wbi_img(wbi_img)?;
 
-  compute_wbi_signature(original_params, &mixin_key)
+  Ok(compute_wbi_signature(
+    query_pairs(original_params)?,
+    &mixin_key,
+    now_secs(),
+  ))
+}
+
+/// Signs `query` at the current time with the mixin key cached on the [Client], see
+/// [wbi_sign_with_ts]. The key is fetched from the nav endpoint only when it is missing or
+/// has expired.
+pub fn wbi_sign<Q: Serialize + ?Sized>(
+  client: &Client,
+  credential: &Credential,
+  query: &Q,
+) -> crate::Result<Vec<(String, String)>> {
+  let mixin_key = cached_mixin_key(client, credential)?;
+  wbi_sign_with_ts(query, &mixin_key, now_secs())
+}
+
+/// Async version of [wbi_sign]
+#[cfg(feature = "async")]
+pub async fn wbi_sign_async<Q: Serialize + ?Sized>(
+  client: &AsyncClient,
+  credential: &Credential,
+  query: &Q,
+) -> crate::Result<Vec<(String, String)>> {
+  let mixin_key = cached_mixin_key_async(client, credential).await?;
+  wbi_sign_with_ts(query, &mixin_key, now_secs())
+}
+
+/// Returns the parameters of `query` with `wts` set to `wts`, sorted by key and followed by
+/// `w_rid`, ready to be sent as the query string of a WBI-signed request. Values are
+/// stripped of `!'()*` as they are signed without them.
+///
+/// `query` may be anything `reqwest` accepts as a query, e.g., a struct deriving
+/// [Serialize] or a slice of pairs.
+pub fn wbi_sign_with_ts<Q: Serialize + ?Sized>(
+  query: &Q,
+  mixin_key: &str,
+  wts: u64,
+) -> crate::Result<Vec<(String, String)>> {
+  let mut params: BTreeMap<String, String> = query_pairs(query)?
+    .into_iter()
+    .map(|(k, v)| (k, strip_value(&v)))
+    .collect();
+  let wbi = compute_wbi_signature(params.clone(), mixin_key, wts);
+  params.insert("wts".to_string(), wbi.wts);
+  let mut signed: Vec<_> = params.into_iter().collect();
+  signed.push(("w_rid".to_string(), wbi.w_rid));
+  Ok(signed)
+}
+
+/// The mixin key cached on the [Client], fetched from the nav endpoint only when it is
+/// missing or has expired
+pub(crate) fn cached_mixin_key(client: &Client, credential: &Credential) -> crate::Result<String> {
+  match client.wbi_keys().get() {
+    Some(mixin_key) => Ok(mixin_key),
+    None => {
+      let nav_info = fetch_nav_info(client, credential)?;
+      let mixin_key = mixin_key_from_wbi_img(&nav_info.data.wbi_img)?;
+      client.wbi_keys().set(mixin_key.clone());
+      Ok(mixin_key)
+    }
+  }
+}
+
+/// Async version of [cached_mixin_key]
+#[cfg(feature = "async")]
+pub(crate) async fn cached_mixin_key_async(
+  client: &AsyncClient,
+  credential: &Credential,
+) -> crate::Result<String> {
+  match client.wbi_keys().get() {
+    Some(mixin_key) => Ok(mixin_key),
+    None => {
+      let nav_info = fetch_nav_info_async(client, credential).await?;
+      let mixin_key = mixin_key_from_wbi_img(&nav_info.data.wbi_img)?;
+      client.wbi_keys().set(mixin_key.clone());
+      Ok(mixin_key)
+    }
+  }
+}
+
+/// `dm_img_*`参数, 用户空间相关API会检查. 其值为浏览器的WebGL信息, 这里使用固定值.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct DmImg {
+  pub dm_img_list: String,
+  pub dm_img_str: String,
+  pub dm_cover_img_str: String,
+  pub dm_img_inter: String,
+}
+
+impl Default for DmImg {
+  fn default() -> Self {
+    Self {
+      dm_img_list: "[]".to_string(),
+      // base64 of "WebGL 1.0 (OpenGL ES 2.0 Chromium)" without the last two characters
+      dm_img_str: "V2ViR0wgMS4wIChPcGVuR0wgRVMgMi4wIENocm9taXVtKQ".to_string(),
+      // base64 of the WebGL renderer and vendor without the last two characters
+      dm_cover_img_str: "QU5HTEUgKEludGVsLCBJbnRlbChSKSBVSEQgR3JhcGhpY3MgNjMwICgweDAwMDAzRTlCKSBEaXJlY3QzRDExIHZzXzVfMCBwc181XzAsIEQzRDExKUdvb2dsZSBJbmMuIChJbnRlbC".to_string(),
+      dm_img_inter: r#"{"ds":[],"wh":[0,0,0],"of":[0,0,0]}"#.to_string(),
+    }
+  }
 }
 
 /// Extract img_key and sub_key from img_url and sub_url.
 /// E.g.,
 /// https://i0.hdslb.com/bfs/wbi/7cd084941338484aae1ad9425b84077c.png -> 7cd084941338484aae1ad9425b84077c
-///
-/// Should only be used on urls that we know satisfy the invariant
-fn wbi_extract_file_stem(url: &str) -> Vec<u8> {
-  let url = url::Url::parse(url).unwrap();
+fn wbi_extract_file_stem(url: &str) -> crate::Result<Vec<u8>> {
+  let malformed = || Error::MalformedEnvelope(format!("invalid WBI key URL: {url:?}"));
+  let url = url::Url::parse(url).map_err(|_| malformed())?;
   let path = Path::new(url.path());
-  path.file_stem().unwrap().as_encoded_bytes().to_vec()
+  let stem = path.file_stem().ok_or_else(malformed)?;
+  Ok(stem.as_encoded_bytes().to_vec())
+}
+
+/// Flattens `query` into (key, value) pairs the way `reqwest` would send it
+fn query_pairs<Q: Serialize + ?Sized>(query: &Q) -> crate::Result<BTreeMap<String, String>> {
+  let encoded = serde_urlencoded::to_string(query).map_err(|e| Error::Query(e.to_string()))?;
+  Ok(
+    url::form_urlencoded::parse(encoded.as_bytes())
+      .into_owned()
+      .collect(),
+  )
+}
+
+/// Removes the characters the web frontend filters out of values before signing
+fn strip_value(value: &str) -> String {
+  value
+    .chars()
+    .filter(|c| !matches!(c, '!' | '\'' | '(' | ')' | '*'))
+    .collect()
 }
 
-fn wbi_encode_url_string(params: &BTreeMap<&str, &str>, mixin_key: &str) -> String {
+pub(crate) fn now_secs() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .expect("SystemTime before UNIX EPOCH")
+    .as_secs()
+}
+
+fn wbi_encode_url_string<K, V>(params: &BTreeMap<K, V>, mixin_key: &str) -> String
+where
+  K: AsRef<str>,
+  V: AsRef<str>,
+{
   let mut buffer = String::new();
 
   // Append query parameters
   let mut first = true;
-  for (&k, &v) in params {
+  for (k, v) in params {
+    let k = urlencoding::encode(k.as_ref());
+    let v = strip_value(v.as_ref());
     if first {
-      buffer += &format!("{}={}", k, urlencoding::encode(v));
+      buffer += &format!("{}={}", k, urlencoding::encode(&v));
       first = false;
     } else {
-      buffer += &format!("&{}={}", k, urlencoding::encode(v));
+      buffer += &format!("&{}={}", k, urlencoding::encode(&v));
     }
   }
 
@@ -79,42 +244,48 @@ fn wbi_encode_url_string(params: &BTreeMap<&str, &str>, mixin_key: &str) -> Stri
   buffer
 }
 
-fn extract_raw_wbi_key(webimg: &WbiImg) -> Vec<u8> {
-  let mut img_key = wbi_extract_file_stem(&webimg.img_url);
-  let sub_key = wbi_extract_file_stem(&webimg.sub_url);
+/// The mixin key of `wbi_img`, or [Error::MalformedEnvelope] if its URLs do not carry
+/// 64 bytes of keys
+pub(crate) fn mixin_key_from_wbi_img(wbi_img: &WbiImg) -> crate::Result<String> {
+  let raw_wbi_key = extract_raw_wbi_key(wbi_img)?;
+  gen_mixin_key(raw_wbi_key)
+}
+
+fn extract_raw_wbi_key(webimg: &WbiImg) -> crate::Result<Vec<u8>> {
+  let mut img_key = wbi_extract_file_stem(&webimg.img_url)?;
+  let sub_key = wbi_extract_file_stem(&webimg.sub_url)?;
   img_key.extend(sub_key);
-  img_key
+  Ok(img_key)
 }
 
-fn gen_mixin_key(raw_wbi_key: impl AsRef<[u8]>) -> String {
+fn gen_mixin_key(raw_wbi_key: impl AsRef<[u8]>) -> crate::Result<String> {
   const MIXIN_KEY_ENC_TAB: [u8; 64] = [
     46, 47, 18, 2, 53, 8, 23, 32, 15, 50, 10, 31, 58, 3, 45, 35, 27, 43, 5, 49, 33, 9, 42, 19, 29,
     28, 14, 39, 12, 38, 41, 13, 37, 48, 7, 16, 24, 55, 40, 61, 26, 17, 0, 1, 60, 51, 30, 4, 22, 25,
     54, 21, 56, 59, 6, 63, 57, 62, 11, 36, 20, 34, 44, 52,
   ];
   let raw_wbi_key = raw_wbi_key.as_ref();
-  let mut mixin_key = {
-    let binding = MIXIN_KEY_ENC_TAB
-      .iter()
-      // 此步操作即遍历 MIXIN_KEY_ENC_TAB，取出 raw_wbi_key 中对应位置的字符
-      .map(|n| raw_wbi_key[*n as usize])
-      // 并收集进数组内
-      .collect::<Vec<u8>>();
-    unsafe { String::from_utf8_unchecked(binding) }
-  };
-  let _ = mixin_key.split_off(32); // 截取前 32 位字符
-  mixin_key
+  if raw_wbi_key.len() < MIXIN_KEY_ENC_TAB.len() {
+    return Err(Error::MalformedEnvelope(format!(
+      "WBI keys of {} bytes, expected {}",
+      raw_wbi_key.len(),
+      MIXIN_KEY_ENC_TAB.len()
+    )));
+  }
+  let binding = MIXIN_KEY_ENC_TAB
+    .iter()
+    // 此步操作即遍历 MIXIN_KEY_ENC_TAB，取出 raw_wbi_key 中对应位置的字符
+    .map(|n| raw_wbi_key[*n as usize])
+    // 截取前 32 位字符
+    .take(32)
+    .collect::<Vec<u8>>();
+  String::from_utf8(binding)
+    .map_err(|_| Error::MalformedEnvelope("WBI keys are not ASCII".to_string()))
 }
 
-fn compute_wbi_signature(original_params: &BTreeMap<&str, &str>, mixin_key: &str) -> Wbi {
-  use std::time::SystemTime;
-  let mut params = original_params.clone();
-  let wts = SystemTime::now()
-    .duration_since(SystemTime::UNIX_EPOCH)
-    .expect("SystemTime before UNIX EPOCH")
-    .as_secs();
+fn compute_wbi_signature(mut params: BTreeMap<String, String>, mixin_key: &str, wts: u64) -> Wbi {
   let wts = wts.to_string();
-  params.insert("wts", &wts);
+  params.insert("wts".to_string(), wts.clone());
 
   let encoded_param_str = wbi_encode_url_string(&params, mixin_key);
   let w_rid = format!("{:02x}", md5::compute(&encoded_param_str));
@@ -124,11 +295,58 @@ fn compute_wbi_signature(original_params: &BTreeMap<&str, &str>, mixin_key: &str
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::{
+    client::Host, credential::extract_credential::get_fake_credential,
+    utils::mock_server::MockServer,
+  };
+
   #[test]
   fn test_mixin_key() {
     let raw_wbi_key = "7cd084941338484aae1ad9425b84077c4932caff0ff746eab6f01bf08b70ac45";
-    let mixin_key = gen_mixin_key(raw_wbi_key);
+    let mixin_key = gen_mixin_key(raw_wbi_key).unwrap();
     assert_eq!("ea1db124af3c7062474693fa704f4ff8", &mixin_key);
+
+    assert!(matches!(
+      gen_mixin_key("7cd084941338484a"),
+      Err(Error::MalformedEnvelope(_))
+    ));
+    let non_ascii = "中".repeat(22);
+    assert!(matches!(
+      gen_mixin_key(non_ascii),
+      Err(Error::MalformedEnvelope(_))
+    ));
+  }
+
+  #[test]
+  fn test_cached_mixin_key_malformed_nav() {
+    const NAV: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/nav.json"));
+    let nav = NAV.replace(
+      "https://i0.hdslb.com/bfs/wbi/7cd084941338484aae1ad9425b84077c.png",
+      "",
+    );
+    let server = MockServer::start(&[("/x/web-interface/nav", &nav)]);
+    let client = Client::builder().host(Host::Api, server.base_url()).build();
+
+    let result = cached_mixin_key(&client, &get_fake_credential());
+    assert!(matches!(result, Err(Error::MalformedEnvelope(_))));
+    assert_eq!(None, client.wbi_keys().get());
+  }
+
+  #[test]
+  fn test_wbi_sign_cached() {
+    const NAV: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/nav.json"));
+    let server = MockServer::start(&[("/x/web-interface/nav", NAV)]);
+    let client = Client::builder().host(Host::Api, server.base_url()).build();
+    let credential = get_fake_credential();
+
+    for _ in 0..2 {
+      let signed = wbi_sign(&client, &credential, &[("foo", "114")]).unwrap();
+      let wts: u64 = signed[1].1.parse().unwrap();
+      let expected =
+        wbi_sign_with_ts(&[("foo", "114")], "ea1db124af3c7062474693fa704f4ff8", wts).unwrap();
+      assert_eq!(expected, signed);
+    }
+    assert_eq!(1, server.received().len());
   }
 
   #[test]
@@ -185,4 +403,100 @@ mod tests {
 
     assert_eq!("8f6f2b5b3d485fe1886cec6a0be8c5d4", w_rid);
   }
+
+  #[test]
+  fn test_wbi_sign_with_ts() {
+    let mixin_key = "ea1db124af3c7062474693fa704f4ff8";
+    let signed = wbi_sign_with_ts(
+      &[("foo", "114"), ("bar", "514"), ("zab", "1919810")],
+      mixin_key,
+      1702204169,
+    )
+    .unwrap();
+    assert_eq!(
+      (
+        "w_rid".to_string(),
+        "8f6f2b5b3d485fe1886cec6a0be8c5d4".to_string()
+      ),
+      signed[4]
+    );
+
+    #[derive(Serialize)]
+    struct Query {
+      foo: &'static str,
+      bar: i64,
+      zab: String,
+      #[serde(skip_serializing_if = "Option::is_none")]
+      baz: Option<i64>,
+    }
+    let query = Query {
+      foo: "one one four(!)",
+      bar: 514,
+      zab: "'1919810*'".to_string(),
+      baz: None,
+    };
+    let signed = wbi_sign_with_ts(&query, mixin_key, 1702204169).unwrap();
+    let pairs: Vec<_> = signed
+      .iter()
+      .map(|(k, v)| (k.as_str(), v.as_str()))
+      .collect();
+    assert_eq!(
+      vec![
+        ("bar", "514"),
+        ("foo", "one one four"),
+        ("wts", "1702204169"),
+        ("zab", "1919810"),
+        ("w_rid", "fbea23e283f8d031ce4af2fe5169f149"),
+      ],
+      pairs
+    );
+  }
+
+  #[test]
+  fn test_wbi_sign_strips_before_signing() {
+    let mixin_key = "ea1db124af3c7062474693fa704f4ff8";
+    let mut params = BTreeMap::new();
+    params.insert("foo", "114!");
+    params.insert("bar", "(514)");
+    params.insert("zab", "1919810");
+    params.insert("wts", "1702204169");
+    assert_eq!(
+      "bar=514&foo=114&wts=1702204169&zab=1919810ea1db124af3c7062474693fa704f4ff8",
+      wbi_encode_url_string(&params, mixin_key)
+    );
+  }
+
+  #[test]
+  fn test_wbi_sign_dm_img() {
+    #[derive(Serialize)]
+    struct Query {
+      mid: i64,
+      #[serde(flatten)]
+      dm_img: DmImg,
+    }
+    let query = Query {
+      mid: 2,
+      dm_img: DmImg::default(),
+    };
+    let signed = wbi_sign_with_ts(&query, "ea1db124af3c7062474693fa704f4ff8", 1702204169).unwrap();
+    let keys: Vec<_> = signed.iter().map(|(k, _)| k.as_str()).collect();
+    assert_eq!(
+      vec![
+        "dm_cover_img_str",
+        "dm_img_inter",
+        "dm_img_list",
+        "dm_img_str",
+        "mid",
+        "wts",
+        "w_rid"
+      ],
+      keys
+    );
+    assert_eq!(r#"{"ds":[],"wh":[0,0,0],"of":[0,0,0]}"#, signed[1].1);
+
+    assert!(matches!(
+      wbi_sign_with_ts(&[("nested", [1, 2])], "", 1702204169),
+      Err(Error::Query(_))
+    ));
+  }
 }
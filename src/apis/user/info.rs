@@ -1,11 +1,13 @@
 //! https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/user/info.md
 
-use std::collections::BTreeMap;
-
 use serde::{Deserialize, Serialize};
 
+#[cfg(feature = "async")]
+use crate::AsyncClient;
 use crate::{
-  apis::misc::sign::wbi::do_wbi_signature, credential::Credential, utils::handle_api_response,
+  apis::misc::sign::wbi::DmImg,
+  client::{AuthKind, Endpoint, Host, Params, RateLimitGroup, Signing},
+  credential::{AppCredential, Credential},
   Client,
 };
 
@@ -234,30 +236,66 @@ pub struct Contract {
   pub is_follow_display: bool,
 }
 
+/// 用户空间详细信息, 需要WBI签名
+#[derive(Debug, Clone, Serialize)]
+pub struct GetUserInfo {
+  pub mid: i64,
+  #[serde(flatten)]
+  pub dm_img: DmImg,
+}
+
+impl GetUserInfo {
+  pub fn new(mid: i64) -> Self {
+    Self {
+      mid,
+      dm_img: DmImg::default(),
+    }
+  }
+}
+
+impl Endpoint for GetUserInfo {
+  type Response = UserInfoResponse;
+
+  fn host(&self) -> Host {
+    Host::Api
+  }
+
+  fn path(&self) -> String {
+    "/x/space/wbi/acc/info".to_string()
+  }
+
+  fn query(&self) -> crate::Result<Params> {
+    Params::from_serialize(self)
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+
+  fn signing(&self) -> Signing {
+    Signing::Wbi
+  }
+
+  fn rate_limit_group(&self) -> RateLimitGroup {
+    RateLimitGroup::Space
+  }
+}
+
 pub fn get_user_info(
   client: &Client,
   credential: &Credential,
   mid: i64,
 ) -> crate::Result<UserInfoResponse> {
-  const API_URL: &str = "https://api.bilibili.com/x/space/wbi/acc/info";
-
-  let mut params = BTreeMap::new();
-  let mid = mid.to_string();
-  params.insert("mid", &*mid);
-
-  // Perform wbi signature
-  let (wbi, _) = do_wbi_signature(client, credential, &params)?;
-  params.insert("wts", wbi.wts());
-  params.insert("w_rid", wbi.w_rid());
-
-  let request = client
-    .get(API_URL)
-    .header("Cookie", format!("SESSDATA={}", credential.sessdata))
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .query(&params)
-    .build()?;
+  client.call(&GetUserInfo::new(mid), credential)
+}
 
-  handle_api_response(client.execute(request)?)
+#[cfg(feature = "async")]
+pub async fn get_user_info_async(
+  client: &AsyncClient,
+  credential: &Credential,
+  mid: i64,
+) -> crate::Result<UserInfoResponse> {
+  client.call(&GetUserInfo::new(mid), credential).await
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -340,29 +378,131 @@ pub struct LevelExp {
   pub next_exp: i32,
 }
 
+/// 登录用户的空间信息
+#[derive(Debug, Clone, Copy)]
+pub struct GetMyInfo;
+
+impl Endpoint for GetMyInfo {
+  type Response = MyInfoResponse;
+
+  fn host(&self) -> Host {
+    Host::Api
+  }
+
+  fn path(&self) -> String {
+    "/x/space/myinfo".to_string()
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::Web
+  }
+
+  fn rate_limit_group(&self) -> RateLimitGroup {
+    RateLimitGroup::Space
+  }
+}
+
 pub fn get_my_info(client: &Client, credential: &Credential) -> crate::Result<MyInfoResponse> {
-  const API_URL: &str = "https://api.bilibili.com/x/space/myinfo";
+  client.call(&GetMyInfo, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_my_info_async(
+  client: &AsyncClient,
+  credential: &Credential,
+) -> crate::Result<MyInfoResponse> {
+  client.call(&GetMyInfo, credential).await
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AppMyInfoResponse {
+  pub data: AppMyInfoData,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct AppMyInfoData {
+  pub mid: i64,
+  pub name: String,
+  pub sign: String,
+  pub coins: f64,
+  pub birthday: String,
+  pub face: String,
+  pub sex: i32,
+  pub level: i32,
+  pub rank: i32,
+  pub silence: i32,
+}
+
+/// APP端的登录用户信息, 使用access_key鉴权
+#[derive(Debug, Clone, Copy)]
+pub struct GetMyInfoApp;
+
+impl Endpoint for GetMyInfoApp {
+  type Response = AppMyInfoResponse;
 
-  let request = client
-    .get(API_URL)
-    .header("Cookie", format!("SESSDATA={}", credential.sessdata))
-    .header("User-Agent", crate::apis::USER_AGENT)
-    .build()?;
+  fn host(&self) -> Host {
+    Host::App
+  }
+
+  fn path(&self) -> String {
+    "/x/v2/account/myinfo".to_string()
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::App
+  }
+
+  fn signing(&self) -> Signing {
+    Signing::App(None)
+  }
 
-  handle_api_response(client.execute(request)?)
+  fn rate_limit_group(&self) -> RateLimitGroup {
+    RateLimitGroup::Space
+  }
+}
+
+pub fn get_my_info_app(
+  client: &Client,
+  credential: &AppCredential,
+) -> crate::Result<AppMyInfoResponse> {
+  client.call(&GetMyInfoApp, credential)
+}
+
+#[cfg(feature = "async")]
+pub async fn get_my_info_app_async(
+  client: &AsyncClient,
+  credential: &AppCredential,
+) -> crate::Result<AppMyInfoResponse> {
+  client.call(&GetMyInfoApp, credential).await
 }
 
 #[cfg(test)]
 mod tests {
-  use super::{get_my_info, get_user_info};
+  use super::{get_my_info, get_my_info_app, get_user_info};
   use crate::{
-    credential::extract_credential::{get_credential_for_test_or_abort, get_fake_credential},
-    utils::assert_error_code,
+    apis::misc::sign::app::AppKey,
+    client::Host,
+    credential::{
+      extract_credential::{get_credential_for_test_or_abort, get_fake_credential},
+      AppCredential,
+    },
+    utils::{assert_error_code, cassette, mock_server::MockServer},
     Client,
   };
 
   #[test]
   fn test_get_my_info_success() {
+    let agent = cassette::client("my_info");
+    let cred = cassette::credential();
+
+    let result = get_my_info(&agent, &cred);
+
+    assert!(result.is_ok())
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_get_my_info_success_live() {
     let agent = Client::new();
     let cred = get_credential_for_test_or_abort();
 
@@ -373,14 +513,113 @@ mod tests {
 
   #[test]
   fn test_get_my_info_unlogged_in() {
+    let agent = cassette::client("my_info_unlogged_in");
+    let cred = get_fake_credential();
+    let result = get_my_info(&agent, &cred);
+    assert_error_code(result, -400);
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_get_my_info_unlogged_in_live() {
     let agent = Client::new();
     let cred = get_fake_credential();
     let result = get_my_info(&agent, &cred);
     assert_error_code(result, -400);
   }
 
+  #[test]
+  fn test_get_my_info_app_is_app_signed() {
+    let server = MockServer::start(&[(
+      "/x/v2/account/myinfo",
+      r#"{"code":0,"message":"0","ttl":1,"data":{"mid":12734361,"name":"test","sign":"",
+        "coins":12.5,"birthday":"2000-01-01","face":"","sex":0,"level":6,"rank":10000,"silence":0}}"#,
+    )]);
+    let agent = Client::builder()
+      .host(Host::App, server.base_url())
+      .app_key(AppKey::tv())
+      .build();
+    let cred = AppCredential::new("token".to_string());
+
+    assert_eq!(12734361, get_my_info_app(&agent, &cred).unwrap().data.mid);
+    let url = &server.received()[0].url;
+    assert!(url.starts_with("/x/v2/account/myinfo?access_key=token&appkey=4409e2ce8ffd12b8&ts="));
+    assert!(url.contains("&sign="));
+  }
+
+  #[test]
+  fn test_get_user_info_caches_wbi_keys() {
+    const NAV: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/nav.json"));
+    const USER_INFO: &str = include_str!(concat!(
+      env!("CARGO_MANIFEST_DIR"),
+      "/fixtures/user_info.json"
+    ));
+    let server = MockServer::start(&[
+      ("/x/web-interface/nav", NAV),
+      ("/x/space/wbi/acc/info", USER_INFO),
+    ]);
+    let agent = Client::builder().host(Host::Api, server.base_url()).build();
+    let cred = get_fake_credential();
+
+    assert_eq!(
+      12734361,
+      get_user_info(&agent, &cred, 12734361).unwrap().data.mid
+    );
+    assert!(get_user_info(&agent, &cred, 12734361).is_ok());
+
+    // The nav endpoint is only hit once for both requests
+    let paths: Vec<_> = server
+      .received()
+      .into_iter()
+      .map(|r| r.url.split('?').next().unwrap().to_string())
+      .collect();
+    assert_eq!(
+      vec![
+        "/x/web-interface/nav",
+        "/x/space/wbi/acc/info",
+        "/x/space/wbi/acc/info"
+      ],
+      paths
+    );
+    let last = server.received().pop().unwrap();
+    assert!(last.url.contains("mid=12734361"));
+    assert!(last.url.contains("w_rid="));
+    assert!(last.url.contains("wts="));
+    assert!(last.url.contains("dm_img_list=%5B%5D"));
+    assert!(last.url.contains("dm_img_inter="));
+  }
+
+  #[test]
+  fn test_get_user_info_refreshes_wbi_keys_on_risk_control() {
+    const NAV: &str = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/nav.json"));
+    let server = MockServer::start(&[
+      ("/x/web-interface/nav", NAV),
+      (
+        "/x/space/wbi/acc/info",
+        r#"{"code":-352,"message":"风控校验失败","ttl":1}"#,
+      ),
+    ]);
+    let agent = Client::builder().host(Host::Api, server.base_url()).build();
+    let cred = get_fake_credential();
+
+    assert_error_code(get_user_info(&agent, &cred, 12734361), -352);
+    // nav -> info (-352) -> nav -> info (-352)
+    assert_eq!(4, server.received().len());
+  }
+
   #[test]
   fn test_get_user_info() {
+    let agent = cassette::client("user_info");
+    let cred = cassette::credential();
+    let mid = 12734361i64;
+    let res = get_user_info(&agent, &cred, mid);
+
+    assert!(res.is_ok());
+  }
+
+  #[test]
+  #[ignore = "hits the real API"]
+  fn test_get_user_info_live() {
     let agent = Client::new();
     let cred = get_credential_for_test_or_abort();
     let mid = 12734361i64;
@@ -388,4 +627,32 @@ mod tests {
 
     assert!(res.is_ok());
   }
+
+  #[cfg(feature = "async")]
+  #[tokio::test]
+  async fn test_get_user_info_async() {
+    use super::get_user_info_async;
+
+    let agent = cassette::async_client("user_info_async");
+    let cred = cassette::credential();
+    let mid = 12734361i64;
+    let res = get_user_info_async(&agent, &cred, mid).await;
+
+    assert!(res.is_ok());
+  }
+
+  #[cfg(feature = "async")]
+  #[tokio::test]
+  #[ignore = "hits the real API"]
+  async fn test_get_user_info_async_live() {
+    use super::get_user_info_async;
+    use crate::AsyncClient;
+
+    let agent = AsyncClient::new();
+    let cred = get_credential_for_test_or_abort();
+    let mid = 12734361i64;
+    let res = get_user_info_async(&agent, &cred, mid).await;
+
+    assert!(res.is_ok());
+  }
 }
@@ -0,0 +1,326 @@
+//! Asynchronous counterpart of [Client](crate::Client), built on top of the
+//! non-blocking `reqwest` client. Only available with the `async` feature.
+
+use std::{future::Future, pin::Pin, sync::Arc, time::Duration};
+
+use super::{
+  bili_ticket::BiliTicketCache,
+  builder::ClientBuilder,
+  credential_refresh::RefreshedCredentials,
+  device::DeviceCookies,
+  endpoint::{build_request, check_auth, Auth, Endpoint, Signing},
+  host::Hosts,
+  middleware::Middlewares,
+  rate_limiting::{RateLimitGroup, RateLimiter},
+  record::{CassetteMode, RecordingTransport, ReplayTransport},
+  retry::RetryPolicy,
+  transport::{AsyncReqwestTransport, HttpRequest, SharedAsyncTransport, TransportFuture},
+  wbi_keys::WbiKeyCache,
+};
+use crate::{
+  apis::misc::{
+    buvid::Buvid,
+    sign::{app::AppKey, bili_ticket::BiliTicket, wbi::cached_mixin_key_async},
+  },
+  credential::Credential,
+  utils::now_millis,
+  Error,
+};
+
+#[derive(Clone)]
+pub struct AsyncClient {
+  inner: AsyncClientInner,
+  hosts: Hosts,
+  retry_policy: RetryPolicy,
+  pub(crate) live_msg_retry_policy: RetryPolicy,
+  app_key: AppKey,
+}
+
+impl Default for AsyncClient {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl AsyncClient {
+  pub fn new() -> Self {
+    Self::builder().build_async()
+  }
+
+  pub fn builder() -> ClientBuilder {
+    ClientBuilder::new()
+  }
+
+  /// 自定义直播弹幕API限流, 参见[ClientBuilder::group_rate_limit]
+  pub fn with_rate_limit(limit: Duration) -> Self {
+    Self::builder().rate_limit(limit).build_async()
+  }
+
+  pub fn hosts(&self) -> &Hosts {
+    &self.hosts
+  }
+
+  pub fn retry_policy(&self) -> &RetryPolicy {
+    &self.retry_policy
+  }
+
+  /// APP端API签名所用的appkey
+  pub fn app_key(&self) -> &AppKey {
+    &self.app_key
+  }
+
+  /// The latest credential in place of `credential`, see [Client::credential](crate::Client::credential)
+  pub fn credential(&self, credential: &Credential) -> Credential {
+    self.inner.credentials.latest(credential)
+  }
+
+  /// 设备指纹Cookie, 参见[init_buvid_async](crate::apis::misc::buvid::init_buvid_async)
+  pub fn buvid(&self) -> Option<Buvid> {
+    self.inner.device.get()
+  }
+
+  /// 设置设备指纹Cookie, 之后的每个请求都会带上
+  pub fn set_buvid(&self, buvid: Buvid) {
+    self.inner.device.set(buvid);
+  }
+
+  /// `bili_ticket`, 参见[init_bili_ticket_async](crate::apis::misc::sign::bili_ticket::init_bili_ticket_async)
+  pub fn bili_ticket(&self) -> Option<BiliTicket> {
+    self.inner.bili_ticket.get()
+  }
+
+  /// 设置`bili_ticket`, 之后的每个请求都会带上, 直到过期
+  pub fn set_bili_ticket(&self, ticket: BiliTicket) {
+    self.inner.bili_ticket.set(ticket);
+  }
+
+  /// Async version of [Client::call](crate::Client::call)
+  pub async fn call<'a, E: Endpoint + Sync + ?Sized>(
+    &self,
+    endpoint: &E,
+    auth: impl Into<Auth<'a>>,
+  ) -> crate::Result<E::Response> {
+    let auth = auth.into();
+    check_auth(endpoint, &auth)?;
+    match auth {
+      Auth::Web(credential) if endpoint.auto_refresh() => {
+        self
+          .with_credential(credential, |credential| async move {
+            self.call_once(endpoint, &Auth::Web(&credential)).await
+          })
+          .await
+      }
+      auth => self.call_once(endpoint, &auth).await,
+    }
+  }
+
+  pub(super) fn from_builder(builder: &ClientBuilder) -> Self {
+    let transport = builder
+      .async_transport
+      .clone()
+      .unwrap_or_else(|| Arc::new(AsyncReqwestTransport::new(reqwest::Client::new())));
+    let transport: SharedAsyncTransport = match &builder.cassette {
+      Some(CassetteMode::Record(path)) => Arc::new(RecordingTransport::new(transport, path)),
+      Some(CassetteMode::Replay(path)) => Arc::new(ReplayTransport::open(path)),
+      None => transport,
+    };
+    let inner = AsyncClientInner {
+      transport,
+      middlewares: Middlewares::new(builder.middlewares.clone()),
+      rate_limiter: Arc::new(RateLimiter::new(&builder.rate_limits)),
+      wbi_keys: Arc::new(WbiKeyCache::new(builder.wbi_key_ttl)),
+      credentials: Arc::new(RefreshedCredentials::new(builder.refresh_hook.clone())),
+      device: Arc::new(DeviceCookies::new(builder.auto_buvid)),
+      bili_ticket: Arc::new(BiliTicketCache::new(builder.auto_bili_ticket)),
+      refresh_lock: Arc::new(tokio::sync::Mutex::new(())),
+    };
+
+    Self {
+      inner,
+      hosts: builder.hosts.clone(),
+      retry_policy: builder.retry_policy.clone(),
+      live_msg_retry_policy: builder.live_msg_retry_policy.clone(),
+      app_key: builder.app_key.clone(),
+    }
+  }
+}
+
+#[derive(Clone)]
+struct AsyncClientInner {
+  transport: SharedAsyncTransport,
+  middlewares: Middlewares,
+  rate_limiter: Arc<RateLimiter>,
+  wbi_keys: Arc<WbiKeyCache>,
+  credentials: Arc<RefreshedCredentials>,
+  device: Arc<DeviceCookies>,
+  bili_ticket: Arc<BiliTicketCache>,
+  refresh_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl AsyncClient {
+  /// Boxed as acquiring the fingerprint or `bili_ticket` automatically sends requests
+  /// through here as well
+  fn send(&self, mut request: HttpRequest) -> TransportFuture<'_> {
+    Box::pin(async move {
+      if self.inner.device.begin_init() {
+        // Errors are covered by the locally generated fallback
+        let _ = crate::apis::misc::buvid::init_buvid_async(self).await;
+        self.inner.device.finish_init();
+      }
+      if self.inner.bili_ticket.begin_fetch() {
+        // Tried again a while later on failure
+        let _ = crate::apis::misc::sign::bili_ticket::init_bili_ticket_async(self).await;
+        self.inner.bili_ticket.finish_fetch();
+      }
+      self.inner.device.attach(&mut request.headers);
+      self.inner.bili_ticket.attach(&mut request.headers);
+      self
+        .inner
+        .middlewares
+        .send_async(request, |request| self.inner.transport.send(request))
+        .await
+    })
+  }
+
+  pub(crate) fn wbi_keys(&self) -> &WbiKeyCache {
+    &self.inner.wbi_keys
+  }
+
+  /// Waits until the rate limit of `group` allows another request
+  pub(crate) async fn wait_till_ready(&self, group: RateLimitGroup) {
+    self.inner.rate_limiter.wait_till_ready(group).await;
+  }
+
+  /// Async version of [Client::with_credential](crate::Client::with_credential)
+  pub(crate) async fn with_credential<T, F, Fut>(
+    &self,
+    credential: &Credential,
+    mut request: F,
+  ) -> crate::Result<T>
+  where
+    F: FnMut(Credential) -> Fut,
+    Fut: Future<Output = crate::Result<T>>,
+  {
+    let credential = self.credential(credential);
+    match request(credential.clone()).await {
+      Err(e) if self.inner.credentials.should_refresh(&e, &credential) => {
+        let refreshed = self.refresh_credential(&credential).await?;
+        request(refreshed).await
+      }
+      result => result,
+    }
+  }
+
+  /// Boxed as refreshing calls endpoints as well
+  fn refresh_credential<'a>(
+    &'a self,
+    stale: &'a Credential,
+  ) -> Pin<Box<dyn Future<Output = crate::Result<Credential>> + Send + 'a>> {
+    Box::pin(async move {
+      let _guard = self.inner.refresh_lock.lock().await;
+      // Another request may have refreshed it while we were waiting
+      let latest = self.credential(stale);
+      if latest.sessdata != stale.sessdata {
+        return Ok(latest);
+      }
+
+      let refreshed =
+        crate::apis::login::cookie_refresh::refresh_credential_async(self, stale, now_millis())
+          .await?;
+      self.inner.credentials.record(stale, &refreshed);
+      Ok(refreshed)
+    })
+  }
+
+  /// [AsyncClient::call] with the credential settled
+  async fn call_once<E: Endpoint + Sync + ?Sized>(
+    &self,
+    endpoint: &E,
+    auth: &Auth<'_>,
+  ) -> crate::Result<E::Response> {
+    let group = endpoint.rate_limit_group();
+    let policy = match group {
+      RateLimitGroup::LiveDanmaku => &self.live_msg_retry_policy,
+      _ => &self.retry_policy,
+    };
+    let policy = policy.for_endpoint(endpoint);
+    let wbi = endpoint.signing() == Signing::Wbi;
+    let attempt = || async {
+      let mixin_key = match auth.web() {
+        Some(credential) if wbi => Some(self.mixin_key(credential).await?),
+        _ => None,
+      };
+      let request = build_request(
+        endpoint,
+        &self.hosts,
+        &self.app_key,
+        auth,
+        mixin_key.as_deref(),
+      )?;
+      self.wait_till_ready(group).await;
+      endpoint.parse(self.send(request).await?)
+    };
+    let attempt = &attempt;
+    self
+      .with_retry_policy(&policy, || async move {
+        match attempt().await {
+          // The cached key has likely been rotated
+          Err(Error::Api(e)) if wbi && e.kind().is_risk_control() => {
+            self.wbi_keys().invalidate();
+            attempt().await
+          }
+          result => result,
+        }
+      })
+      .await
+  }
+
+  /// Boxed as fetching the key calls an endpoint as well
+  fn mixin_key<'a>(
+    &'a self,
+    credential: &'a Credential,
+  ) -> Pin<Box<dyn Future<Output = crate::Result<String>> + Send + 'a>> {
+    Box::pin(cached_mixin_key_async(self, credential))
+  }
+
+  pub(crate) async fn with_retry_policy<T, F, Fut>(
+    &self,
+    policy: &RetryPolicy,
+    mut request: F,
+  ) -> crate::Result<T>
+  where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = crate::Result<T>>,
+  {
+    let mut attempt = 1;
+    loop {
+      match request().await {
+        Err(e) => match policy.next_delay(&e, attempt) {
+          Some(delay) => {
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+          }
+          None => return Err(e),
+        },
+        result => return result,
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::AsyncClient;
+
+  #[test]
+  pub fn test_async_client_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<AsyncClient>();
+  }
+
+  #[test]
+  pub fn test_async_client_sync() {
+    fn assert_sync<T: Sync>() {}
+    assert_sync::<AsyncClient>();
+  }
+}
@@ -0,0 +1,114 @@
+use std::{
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+  },
+  time::{Duration, Instant},
+};
+
+use reqwest::header::HeaderMap;
+
+use crate::{
+  apis::misc::sign::bili_ticket::BiliTicket,
+  utils::{add_cookies, now_millis},
+};
+
+/// Tickets expiring within this many seconds are fetched again
+const EXPIRY_MARGIN_SECS: i64 = 60 * 60;
+/// Failed fetches are not attempted again before this
+const FETCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The `bili_ticket` of a client, attached to every request it sends while valid.
+pub(crate) struct BiliTicketCache {
+  auto: bool,
+  ticket: Mutex<Option<BiliTicket>>,
+  last_fetch: Mutex<Option<Instant>>,
+  fetching: AtomicBool,
+}
+
+impl BiliTicketCache {
+  pub fn new(auto: bool) -> Self {
+    Self {
+      auto,
+      ticket: Mutex::new(None),
+      last_fetch: Mutex::new(None),
+      fetching: AtomicBool::new(false),
+    }
+  }
+
+  /// The stored ticket, which may have expired
+  pub fn get(&self) -> Option<BiliTicket> {
+    self.ticket.lock().expect("Lock poisoned").clone()
+  }
+
+  /// The stored ticket unless it is about to expire
+  pub fn valid(&self) -> Option<BiliTicket> {
+    self
+      .get()
+      .filter(|ticket| !ticket.expires_within(now_millis() / 1000, EXPIRY_MARGIN_SECS))
+  }
+
+  pub fn set(&self, ticket: BiliTicket) {
+    *self.ticket.lock().expect("Lock poisoned") = Some(ticket);
+  }
+
+  /// Whether the caller should fetch a ticket now. Only one caller gets `true`, and it must
+  /// call [BiliTicketCache::finish_fetch] afterwards. Requests sent meanwhile, including
+  /// the one fetching it, go without.
+  pub fn begin_fetch(&self) -> bool {
+    if !self.auto || self.valid().is_some() {
+      return false;
+    }
+    let last_fetch = *self.last_fetch.lock().expect("Lock poisoned");
+    if last_fetch.is_some_and(|at| at.elapsed() < FETCH_INTERVAL) {
+      return false;
+    }
+    self
+      .fetching
+      .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+      .is_ok()
+  }
+
+  pub fn finish_fetch(&self) {
+    *self.last_fetch.lock().expect("Lock poisoned") = Some(Instant::now());
+    self.fetching.store(false, Ordering::Release);
+  }
+
+  /// Adds `bili_ticket` and `bili_ticket_expires` to the `Cookie` header in `headers`
+  pub fn attach(&self, headers: &mut HeaderMap) {
+    if let Some(ticket) = self.valid() {
+      add_cookies(headers, ticket.cookies());
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::BiliTicketCache;
+  use crate::{apis::misc::sign::bili_ticket::BiliTicket, utils::now_millis};
+
+  #[test]
+  fn test_bili_ticket_cache() {
+    let cache = BiliTicketCache::new(true);
+    assert!(cache.begin_fetch());
+    assert!(!cache.begin_fetch());
+    cache.finish_fetch();
+    // Failed just now
+    assert!(!cache.begin_fetch());
+
+    cache.set(BiliTicket {
+      ticket: "expiring".to_string(),
+      expires_at: now_millis() / 1000 + 60,
+    });
+    assert!(cache.get().is_some());
+    assert_eq!(None, cache.valid());
+
+    let ticket = BiliTicket {
+      ticket: "fresh".to_string(),
+      expires_at: now_millis() / 1000 + 259200,
+    };
+    cache.set(ticket.clone());
+    assert_eq!(Some(ticket), cache.valid());
+    assert!(!BiliTicketCache::new(false).begin_fetch());
+  }
+}
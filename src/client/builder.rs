@@ -0,0 +1,223 @@
+use std::{
+  collections::HashMap,
+  path::PathBuf,
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use super::{
+  bili_ticket::BiliTicketCache,
+  credential_refresh::{RefreshHook, RefreshedCredentials},
+  device::DeviceCookies,
+  host::{Host, Hosts},
+  middleware::{Middleware, Middlewares},
+  rate_limiting::{default_rate_limits, RateLimit, RateLimitGroup, RateLimiter},
+  record::{CassetteMode, RecordingTransport, ReplayTransport},
+  retry::RetryPolicy,
+  transport::{ReqwestTransport, SharedTransport, Transport},
+  wbi_keys::WbiKeyCache,
+  Client, ClientInner,
+};
+#[cfg(feature = "async")]
+use super::{
+  transport::{AsyncTransport, SharedAsyncTransport},
+  AsyncClient,
+};
+use crate::{apis::misc::sign::app::AppKey, credential::Credential};
+
+/// Builder for [Client] (and [AsyncClient] with the `async` feature).
+///
+/// ```no_run
+/// use bili_api_rs::client::{Client, Host};
+///
+/// let client = Client::builder()
+///   .host(Host::ApiLive, "http://127.0.0.1:8080")
+///   .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+  pub(super) rate_limits: HashMap<RateLimitGroup, RateLimit>,
+  pub(super) wbi_key_ttl: Duration,
+  pub(super) hosts: Hosts,
+  pub(super) retry_policy: RetryPolicy,
+  pub(super) live_msg_retry_policy: RetryPolicy,
+  pub(super) refresh_hook: Option<RefreshHook>,
+  pub(super) app_key: AppKey,
+  pub(super) auto_buvid: bool,
+  pub(super) auto_bili_ticket: bool,
+  pub(super) transport: Option<SharedTransport>,
+  #[cfg(feature = "async")]
+  pub(super) async_transport: Option<SharedAsyncTransport>,
+  pub(super) cassette: Option<CassetteMode>,
+  pub(super) middlewares: Vec<Arc<dyn Middleware>>,
+}
+
+impl Default for ClientBuilder {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl ClientBuilder {
+  pub fn new() -> Self {
+    const DEFAULT_WBI_KEY_TTL: Duration = Duration::from_secs(60 * 60);
+    Self {
+      rate_limits: default_rate_limits(),
+      wbi_key_ttl: DEFAULT_WBI_KEY_TTL,
+      hosts: Hosts::default(),
+      retry_policy: RetryPolicy::default(),
+      live_msg_retry_policy: RetryPolicy::live_msg(),
+      refresh_hook: None,
+      app_key: AppKey::default(),
+      auto_buvid: false,
+      auto_bili_ticket: false,
+      transport: None,
+      #[cfg(feature = "async")]
+      async_transport: None,
+      cassette: None,
+      middlewares: Vec::new(),
+    }
+  }
+
+  /// 自定义直播弹幕API限流: 每两条弹幕之间至少间隔`limit`
+  pub fn rate_limit(&mut self, limit: Duration) -> &mut Self {
+    self.group_rate_limit(RateLimitGroup::LiveDanmaku, RateLimit::every(limit))
+  }
+
+  /// 自定义某一API分组的限流
+  pub fn group_rate_limit(&mut self, group: RateLimitGroup, limit: RateLimit) -> &mut Self {
+    self.rate_limits.insert(group, limit);
+    self
+  }
+
+  /// 取消某一API分组的限流
+  pub fn no_rate_limit(&mut self, group: RateLimitGroup) -> &mut Self {
+    self.rate_limits.remove(&group);
+    self
+  }
+
+  /// WBI签名密钥的缓存时间, 过期后会在下一次签名时重新获取
+  pub fn wbi_key_ttl(&mut self, ttl: Duration) -> &mut Self {
+    self.wbi_key_ttl = ttl;
+    self
+  }
+
+  /// 自定义API域名, e.g., 指向本地的测试服务器
+  pub fn host(&mut self, host: Host, base: impl Into<String>) -> &mut Self {
+    self.hosts.set(host, base);
+    self
+  }
+
+  /// 所有API共用的重试策略
+  pub fn retry_policy(&mut self, policy: RetryPolicy) -> &mut Self {
+    self.retry_policy = policy;
+    self
+  }
+
+  /// 发送直播弹幕的重试策略, 默认在发送过快时等待3秒后重试一次
+  pub fn live_msg_retry_policy(&mut self, policy: RetryPolicy) -> &mut Self {
+    self.live_msg_retry_policy = policy;
+    self
+  }
+
+  /// APP端API签名所用的appkey, 默认为[AppKey::android]
+  pub fn app_key(&mut self, app_key: AppKey) -> &mut Self {
+    self.app_key = app_key;
+    self
+  }
+
+  /// 需要登录的API返回登录失效等错误时, 自动刷新Cookie并重试一次. 凭据中须带有refresh_token.
+  ///
+  /// 刷新成功后以新凭据调用`on_refresh`, e.g., 用于持久化. 之后使用旧凭据的请求会自动改用新凭据,
+  /// 新凭据也可以通过[Client::credential]获取.
+  pub fn auto_refresh_credential(
+    &mut self,
+    on_refresh: impl Fn(&Credential) + Send + Sync + 'static,
+  ) -> &mut Self {
+    self.refresh_hook = Some(RefreshHook::new(on_refresh));
+    self
+  }
+
+  /// 在第一次请求前自动申请并激活设备指纹, 失败时改用本地生成的指纹.
+  /// 参见[init_buvid](crate::apis::misc::buvid::init_buvid)
+  pub fn auto_buvid(&mut self) -> &mut Self {
+    self.auto_buvid = true;
+    self
+  }
+
+  /// 在第一次请求前自动申请`bili_ticket`, 过期前重新申请.
+  /// 参见[init_bili_ticket](crate::apis::misc::sign::bili_ticket::init_bili_ticket)
+  pub fn auto_bili_ticket(&mut self) -> &mut Self {
+    self.auto_bili_ticket = true;
+    self
+  }
+
+  /// 自定义[Client]发送请求所用的HTTP栈, 默认为[ReqwestTransport]
+  pub fn transport(&mut self, transport: impl Transport + 'static) -> &mut Self {
+    self.transport = Some(Arc::new(transport));
+    self
+  }
+
+  /// 自定义[AsyncClient]发送请求所用的HTTP栈, 默认为[AsyncReqwestTransport](super::AsyncReqwestTransport)
+  #[cfg(feature = "async")]
+  pub fn async_transport(&mut self, transport: impl AsyncTransport + 'static) -> &mut Self {
+    self.async_transport = Some(Arc::new(transport));
+    self
+  }
+
+  /// 添加一个[Middleware], 在每个请求发出前和收到响应后调用, e.g., 用于日志或[Metrics](super::Metrics).
+  /// 请求按添加顺序经过各个middleware, 响应按相反顺序
+  pub fn middleware(&mut self, middleware: impl Middleware + 'static) -> &mut Self {
+    self.middlewares.push(Arc::new(middleware));
+    self
+  }
+
+  /// 记录每个请求及其响应到`path`的[Cassette](super::Cassette)文件, 其中的Cookie, csrf和
+  /// access_token等会被隐去. 请求仍由[ClientBuilder::transport]发送
+  pub fn record(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+    self.cassette = Some(CassetteMode::Record(path.into()));
+    self
+  }
+
+  /// 不访问网络, 以`path`中[ClientBuilder::record]记录的响应回复请求, 用于离线测试.
+  /// 参见[ReplayTransport](super::ReplayTransport)
+  pub fn replay(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+    self.cassette = Some(CassetteMode::Replay(path.into()));
+    self
+  }
+
+  pub fn build(&self) -> Client {
+    let transport = self
+      .transport
+      .clone()
+      .unwrap_or_else(|| Arc::new(ReqwestTransport::new(reqwest::blocking::Client::new())));
+    let transport: SharedTransport = match &self.cassette {
+      Some(CassetteMode::Record(path)) => Arc::new(RecordingTransport::new(transport, path)),
+      Some(CassetteMode::Replay(path)) => Arc::new(ReplayTransport::open(path)),
+      None => transport,
+    };
+    let inner = ClientInner {
+      transport,
+      middlewares: Middlewares::new(self.middlewares.clone()),
+      rate_limiter: Arc::new(RateLimiter::new(&self.rate_limits)),
+      wbi_keys: Arc::new(WbiKeyCache::new(self.wbi_key_ttl)),
+      credentials: Arc::new(RefreshedCredentials::new(self.refresh_hook.clone())),
+      device: Arc::new(DeviceCookies::new(self.auto_buvid)),
+      bili_ticket: Arc::new(BiliTicketCache::new(self.auto_bili_ticket)),
+      refresh_lock: Arc::new(Mutex::new(())),
+    };
+
+    Client {
+      inner,
+      hosts: self.hosts.clone(),
+      retry_policy: self.retry_policy.clone(),
+      live_msg_retry_policy: self.live_msg_retry_policy.clone(),
+      app_key: self.app_key.clone(),
+    }
+  }
+
+  #[cfg(feature = "async")]
+  pub fn build_async(&self) -> AsyncClient {
+    AsyncClient::from_builder(self)
+  }
+}
@@ -0,0 +1,196 @@
+use std::{collections::HashMap, fmt, sync::Arc, sync::Mutex};
+
+use crate::{credential::Credential, error::ErrorCode, Error};
+
+/// Called with the new credential each time the client refreshed one, e.g., to persist it
+#[derive(Clone)]
+pub(crate) struct RefreshHook(Arc<dyn Fn(&Credential) + Send + Sync>);
+
+impl RefreshHook {
+  pub fn new(hook: impl Fn(&Credential) + Send + Sync + 'static) -> Self {
+    Self(Arc::new(hook))
+  }
+}
+
+impl fmt::Debug for RefreshHook {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("RefreshHook")
+  }
+}
+
+/// Credentials refreshed automatically by the client, the latest one per account, so that
+/// later calls made with the stale credential use the refreshed one.
+pub(crate) struct RefreshedCredentials {
+  hook: Option<RefreshHook>,
+  replaced: Mutex<HashMap<Account, Replacement>>,
+}
+
+/// Stale `SESSDATA`s kept per account: the one first refreshed, which callers usually keep
+/// passing, and those handed to the refresh hook most recently
+const MAX_STALE: usize = 8;
+
+/// The latest refresh of the credentials with the `stale` `SESSDATA`s
+#[derive(Debug)]
+struct Replacement {
+  stale: Vec<String>,
+  latest: Credential,
+}
+
+/// The `DedeUserID` of a credential, or its `SESSDATA` if it has none
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Account {
+  Uid(String),
+  Session(String),
+}
+
+impl Account {
+  fn of(credential: &Credential) -> Self {
+    match credential.dede_user_id.as_deref() {
+      Some(uid) if !uid.is_empty() => Account::Uid(uid.to_string()),
+      _ => Account::Session(credential.sessdata.clone()),
+    }
+  }
+}
+
+impl RefreshedCredentials {
+  pub fn new(hook: Option<RefreshHook>) -> Self {
+    Self {
+      hook,
+      replaced: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// The latest credential replacing `credential`, or `credential` itself. Any other
+  /// credential of the account, e.g., from a new login, wins over the refreshed one.
+  pub fn latest(&self, credential: &Credential) -> Credential {
+    let mut replaced = self.replaced.lock().expect("Lock poisoned");
+    let stale = replaced
+      .values()
+      .find(|replacement| replacement.stale.contains(&credential.sessdata));
+    if let Some(replacement) = stale {
+      return replacement.latest.clone();
+    }
+    let account = Account::of(credential);
+    if replaced
+      .get(&account)
+      .is_some_and(|replacement| replacement.latest.sessdata != credential.sessdata)
+    {
+      replaced.remove(&account);
+    }
+    credential.clone()
+  }
+
+  /// Whether `err` returned for `credential` should be handled by refreshing it
+  pub fn should_refresh(&self, err: &Error, credential: &Credential) -> bool {
+    self.hook.is_some()
+      && err.api_code().is_some_and(ErrorCode::is_auth_error)
+      && credential.refresh_token().is_some_and(|t| !t.is_empty())
+  }
+
+  pub fn record(&self, stale: &Credential, refreshed: &Credential) {
+    {
+      let mut replaced = self.replaced.lock().expect("Lock poisoned");
+      // Refreshing the latest credential again keeps standing in for the stale one
+      let chained = replaced
+        .iter_mut()
+        .find(|(_, replacement)| replacement.latest.sessdata == stale.sessdata);
+      match chained {
+        Some((_, replacement)) => {
+          if replacement.stale.len() == MAX_STALE {
+            replacement.stale.remove(1);
+          }
+          replacement.stale.push(stale.sessdata.clone());
+          replacement.latest = refreshed.clone();
+        }
+        None => {
+          let replacement = Replacement {
+            stale: vec![stale.sessdata.clone()],
+            latest: refreshed.clone(),
+          };
+          replaced.insert(Account::of(stale), replacement);
+        }
+      }
+    }
+    if let Some(hook) = &self.hook {
+      (hook.0)(refreshed);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+
+  use super::{RefreshHook, RefreshedCredentials};
+  use crate::{credential::Credential, error::ApiError, Error};
+
+  #[test]
+  fn test_refresh_chain() {
+    let saved = Arc::new(Mutex::new(Vec::new()));
+    let hook = {
+      let saved = Arc::clone(&saved);
+      RefreshHook::new(move |c: &Credential| saved.lock().unwrap().push(c.sessdata.clone()))
+    };
+    let store = RefreshedCredentials::new(Some(hook));
+
+    let mut first = Credential::new("a".to_string(), "jct".to_string());
+    first.set_cookie("ac_time_value", "token");
+    let second = Credential::new("b".to_string(), "jct".to_string());
+    let third = Credential::new("c".to_string(), "jct".to_string());
+
+    let not_logged_in = Error::Api(ApiError::new(-101, "账号未登录".to_string()));
+    assert!(store.should_refresh(&not_logged_in, &first));
+    assert!(!store.should_refresh(&not_logged_in, &second));
+    assert!(!store.should_refresh(&Error::Api(ApiError::new(-400, String::new())), &first));
+
+    store.record(&first, &second);
+    store.record(&second, &third);
+    assert_eq!("c", store.latest(&first).sessdata);
+    assert_eq!("c", store.latest(&second).sessdata);
+    assert_eq!("c", store.latest(&third).sessdata);
+    assert_eq!(vec!["b", "c"], *saved.lock().unwrap());
+    // Without DedeUserID, keyed by the SESSDATA first replaced
+    assert_eq!(1, store.replaced.lock().unwrap().len());
+
+    // One entry per account however often it is refreshed
+    let account = |sessdata: &str| {
+      let mut credential = Credential::new(sessdata.to_string(), "jct".to_string());
+      credential.set_cookie("DedeUserID", "12734361");
+      credential
+    };
+    let store = RefreshedCredentials::new(Some(RefreshHook::new(|_| {})));
+    for i in 0..10 {
+      store.record(&account(&i.to_string()), &account(&(i + 1).to_string()));
+    }
+    assert_eq!(1, store.replaced.lock().unwrap().len());
+    assert_eq!("10", store.latest(&account("0")).sessdata);
+    assert_eq!("10", store.latest(&account("9")).sessdata);
+    assert_eq!("10", store.latest(&account("10")).sessdata);
+    assert_eq!(
+      "other",
+      store
+        .latest(&Credential::new("other".to_string(), "jct".to_string()))
+        .sessdata
+    );
+
+    let disabled = RefreshedCredentials::new(None);
+    assert!(!disabled.should_refresh(&not_logged_in, &first));
+  }
+
+  #[test]
+  fn test_new_credential_wins() {
+    let account = |sessdata: &str| {
+      let mut credential = Credential::new(sessdata.to_string(), "jct".to_string());
+      credential.set_cookie("DedeUserID", "12734361");
+      credential
+    };
+    let store = RefreshedCredentials::new(Some(RefreshHook::new(|_| {})));
+    store.record(&account("stale"), &account("refreshed"));
+    assert_eq!("refreshed", store.latest(&account("stale")).sessdata);
+
+    // e.g., from a new QR login
+    assert_eq!("login", store.latest(&account("login")).sessdata);
+    assert!(store.replaced.lock().unwrap().is_empty());
+    assert_eq!("stale", store.latest(&account("stale")).sessdata);
+  }
+}
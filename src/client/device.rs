@@ -0,0 +1,120 @@
+use std::sync::{
+  atomic::{AtomicBool, Ordering},
+  Mutex,
+};
+
+use reqwest::header::HeaderMap;
+
+use crate::{apis::misc::buvid::Buvid, utils::add_cookies};
+
+/// The device fingerprint cookies (`buvid3`, `buvid4`, `b_nut`, `_uuid`) of a client,
+/// attached to every request it sends.
+pub(crate) struct DeviceCookies {
+  auto: bool,
+  buvid: Mutex<Option<Buvid>>,
+  initializing: AtomicBool,
+}
+
+impl DeviceCookies {
+  pub fn new(auto: bool) -> Self {
+    Self {
+      auto,
+      buvid: Mutex::new(None),
+      initializing: AtomicBool::new(false),
+    }
+  }
+
+  pub fn get(&self) -> Option<Buvid> {
+    self.buvid.lock().expect("Lock poisoned").clone()
+  }
+
+  pub fn set(&self, buvid: Buvid) {
+    *self.buvid.lock().expect("Lock poisoned") = Some(buvid);
+  }
+
+  /// Whether the caller should acquire the fingerprint now. Only one caller gets `true`,
+  /// and it must call [DeviceCookies::finish_init] afterwards. Requests sent meanwhile,
+  /// including those made to acquire it, go without.
+  pub fn begin_init(&self) -> bool {
+    self.auto
+      && self.get().is_none()
+      && self
+        .initializing
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+        .is_ok()
+  }
+
+  /// Falls back to a locally generated fingerprint if none was acquired
+  pub fn finish_init(&self) {
+    self
+      .buvid
+      .lock()
+      .expect("Lock poisoned")
+      .get_or_insert_with(Buvid::generate);
+    self.initializing.store(false, Ordering::Release);
+  }
+
+  /// Adds the fingerprint cookies to the `Cookie` header in `headers`.
+  /// Cookies already present, e.g., the `buvid3` of a [Credential](crate::credential::Credential), win.
+  pub fn attach(&self, headers: &mut HeaderMap) {
+    if let Some(buvid) = self.get() {
+      add_cookies(headers, buvid.cookies());
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use reqwest::header::{HeaderMap, HeaderValue, COOKIE};
+
+  use super::DeviceCookies;
+  use crate::apis::misc::buvid::Buvid;
+
+  fn buvid() -> Buvid {
+    Buvid {
+      buvid3: "B3-infoc".to_string(),
+      buvid4: "B4-n1".to_string(),
+      b_nut: 1702204169,
+      uuid: "U-infoc".to_string(),
+    }
+  }
+
+  #[test]
+  fn test_attach() {
+    let device = DeviceCookies::new(false);
+    let mut headers = HeaderMap::new();
+    device.attach(&mut headers);
+    assert!(headers.get(COOKIE).is_none());
+
+    device.set(buvid());
+    device.attach(&mut headers);
+    assert_eq!(
+      "buvid3=B3-infoc; buvid4=B4-n1; b_nut=1702204169; _uuid=U-infoc",
+      headers[COOKIE]
+    );
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+      COOKIE,
+      HeaderValue::from_static("SESSDATA=sess; buvid3=mine"),
+    );
+    device.attach(&mut headers);
+    assert_eq!(
+      "SESSDATA=sess; buvid3=mine; buvid4=B4-n1; b_nut=1702204169; _uuid=U-infoc",
+      headers[COOKIE]
+    );
+  }
+
+  #[test]
+  fn test_init_once() {
+    let device = DeviceCookies::new(false);
+    assert!(!device.begin_init());
+
+    let device = DeviceCookies::new(true);
+    assert!(device.begin_init());
+    assert!(!device.begin_init());
+    device.finish_init();
+    assert!(device.get().unwrap().buvid3.ends_with("infoc"));
+    assert!(!device.begin_init());
+  }
+}
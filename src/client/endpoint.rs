@@ -0,0 +1,552 @@
+//! Declarative descriptions of the APIs. An [Endpoint] says what a request looks like and
+//! what it needs, e.g., a cookie, `csrf` or a signature, and [Client::call](crate::Client::call)
+//! takes care of the rest: credential refresh, retries, rate limiting and parsing.
+//!
+//! ```no_run
+//! use bili_api_rs::client::{Auth, Client, Endpoint, Host, Params};
+//! use serde::Deserialize;
+//!
+//! #[derive(Debug, Deserialize)]
+//! struct RoomInitResponse {
+//!   data: serde_json::Value,
+//! }
+//!
+//! struct RoomInit {
+//!   room_id: i32,
+//! }
+//!
+//! impl Endpoint for RoomInit {
+//!   type Response = RoomInitResponse;
+//!
+//!   fn host(&self) -> Host {
+//!     Host::ApiLive
+//!   }
+//!
+//!   fn path(&self) -> String {
+//!     "/room/v1/Room/room_init".to_string()
+//!   }
+//!
+//!   fn query(&self) -> bili_api_rs::Result<Params> {
+//!     Ok(Params::new().with("id", self.room_id))
+//!   }
+//! }
+//!
+//! let client = Client::new();
+//! let response = client.call(&RoomInit { room_id: 5050 }, Auth::None).unwrap();
+//! ```
+
+use reqwest::{
+  header::{HeaderMap, HeaderValue, CONTENT_TYPE, COOKIE, USER_AGENT},
+  Method, Url,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+  host::{Host, Hosts},
+  rate_limiting::RateLimitGroup,
+  transport::{HttpRequest, HttpResponse},
+};
+use crate::{
+  apis::misc::sign::{
+    app::{app_sign, AppKey},
+    wbi::{now_secs, wbi_sign_with_ts},
+  },
+  credential::{AppCredential, Credential},
+  utils::handle_api_response,
+  Error,
+};
+
+/// Query or form parameters, sent in the order they were added
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params(Vec<(String, String)>);
+
+impl Params {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The fields of `value` as `reqwest` would send it as a query, e.g., a struct deriving
+  /// [Serialize] or a slice of pairs
+  pub fn from_serialize<T: Serialize + ?Sized>(value: &T) -> crate::Result<Self> {
+    let encoded = serde_urlencoded::to_string(value).map_err(|e| Error::Query(e.to_string()))?;
+    Ok(Self(
+      url::form_urlencoded::parse(encoded.as_bytes())
+        .into_owned()
+        .collect(),
+    ))
+  }
+
+  pub fn with(mut self, name: impl Into<String>, value: impl ToString) -> Self {
+    self.push(name, value);
+    self
+  }
+
+  pub fn push(&mut self, name: impl Into<String>, value: impl ToString) -> &mut Self {
+    self.0.push((name.into(), value.to_string()));
+    self
+  }
+
+  /// Replaces the value of the first `name`, or adds it if there is none
+  pub fn set(&mut self, name: impl Into<String>, value: impl ToString) -> &mut Self {
+    let name = name.into();
+    match self.0.iter_mut().find(|(n, _)| *n == name) {
+      Some((_, v)) => *v = value.to_string(),
+      None => self.0.push((name, value.to_string())),
+    }
+    self
+  }
+
+  /// The first value of `name`
+  pub fn get(&self, name: &str) -> Option<&str> {
+    self
+      .0
+      .iter()
+      .find(|(n, _)| n == name)
+      .map(|(_, v)| v.as_str())
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+    self.0.iter().map(|(n, v)| (n.as_str(), v.as_str()))
+  }
+
+  /// `application/x-www-form-urlencoded`
+  pub fn encode(&self) -> String {
+    url::form_urlencoded::Serializer::new(String::new())
+      .extend_pairs(self.iter())
+      .finish()
+  }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Params {
+  fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+    Self(
+      iter
+        .into_iter()
+        .map(|(k, v)| (k.into(), v.into()))
+        .collect(),
+    )
+  }
+}
+
+/// The body of a request
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Body {
+  #[default]
+  None,
+  /// `application/x-www-form-urlencoded`
+  Form(Params),
+  /// `application/json`
+  Json(String),
+}
+
+/// The credential an [Endpoint] requires
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthKind {
+  /// 无需登录. A web credential is still sent as cookies if given.
+  #[default]
+  None,
+  /// Web端Cookie, i.e., [Credential]
+  Web,
+  /// APP端access_key, i.e., [AppCredential]
+  App,
+}
+
+/// The credential to call an [Endpoint] with
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Auth<'a> {
+  #[default]
+  None,
+  Web(&'a Credential),
+  App(&'a AppCredential),
+}
+
+impl<'a> Auth<'a> {
+  pub fn kind(&self) -> AuthKind {
+    match self {
+      Auth::None => AuthKind::None,
+      Auth::Web(_) => AuthKind::Web,
+      Auth::App(_) => AuthKind::App,
+    }
+  }
+
+  pub fn web(&self) -> Option<&'a Credential> {
+    match self {
+      Auth::Web(credential) => Some(credential),
+      _ => None,
+    }
+  }
+
+  pub fn app(&self) -> Option<&'a AppCredential> {
+    match self {
+      Auth::App(credential) => Some(credential),
+      _ => None,
+    }
+  }
+}
+
+impl<'a> From<&'a Credential> for Auth<'a> {
+  fn from(credential: &'a Credential) -> Self {
+    Auth::Web(credential)
+  }
+}
+
+impl<'a> From<&'a AppCredential> for Auth<'a> {
+  fn from(credential: &'a AppCredential) -> Self {
+    Auth::App(credential)
+  }
+}
+
+impl<'a> From<Option<&'a Credential>> for Auth<'a> {
+  fn from(credential: Option<&'a Credential>) -> Self {
+    credential.map_or(Auth::None, Auth::Web)
+  }
+}
+
+/// How the parameters of an [Endpoint] are signed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Signing {
+  #[default]
+  None,
+  /// WBI签名, 签名query. The mixin key is cached on the client, and dropped and fetched
+  /// again if the request is rejected by risk control (-352).
+  Wbi,
+  /// APP签名, 签名form, 没有form时签名query. None for the
+  /// [app_key](crate::Client::app_key) of the client.
+  App(Option<AppKey>),
+}
+
+/// An API, see the [module documentation](self).
+///
+/// [query](Endpoint::query) and [body](Endpoint::body) are evaluated once per attempt, so
+/// values that have to be fresh, e.g., timestamps, may be computed there.
+pub trait Endpoint {
+  type Response: DeserializeOwned;
+
+  fn host(&self) -> Host;
+
+  fn path(&self) -> String;
+
+  fn method(&self) -> Method {
+    Method::GET
+  }
+
+  fn query(&self) -> crate::Result<Params> {
+    Ok(Params::new())
+  }
+
+  fn body(&self) -> crate::Result<Body> {
+    Ok(Body::None)
+  }
+
+  fn auth(&self) -> AuthKind {
+    AuthKind::None
+  }
+
+  /// Whether an expired web credential is refreshed and the request tried once more, see
+  /// [ClientBuilder::auto_refresh_credential](super::ClientBuilder::auto_refresh_credential).
+  /// Turned off by the cookie refresh APIs themselves.
+  fn auto_refresh(&self) -> bool {
+    true
+  }
+
+  /// Whether failed attempts are retried under the client's [RetryPolicy](super::RetryPolicy).
+  /// Turned off by requests that must never be sent twice, e.g., the cookie refresh APIs
+  /// which consume the `refresh_token` they are sent with.
+  fn retry(&self) -> bool {
+    true
+  }
+
+  /// Whether sending the request twice does no harm. Network and server errors, after which
+  /// the request may have been carried out, are only retried for idempotent endpoints.
+  /// Defaults to GET requests.
+  fn idempotent(&self) -> bool {
+    self.method() == Method::GET
+  }
+
+  /// Names of the parameters set to `bili_jct`, e.g., `csrf` and `csrf_token`. They are
+  /// appended to the form, or to the query if there is no form, unless already there,
+  /// e.g., as an empty placeholder to keep the order of the parameters.
+  fn csrf(&self) -> &'static [&'static str] {
+    &[]
+  }
+
+  fn signing(&self) -> Signing {
+    Signing::None
+  }
+
+  /// [RateLimitGroup::LiveDanmaku] also selects
+  /// [ClientBuilder::live_msg_retry_policy](super::ClientBuilder::live_msg_retry_policy)
+  fn rate_limit_group(&self) -> RateLimitGroup {
+    RateLimitGroup::General
+  }
+
+  /// 默认按照B站的JSON格式解析, 参见[handle_api_response]
+  fn parse(&self, response: HttpResponse) -> crate::Result<Self::Response> {
+    handle_api_response(response)
+  }
+}
+
+/// Fails if `auth` is not what `endpoint` requires
+pub(crate) fn check_auth<E: Endpoint + ?Sized>(endpoint: &E, auth: &Auth) -> crate::Result<()> {
+  let required = endpoint.auth();
+  let missing = match required {
+    AuthKind::None => false,
+    AuthKind::Web => auth.web().is_none(),
+    AuthKind::App => auth.app().is_none(),
+  };
+  let needs_cookie = !endpoint.csrf().is_empty() || endpoint.signing() == Signing::Wbi;
+  if missing || (needs_cookie && auth.web().is_none()) {
+    let what = match required {
+      AuthKind::App => "an APP credential",
+      _ => "a web credential",
+    };
+    return Err(Error::Credential(format!(
+      "{} requires {}",
+      endpoint.path(),
+      what
+    )));
+  }
+  Ok(())
+}
+
+/// Builds the request of one attempt. `mixin_key` is required for [Signing::Wbi].
+pub(crate) fn build_request<E: Endpoint + ?Sized>(
+  endpoint: &E,
+  hosts: &Hosts,
+  app_key: &AppKey,
+  auth: &Auth,
+  mixin_key: Option<&str>,
+) -> crate::Result<HttpRequest> {
+  let mut query = endpoint.query()?;
+  let mut body = endpoint.body()?;
+
+  if let Some(credential) = auth.web() {
+    for name in endpoint.csrf() {
+      match &mut body {
+        Body::Form(form) => form.set(*name, &credential.bili_jct),
+        _ => query.set(*name, &credential.bili_jct),
+      };
+    }
+  }
+
+  match endpoint.signing() {
+    Signing::None => {
+      if let Some(credential) = auth.app() {
+        query.push("access_key", &credential.access_key);
+      }
+    }
+    Signing::Wbi => {
+      let mixin_key = mixin_key.expect("WBI signing without a mixin key");
+      query = wbi_sign_with_ts(&query.0, mixin_key, now_secs())?
+        .into_iter()
+        .collect();
+    }
+    Signing::App(key) => {
+      let key = key.as_ref().unwrap_or(app_key);
+      let access_key = auth.app().map(|c| ("access_key", c.access_key.as_str()));
+      let sign = |params: &Params| -> Params {
+        app_sign(params.iter().chain(access_key), key)
+          .into_iter()
+          .collect()
+      };
+      match &mut body {
+        Body::Form(form) => *form = sign(form),
+        _ => query = sign(&query),
+      }
+    }
+  }
+
+  let mut url = Url::parse(&hosts.url(endpoint.host(), &endpoint.path()))
+    .map_err(|e| Error::Query(e.to_string()))?;
+  if !query.is_empty() {
+    url.query_pairs_mut().extend_pairs(query.iter());
+  }
+
+  let mut headers = HeaderMap::new();
+  headers.insert(
+    USER_AGENT,
+    HeaderValue::from_static(crate::apis::USER_AGENT),
+  );
+  if let Some(credential) = auth.web() {
+    let cookie = HeaderValue::from_str(&credential.to_cookie_str())
+      .map_err(|e| Error::Credential(format!("invalid cookie: {}", e)))?;
+    headers.insert(COOKIE, cookie);
+  }
+  let body = match body {
+    Body::None => Vec::new(),
+    Body::Form(form) => {
+      headers.insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-www-form-urlencoded"),
+      );
+      form.encode().into_bytes()
+    }
+    Body::Json(json) => {
+      headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+      json.into_bytes()
+    }
+  };
+
+  Ok(HttpRequest {
+    method: endpoint.method(),
+    url,
+    headers,
+    body,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+
+  use reqwest::StatusCode;
+  use serde::Deserialize;
+
+  use super::*;
+  use crate::Client;
+
+  #[derive(Debug, Deserialize)]
+  struct Empty {}
+
+  struct Wear {
+    medal_id: i32,
+  }
+
+  impl Endpoint for Wear {
+    type Response = Empty;
+
+    fn host(&self) -> Host {
+      Host::ApiLive
+    }
+
+    fn path(&self) -> String {
+      "/xlive/web-room/v1/fansMedal/wear".to_string()
+    }
+
+    fn method(&self) -> Method {
+      Method::POST
+    }
+
+    fn body(&self) -> crate::Result<Body> {
+      Ok(Body::Form(Params::new().with("medal_id", self.medal_id)))
+    }
+
+    fn auth(&self) -> AuthKind {
+      AuthKind::Web
+    }
+
+    fn csrf(&self) -> &'static [&'static str] {
+      &["csrf", "csrf_token"]
+    }
+  }
+
+  struct AppInfo;
+
+  impl Endpoint for AppInfo {
+    type Response = Empty;
+
+    fn host(&self) -> Host {
+      Host::App
+    }
+
+    fn path(&self) -> String {
+      "/x/v2/account/myinfo".to_string()
+    }
+
+    fn query(&self) -> crate::Result<Params> {
+      Ok(Params::new().with("build", 6731100))
+    }
+
+    fn auth(&self) -> AuthKind {
+      AuthKind::App
+    }
+
+    fn signing(&self) -> Signing {
+      Signing::App(None)
+    }
+  }
+
+  fn recording_client() -> (Client, Arc<Mutex<Vec<HttpRequest>>>) {
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let client = {
+      let sent = Arc::clone(&sent);
+      Client::builder()
+        .transport(move |request: HttpRequest| {
+          sent.lock().unwrap().push(request);
+          Ok(HttpResponse::new(
+            StatusCode::OK,
+            r#"{"code":0,"message":"0","data":{}}"#,
+          ))
+        })
+        .build()
+    };
+    (client, sent)
+  }
+
+  #[test]
+  fn test_csrf_goes_into_form() {
+    let (client, sent) = recording_client();
+    let credential = Credential::new("sess".to_string(), "jct".to_string());
+    client.call(&Wear { medal_id: 42 }, &credential).unwrap();
+
+    let request = &sent.lock().unwrap()[0];
+    assert_eq!(Method::POST, request.method);
+    assert_eq!(None, request.url.query());
+    assert_eq!("medal_id=42&csrf=jct&csrf_token=jct", request.body_text());
+    assert_eq!(
+      "application/x-www-form-urlencoded",
+      request.headers[CONTENT_TYPE]
+    );
+    assert!(request.headers[COOKIE]
+      .to_str()
+      .unwrap()
+      .contains("SESSDATA=sess"));
+  }
+
+  #[test]
+  fn test_missing_credential() {
+    let (client, sent) = recording_client();
+    let result = client.call(&Wear { medal_id: 42 }, Auth::None);
+    assert!(matches!(result, Err(Error::Credential(_))));
+    let app = AppCredential::new("token".to_string());
+    let result = client.call(&Wear { medal_id: 42 }, &app);
+    assert!(matches!(result, Err(Error::Credential(_))));
+    assert!(sent.lock().unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_app_signed_query() {
+    let (client, sent) = recording_client();
+    let credential = AppCredential::new("token".to_string());
+    client.call(&AppInfo, &credential).unwrap();
+
+    let request = &sent.lock().unwrap()[0];
+    let query = request.url.query().unwrap();
+    assert!(query.starts_with("access_key=token&appkey=1d8b6e7d45233436&build=6731100&ts="));
+    assert!(query.contains("&sign="));
+    assert_eq!(None, request.headers.get(COOKIE));
+  }
+
+  #[test]
+  fn test_params_from_serialize() {
+    #[derive(Serialize)]
+    struct Query {
+      mid: i64,
+      #[serde(skip_serializing_if = "Option::is_none")]
+      reply: Option<i64>,
+      keyword: &'static str,
+    }
+    let params = Params::from_serialize(&Query {
+      mid: 2,
+      reply: None,
+      keyword: "a&b",
+    })
+    .unwrap();
+    assert_eq!(Some("a&b"), params.get("keyword"));
+    assert_eq!("mid=2&keyword=a%26b", params.encode());
+  }
+}
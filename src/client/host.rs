@@ -0,0 +1,116 @@
+//! Base hosts of the Bilibili API domains. Every API function resolves its URL
+//! through the [Hosts] of the client, so that requests can be redirected, e.g., to
+//! a local stand-in server in tests.
+
+/// Bilibili API所在的域名
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Host {
+  /// https://api.bilibili.com
+  Api,
+  /// https://api.live.bilibili.com
+  ApiLive,
+  /// https://passport.bilibili.com
+  Passport,
+  /// https://www.bilibili.com
+  Www,
+  /// https://app.bilibili.com
+  App,
+}
+
+impl Host {
+  pub fn default_base(self) -> &'static str {
+    match self {
+      Host::Api => "https://api.bilibili.com",
+      Host::ApiLive => "https://api.live.bilibili.com",
+      Host::Passport => "https://passport.bilibili.com",
+      Host::Www => "https://www.bilibili.com",
+      Host::App => "https://app.bilibili.com",
+    }
+  }
+}
+
+/// The base URL used for each [Host]. Defaults to the official Bilibili domains.
+#[derive(Debug, Clone)]
+pub struct Hosts {
+  api: String,
+  api_live: String,
+  passport: String,
+  www: String,
+  app: String,
+}
+
+impl Default for Hosts {
+  fn default() -> Self {
+    Self {
+      api: Host::Api.default_base().to_string(),
+      api_live: Host::ApiLive.default_base().to_string(),
+      passport: Host::Passport.default_base().to_string(),
+      www: Host::Www.default_base().to_string(),
+      app: Host::App.default_base().to_string(),
+    }
+  }
+}
+
+impl Hosts {
+  pub fn get(&self, host: Host) -> &str {
+    match host {
+      Host::Api => &self.api,
+      Host::ApiLive => &self.api_live,
+      Host::Passport => &self.passport,
+      Host::Www => &self.www,
+      Host::App => &self.app,
+    }
+  }
+
+  /// Override the base URL of `host`, e.g., `http://127.0.0.1:8080`.
+  /// Trailing slashes are ignored.
+  pub fn set(&mut self, host: Host, base: impl Into<String>) -> &mut Self {
+    let mut base = base.into();
+    while base.ends_with('/') {
+      base.pop();
+    }
+    match host {
+      Host::Api => self.api = base,
+      Host::ApiLive => self.api_live = base,
+      Host::Passport => self.passport = base,
+      Host::Www => self.www = base,
+      Host::App => self.app = base,
+    }
+    self
+  }
+
+  /// Join the base URL of `host` with an absolute API path.
+  pub fn url(&self, host: Host, path: &str) -> String {
+    format!("{}{}", self.get(host), path)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Host, Hosts};
+
+  #[test]
+  fn test_default_hosts() {
+    let hosts = Hosts::default();
+    assert_eq!(
+      "https://api.live.bilibili.com/room/v1/Room/get_info",
+      hosts.url(Host::ApiLive, "/room/v1/Room/get_info")
+    );
+    assert_eq!(
+      "https://api.bilibili.com/x/web-interface/nav",
+      hosts.url(Host::Api, "/x/web-interface/nav")
+    );
+  }
+
+  #[test]
+  fn test_override_host() {
+    let mut hosts = Hosts::default();
+    hosts.set(Host::Api, "http://127.0.0.1:8080/");
+    assert_eq!(
+      "http://127.0.0.1:8080/x/space/myinfo",
+      hosts.url(Host::Api, "/x/space/myinfo")
+    );
+    // Other hosts are untouched
+    assert_eq!("https://passport.bilibili.com", hosts.get(Host::Passport));
+  }
+}
@@ -0,0 +1,150 @@
+use std::{
+  collections::{BTreeMap, HashMap},
+  sync::{Arc, Mutex},
+  time::Duration,
+};
+
+use super::middleware::{Middleware, Outcome, RequestContext};
+use super::transport::HttpResponse;
+
+/// Upper bounds of the buckets of a [LatencyHistogram]
+pub const LATENCY_BUCKETS: [Duration; 8] = [
+  Duration::from_millis(50),
+  Duration::from_millis(100),
+  Duration::from_millis(250),
+  Duration::from_millis(500),
+  Duration::from_secs(1),
+  Duration::from_millis(2500),
+  Duration::from_secs(5),
+  Duration::from_secs(10),
+];
+
+/// Latencies of the requests to an endpoint that ended with the same code
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+  /// Non-cumulative counts of [LATENCY_BUCKETS], plus one for the slower ones
+  counts: [u64; LATENCY_BUCKETS.len() + 1],
+  sum: Duration,
+}
+
+impl LatencyHistogram {
+  pub fn observe(&mut self, latency: Duration) {
+    let bucket = LATENCY_BUCKETS
+      .iter()
+      .position(|bound| latency <= *bound)
+      .unwrap_or(LATENCY_BUCKETS.len());
+    self.counts[bucket] += 1;
+    self.sum += latency;
+  }
+
+  /// Number of requests
+  pub fn count(&self) -> u64 {
+    self.counts.iter().sum()
+  }
+
+  /// Total latency of all requests
+  pub fn sum(&self) -> Duration {
+    self.sum
+  }
+
+  /// (upper bound, number of requests in the bucket), ending with `(None, n)` for the
+  /// requests slower than all bounds
+  pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+    LATENCY_BUCKETS
+      .iter()
+      .copied()
+      .map(Some)
+      .chain([None])
+      .zip(self.counts.iter().copied())
+  }
+}
+
+/// What [Metrics] are grouped by
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MetricsKey {
+  /// See [RequestContext::endpoint]
+  pub endpoint: String,
+  /// See [Outcome::code]. None for failed requests and non-JSON responses.
+  pub code: Option<i32>,
+}
+
+/// A [Middleware] counting requests and their latencies per endpoint and Bilibili code.
+///
+/// ```no_run
+/// use bili_api_rs::client::{Client, Metrics};
+///
+/// let metrics = Metrics::new();
+/// let client = Client::builder().middleware(metrics.clone()).build();
+/// // ...
+/// for (key, histogram) in metrics.snapshot() {
+///   println!("{} {:?}: {} in {:?}", key.endpoint, key.code, histogram.count(), histogram.sum());
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+  histograms: Arc<Mutex<HashMap<MetricsKey, LatencyHistogram>>>,
+}
+
+impl Metrics {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn snapshot(&self) -> BTreeMap<MetricsKey, LatencyHistogram> {
+    let histograms = self.histograms.lock().expect("Lock poisoned");
+    histograms
+      .iter()
+      .map(|(key, histogram)| (key.clone(), histogram.clone()))
+      .collect()
+  }
+
+  pub fn reset(&self) {
+    self.histograms.lock().expect("Lock poisoned").clear();
+  }
+
+  fn observe(&self, context: &RequestContext, outcome: &Outcome) {
+    let key = MetricsKey {
+      endpoint: context.endpoint.clone(),
+      code: outcome.code,
+    };
+    self
+      .histograms
+      .lock()
+      .expect("Lock poisoned")
+      .entry(key)
+      .or_default()
+      .observe(outcome.latency);
+  }
+}
+
+impl Middleware for Metrics {
+  fn on_response(&self, context: &RequestContext, outcome: &Outcome, _: &mut HttpResponse) {
+    self.observe(context, outcome);
+  }
+
+  fn on_error(&self, context: &RequestContext, outcome: &Outcome, _: &crate::Error) {
+    self.observe(context, outcome);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::LatencyHistogram;
+
+  #[test]
+  fn test_histogram() {
+    let mut histogram = LatencyHistogram::default();
+    for ms in [10, 50, 51, 700, 60_000] {
+      histogram.observe(Duration::from_millis(ms));
+    }
+    assert_eq!(5, histogram.count());
+    assert_eq!(Duration::from_millis(60_811), histogram.sum());
+    let buckets: Vec<_> = histogram.buckets().collect();
+    assert_eq!((Some(Duration::from_millis(50)), 2), buckets[0]);
+    assert_eq!((Some(Duration::from_millis(100)), 1), buckets[1]);
+    assert_eq!((Some(Duration::from_secs(1)), 1), buckets[4]);
+    assert_eq!((None, 1), buckets[8]);
+  }
+}
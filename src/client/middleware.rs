@@ -0,0 +1,357 @@
+//! Hooks around every request a client sends, e.g., for logging or metrics.
+//!
+//! [Middleware]s see requests after cookies have been attached and before they are handed
+//! to the [Transport](super::Transport), and responses as they come back from it. Retried
+//! requests go through the chain once per attempt. With the `tracing` feature, each request
+//! is also sent within a `bili_request` span.
+
+use std::{
+  fmt,
+  sync::Arc,
+  time::{Duration, Instant},
+};
+
+use reqwest::{Method, StatusCode};
+
+#[cfg(feature = "async")]
+use super::transport::TransportFuture;
+use super::transport::{HttpRequest, HttpResponse};
+
+/// The request a [Middleware] is called for
+#[derive(Debug, Clone)]
+pub struct RequestContext {
+  pub method: Method,
+  /// The path of the API, e.g., `/x/space/myinfo`
+  pub endpoint: String,
+}
+
+impl RequestContext {
+  fn of(request: &HttpRequest) -> Self {
+    Self {
+      method: request.method.clone(),
+      endpoint: request.url.path().to_string(),
+    }
+  }
+}
+
+/// How a request went, as received from the transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outcome {
+  pub latency: Duration,
+  /// None if no response was received
+  pub status: Option<StatusCode>,
+  /// Bilibili的业务错误码, i.e., `code` of the response body. None if no response was
+  /// received or the body is not Bilibili's JSON envelope.
+  pub code: Option<i32>,
+}
+
+/// Observes and modifies the requests and responses of a client, see
+/// [ClientBuilder::middleware](super::ClientBuilder::middleware).
+///
+/// Requests go through the middlewares in the order they were added, responses in reverse.
+pub trait Middleware: Send + Sync {
+  fn on_request(&self, _context: &RequestContext, _request: &mut HttpRequest) {}
+
+  fn on_response(
+    &self,
+    _context: &RequestContext,
+    _outcome: &Outcome,
+    _response: &mut HttpResponse,
+  ) {
+  }
+
+  /// Called instead of [Middleware::on_response] if the transport failed
+  fn on_error(&self, _context: &RequestContext, _outcome: &Outcome, _error: &crate::Error) {}
+}
+
+impl<T: Middleware + ?Sized> Middleware for Arc<T> {
+  fn on_request(&self, context: &RequestContext, request: &mut HttpRequest) {
+    (**self).on_request(context, request)
+  }
+
+  fn on_response(&self, context: &RequestContext, outcome: &Outcome, response: &mut HttpResponse) {
+    (**self).on_response(context, outcome, response)
+  }
+
+  fn on_error(&self, context: &RequestContext, outcome: &Outcome, error: &crate::Error) {
+    (**self).on_error(context, outcome, error)
+  }
+}
+
+impl fmt::Debug for dyn Middleware {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("Middleware")
+  }
+}
+
+/// `code` of a JSON body
+fn bili_code(response: &HttpResponse) -> Option<i32> {
+  #[derive(serde::Deserialize)]
+  struct Envelope {
+    code: i32,
+  }
+  serde_json::from_slice::<Envelope>(&response.body)
+    .ok()
+    .map(|e| e.code)
+}
+
+/// The middlewares of a client
+#[derive(Clone, Default)]
+pub(crate) struct Middlewares(Vec<Arc<dyn Middleware>>);
+
+impl Middlewares {
+  pub fn new(middlewares: Vec<Arc<dyn Middleware>>) -> Self {
+    Self(middlewares)
+  }
+
+  fn before(&self, request: &mut HttpRequest) -> RequestContext {
+    let context = RequestContext::of(request);
+    for middleware in &self.0 {
+      middleware.on_request(&context, request);
+    }
+    context
+  }
+
+  fn after(
+    &self,
+    context: &RequestContext,
+    mut result: crate::Result<HttpResponse>,
+    latency: Duration,
+    #[cfg(feature = "tracing")] span: &tracing::Span,
+  ) -> crate::Result<HttpResponse> {
+    let outcome = Outcome {
+      latency,
+      status: result.as_ref().ok().map(HttpResponse::status),
+      code: result.as_ref().ok().and_then(bili_code),
+    };
+    #[cfg(feature = "tracing")]
+    trace_outcome(span, &outcome, &result);
+    match &mut result {
+      Ok(response) => {
+        for middleware in self.0.iter().rev() {
+          middleware.on_response(context, &outcome, response);
+        }
+      }
+      Err(error) => {
+        for middleware in self.0.iter().rev() {
+          middleware.on_error(context, &outcome, error);
+        }
+      }
+    }
+    result
+  }
+
+  /// Runs `request` through the middlewares around `send`
+  pub fn send(
+    &self,
+    mut request: HttpRequest,
+    send: impl FnOnce(HttpRequest) -> crate::Result<HttpResponse>,
+  ) -> crate::Result<HttpResponse> {
+    let context = self.before(&mut request);
+    #[cfg(feature = "tracing")]
+    let span = request_span(&context);
+    let start = Instant::now();
+    #[cfg(feature = "tracing")]
+    let result = span.in_scope(|| send(request));
+    #[cfg(not(feature = "tracing"))]
+    let result = send(request);
+    self.after(
+      &context,
+      result,
+      start.elapsed(),
+      #[cfg(feature = "tracing")]
+      &span,
+    )
+  }
+
+  /// Async version of [Middlewares::send]
+  #[cfg(feature = "async")]
+  pub async fn send_async<'a>(
+    &self,
+    mut request: HttpRequest,
+    send: impl FnOnce(HttpRequest) -> TransportFuture<'a>,
+  ) -> crate::Result<HttpResponse> {
+    let context = self.before(&mut request);
+    #[cfg(feature = "tracing")]
+    let span = request_span(&context);
+    let start = Instant::now();
+    #[cfg(feature = "tracing")]
+    let result = tracing::Instrument::instrument(send(request), span.clone()).await;
+    #[cfg(not(feature = "tracing"))]
+    let result = send(request).await;
+    self.after(
+      &context,
+      result,
+      start.elapsed(),
+      #[cfg(feature = "tracing")]
+      &span,
+    )
+  }
+}
+
+#[cfg(feature = "tracing")]
+fn request_span(context: &RequestContext) -> tracing::Span {
+  tracing::debug_span!(
+    "bili_request",
+    method = %context.method,
+    endpoint = %context.endpoint,
+    status = tracing::field::Empty,
+    code = tracing::field::Empty,
+    latency_ms = tracing::field::Empty,
+  )
+}
+
+#[cfg(feature = "tracing")]
+fn trace_outcome(span: &tracing::Span, outcome: &Outcome, result: &crate::Result<HttpResponse>) {
+  let latency_ms = outcome.latency.as_millis() as u64;
+  span.record("latency_ms", latency_ms);
+  if let Some(status) = outcome.status {
+    span.record("status", status.as_u16());
+  }
+  if let Some(code) = outcome.code {
+    span.record("code", code);
+  }
+  let _enter = span.enter();
+  match result {
+    Ok(_) => {
+      tracing::debug!(latency_ms, status = ?outcome.status, code = ?outcome.code, "response")
+    }
+    Err(e) => tracing::warn!(latency_ms, error = %e, "request failed"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+
+  use reqwest::{header::HeaderValue, StatusCode};
+
+  use super::*;
+  use crate::{
+    apis::live::info::get_room_init_info, client::Metrics, utils::assert_error_code, Client, Error,
+  };
+
+  const NOT_EXIST: &str =
+    r#"{"code":60004,"msg":"直播间不存在","message":"直播间不存在","data":{}}"#;
+
+  /// Records the calls it gets, tagged with `name`
+  struct Log {
+    name: &'static str,
+    calls: Arc<Mutex<Vec<String>>>,
+  }
+
+  impl Middleware for Log {
+    fn on_request(&self, context: &RequestContext, request: &mut HttpRequest) {
+      request
+        .headers
+        .insert("x-middleware", HeaderValue::from_static(self.name));
+      self.calls.lock().unwrap().push(format!(
+        "{} request {} {}",
+        self.name, context.method, context.endpoint
+      ));
+    }
+
+    fn on_response(&self, _: &RequestContext, outcome: &Outcome, response: &mut HttpResponse) {
+      self.calls.lock().unwrap().push(format!(
+        "{} response {:?} {:?}",
+        self.name, outcome.status, outcome.code
+      ));
+      if self.name == "outer" {
+        response.body = r#"{"code":-404,"message":"啥都木有"}"#.into();
+      }
+    }
+
+    fn on_error(&self, _: &RequestContext, outcome: &Outcome, error: &Error) {
+      self.calls.lock().unwrap().push(format!(
+        "{} error {:?} {}",
+        self.name, outcome.status, error
+      ));
+    }
+  }
+
+  #[test]
+  fn test_middleware_order() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let client = {
+      let sent = Arc::clone(&sent);
+      Client::builder()
+        .middleware(Log {
+          name: "outer",
+          calls: Arc::clone(&calls),
+        })
+        .middleware(Log {
+          name: "inner",
+          calls: Arc::clone(&calls),
+        })
+        .transport(move |request: HttpRequest| {
+          sent.lock().unwrap().push(request);
+          Ok(HttpResponse::new(StatusCode::OK, NOT_EXIST))
+        })
+        .build()
+    };
+
+    // The outer middleware rewrites the response
+    assert_error_code(get_room_init_info(&client, 1), -404);
+    assert_eq!(
+      vec![
+        "outer request GET /room/v1/Room/room_init",
+        "inner request GET /room/v1/Room/room_init",
+        "inner response Some(200) Some(60004)",
+        "outer response Some(200) Some(60004)",
+      ],
+      *calls.lock().unwrap()
+    );
+    // The last middleware to modify the request wins
+    assert_eq!("inner", sent.lock().unwrap()[0].headers["x-middleware"]);
+  }
+
+  #[test]
+  fn test_middleware_on_error() {
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let client = Client::builder()
+      .retry_policy(crate::client::RetryPolicy::none())
+      .middleware(Log {
+        name: "log",
+        calls: Arc::clone(&calls),
+      })
+      .transport(|_: HttpRequest| Err(Error::Io(std::io::ErrorKind::TimedOut.into())))
+      .build();
+
+    assert!(get_room_init_info(&client, 1).is_err());
+    let calls = calls.lock().unwrap();
+    assert_eq!(2, calls.len());
+    assert!(calls[1].starts_with("log error None"));
+  }
+
+  #[test]
+  fn test_metrics() {
+    let metrics = Metrics::new();
+    let client = Client::builder()
+      .middleware(metrics.clone())
+      .transport(|request: HttpRequest| {
+        let body = match request.url.query() {
+          Some("id=1") => NOT_EXIST,
+          _ => "<html></html>",
+        };
+        Ok(HttpResponse::new(StatusCode::OK, body))
+      })
+      .build();
+    let _ = get_room_init_info(&client, 1);
+    let _ = get_room_init_info(&client, 1);
+    let _ = get_room_init_info(&client, 2);
+
+    let snapshot = metrics.snapshot();
+    let counts: Vec<_> = snapshot
+      .iter()
+      .map(|(key, histogram)| (key.endpoint.as_str(), key.code, histogram.count()))
+      .collect();
+    assert_eq!(
+      vec![
+        ("/room/v1/Room/room_init", None, 1),
+        ("/room/v1/Room/room_init", Some(60004), 2),
+      ],
+      counts
+    );
+  }
+}
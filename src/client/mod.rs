@@ -6,20 +6,65 @@ use std::{
   time::Duration,
 };
 
-use reqwest::{
-  blocking::{Request, RequestBuilder, Response},
-  IntoUrl,
+use self::{
+  bili_ticket::BiliTicketCache, credential_refresh::RefreshedCredentials, device::DeviceCookies,
+  middleware::Middlewares, rate_limiting::RateLimiter, transport::SharedTransport,
+  wbi_keys::WbiKeyCache,
+};
+use crate::{
+  apis::misc::{
+    buvid::Buvid,
+    sign::{app::AppKey, bili_ticket::BiliTicket, wbi::cached_mixin_key},
+  },
+  credential::Credential,
+  utils::now_millis,
+  Error,
 };
 
-use self::{config::LiveMsgConfig, rate_limiting::RateLimiter};
-
-mod config;
+#[cfg(feature = "async")]
+mod async_client;
+mod bili_ticket;
+mod builder;
+mod credential_refresh;
+mod device;
+mod endpoint;
+mod host;
+mod metrics;
+mod middleware;
+mod pagination;
 mod rate_limiting;
+mod record;
+mod retry;
+mod transport;
+mod wbi_keys;
+
+#[cfg(feature = "async")]
+pub use async_client::AsyncClient;
+pub use builder::ClientBuilder;
+pub use endpoint::{Auth, AuthKind, Body, Endpoint, Params, Signing};
+pub use host::{Host, Hosts};
+pub use metrics::{LatencyHistogram, Metrics, MetricsKey, LATENCY_BUCKETS};
+pub use middleware::{Middleware, Outcome, RequestContext};
+pub use pagination::{Page, Paginated, Paginator};
+#[cfg(feature = "danmaku-async")]
+pub(crate) use rate_limiting::TokenBucket;
+pub use rate_limiting::{RateLimit, RateLimitGroup};
+pub use record::{
+  Cassette, Interaction, RecordedRequest, RecordedResponse, RecordingTransport, ReplayTransport,
+  REDACTED,
+};
+pub use retry::{RetryClass, RetryPolicy};
+#[cfg(feature = "async")]
+pub use transport::{AsyncReqwestTransport, AsyncTransport, TransportFuture};
+pub use transport::{HttpRequest, HttpResponse, ReqwestTransport, Transport};
 
 #[derive(Clone)]
 pub struct Client {
   inner: ClientInner,
-  pub(crate) live_msg_config: LiveMsgConfig,
+  hosts: Hosts,
+  retry_policy: RetryPolicy,
+  pub(crate) live_msg_retry_policy: RetryPolicy,
+  app_key: AppKey,
 }
 
 impl Default for Client {
@@ -30,53 +75,268 @@ impl Default for Client {
 
 impl Client {
   pub fn new() -> Self {
-    const DEFAULT_LIMIT: Duration = Duration::from_millis(1000);
-    Self::with_rate_limit(DEFAULT_LIMIT)
+    Self::builder().build()
+  }
+
+  pub fn builder() -> ClientBuilder {
+    ClientBuilder::new()
   }
 
-  /// 自定义直播弹幕API限流
+  /// 自定义直播弹幕API限流, 参见[ClientBuilder::group_rate_limit]
   pub fn with_rate_limit(limit: Duration) -> Self {
-    let rate_limiter = RateLimiter::new(limit);
-    let inner = ClientInner {
-      client: reqwest::blocking::Client::new(),
-      rate_limiter: Arc::new(Mutex::new(rate_limiter)),
-    };
+    Self::builder().rate_limit(limit).build()
+  }
+
+  pub fn hosts(&self) -> &Hosts {
+    &self.hosts
+  }
+
+  pub fn retry_policy(&self) -> &RetryPolicy {
+    &self.retry_policy
+  }
+
+  /// APP端API签名所用的appkey
+  pub fn app_key(&self) -> &AppKey {
+    &self.app_key
+  }
+
+  /// The latest credential in place of `credential`. Differs from `credential` once the
+  /// client has refreshed it, see [ClientBuilder::auto_refresh_credential].
+  pub fn credential(&self, credential: &Credential) -> Credential {
+    self.inner.credentials.latest(credential)
+  }
+
+  /// 设备指纹Cookie, 参见[init_buvid](crate::apis::misc::buvid::init_buvid)
+  pub fn buvid(&self) -> Option<Buvid> {
+    self.inner.device.get()
+  }
+
+  /// 设置设备指纹Cookie, 之后的每个请求都会带上
+  pub fn set_buvid(&self, buvid: Buvid) {
+    self.inner.device.set(buvid);
+  }
+
+  /// `bili_ticket`, 参见[init_bili_ticket](crate::apis::misc::sign::bili_ticket::init_bili_ticket)
+  pub fn bili_ticket(&self) -> Option<BiliTicket> {
+    self.inner.bili_ticket.get()
+  }
+
+  /// 设置`bili_ticket`, 之后的每个请求都会带上, 直到过期
+  pub fn set_bili_ticket(&self, ticket: BiliTicket) {
+    self.inner.bili_ticket.set(ticket);
+  }
 
-    Self {
-      inner,
-      live_msg_config: LiveMsgConfig::with_duration_and_retry(Duration::from_secs(3), 1),
+  /// Calls `endpoint` with `auth`, which may be a [Credential], an
+  /// [AppCredential](crate::credential::AppCredential) or [Auth::None].
+  ///
+  /// An expired web credential is refreshed if enabled, failed attempts are retried under
+  /// the [RetryPolicy], and each attempt waits for the rate limit of the endpoint.
+  pub fn call<'a, E: Endpoint + ?Sized>(
+    &self,
+    endpoint: &E,
+    auth: impl Into<Auth<'a>>,
+  ) -> crate::Result<E::Response> {
+    let auth = auth.into();
+    endpoint::check_auth(endpoint, &auth)?;
+    match auth {
+      Auth::Web(credential) if endpoint.auto_refresh() => self
+        .with_credential(credential, |credential| {
+          self.call_once(endpoint, &Auth::Web(credential))
+        }),
+      auth => self.call_once(endpoint, &auth),
     }
   }
 }
 
 #[derive(Clone)]
 struct ClientInner {
-  client: reqwest::blocking::Client,
-  rate_limiter: Arc<Mutex<RateLimiter>>,
+  transport: SharedTransport,
+  middlewares: Middlewares,
+  rate_limiter: Arc<RateLimiter>,
+  wbi_keys: Arc<WbiKeyCache>,
+  credentials: Arc<RefreshedCredentials>,
+  device: Arc<DeviceCookies>,
+  bili_ticket: Arc<BiliTicketCache>,
+  /// Serializes refreshes so that a credential is refreshed only once
+  refresh_lock: Arc<Mutex<()>>,
 }
 
 impl Client {
-  pub(crate) fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
-    self.inner.client.get(url)
+  fn send(&self, mut request: HttpRequest) -> crate::Result<HttpResponse> {
+    if self.inner.device.begin_init() {
+      // Errors are covered by the locally generated fallback
+      let _ = crate::apis::misc::buvid::init_buvid(self);
+      self.inner.device.finish_init();
+    }
+    if self.inner.bili_ticket.begin_fetch() {
+      // Tried again a while later on failure
+      let _ = crate::apis::misc::sign::bili_ticket::init_bili_ticket(self);
+      self.inner.bili_ticket.finish_fetch();
+    }
+    self.inner.device.attach(&mut request.headers);
+    self.inner.bili_ticket.attach(&mut request.headers);
+    self
+      .inner
+      .middlewares
+      .send(request, |request| self.inner.transport.send(request))
+  }
+
+  pub(crate) fn wbi_keys(&self) -> &WbiKeyCache {
+    &self.inner.wbi_keys
+  }
+
+  /// Blocks until the rate limit of `group` allows another request
+  pub(crate) fn block_till_ready(&self, group: RateLimitGroup) {
+    self.inner.rate_limiter.block_till_ready(group);
   }
 
-  pub(crate) fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
-    self.inner.client.post(url)
+  /// Runs an authenticated `request` with the latest credential in place of `credential`.
+  /// If auto refresh is enabled and the credential turns out to be expired, refreshes it
+  /// and tries once more.
+  pub(crate) fn with_credential<T>(
+    &self,
+    credential: &Credential,
+    mut request: impl FnMut(&Credential) -> crate::Result<T>,
+  ) -> crate::Result<T> {
+    let credential = self.credential(credential);
+    match request(&credential) {
+      Err(e) if self.inner.credentials.should_refresh(&e, &credential) => {
+        let refreshed = self.refresh_credential(&credential)?;
+        request(&refreshed)
+      }
+      result => result,
+    }
+  }
+
+  fn refresh_credential(&self, stale: &Credential) -> crate::Result<Credential> {
+    let _guard = self.inner.refresh_lock.lock().expect("Lock poisoned");
+    // Another request may have refreshed it while we were waiting
+    let latest = self.credential(stale);
+    if latest.sessdata != stale.sessdata {
+      return Ok(latest);
+    }
+
+    let refreshed =
+      crate::apis::login::cookie_refresh::refresh_credential(self, stale, now_millis())?;
+    self.inner.credentials.record(stale, &refreshed);
+    Ok(refreshed)
   }
 
-  pub(crate) fn execute(&self, request: Request) -> reqwest::Result<Response> {
-    self.inner.client.execute(request)
+  /// [Client::call] with the credential settled
+  fn call_once<E: Endpoint + ?Sized>(
+    &self,
+    endpoint: &E,
+    auth: &Auth,
+  ) -> crate::Result<E::Response> {
+    let group = endpoint.rate_limit_group();
+    let policy = match group {
+      RateLimitGroup::LiveDanmaku => &self.live_msg_retry_policy,
+      _ => &self.retry_policy,
+    };
+    let policy = policy.for_endpoint(endpoint);
+    let wbi = endpoint.signing() == Signing::Wbi;
+    let attempt = || {
+      let mixin_key = match auth.web() {
+        Some(credential) if wbi => Some(cached_mixin_key(self, credential)?),
+        _ => None,
+      };
+      let request = endpoint::build_request(
+        endpoint,
+        &self.hosts,
+        &self.app_key,
+        auth,
+        mixin_key.as_deref(),
+      )?;
+      self.block_till_ready(group);
+      endpoint.parse(self.send(request)?)
+    };
+    self.with_retry_policy(&policy, || match attempt() {
+      // The cached key has likely been rotated
+      Err(Error::Api(e)) if wbi && e.kind().is_risk_control() => {
+        self.wbi_keys().invalidate();
+        attempt()
+      }
+      result => result,
+    })
   }
 
-  pub(crate) fn block_till_ready(&self) {
-    let mut guard = self.inner.rate_limiter.lock().expect("Lock poisoned");
-    guard.block_till_ready();
+  pub(crate) fn with_retry_policy<T>(
+    &self,
+    policy: &RetryPolicy,
+    mut request: impl FnMut() -> crate::Result<T>,
+  ) -> crate::Result<T> {
+    let mut attempt = 1;
+    loop {
+      match request() {
+        Err(e) => match policy.next_delay(&e, attempt) {
+          Some(delay) => {
+            std::thread::sleep(delay);
+            attempt += 1;
+          }
+          None => return Err(e),
+        },
+        result => return result,
+      }
+    }
   }
 }
 
 #[cfg(test)]
 mod tests {
-  use crate::client::Client;
+  use std::time::Duration;
+
+  use crate::{
+    apis::live::{info::get_room_init_info, user::wear_medal},
+    client::{Client, Host, RetryClass, RetryPolicy},
+    credential::extract_credential::get_fake_credential,
+    utils::{assert_error_code, mock_server::MockServer},
+  };
+
+  #[test]
+  fn test_retry_policy_applies_to_api_functions() {
+    let server = MockServer::start(&[(
+      "/room/v1/Room/room_init",
+      r#"{"code":-503,"message":"过载保护,服务暂不可用"}"#,
+    )]);
+    let mut policy = RetryPolicy::none();
+    policy
+      .max_attempts(3)
+      .base_delay(Duration::from_millis(1))
+      .retry_on(RetryClass::ServerError);
+    let agent = Client::builder()
+      .host(Host::ApiLive, server.base_url())
+      .retry_policy(policy)
+      .build();
+
+    assert_error_code(get_room_init_info(&agent, 1029), -503);
+    assert_eq!(3, server.received().len());
+
+    // Errors outside of the enabled classes are returned right away
+    let agent = Client::builder()
+      .host(Host::ApiLive, server.base_url())
+      .retry_policy(RetryPolicy::none())
+      .build();
+    assert_error_code(get_room_init_info(&agent, 1029), -503);
+    assert_eq!(4, server.received().len());
+  }
+
+  #[test]
+  fn test_retry_policy_spares_non_idempotent_requests() {
+    let server = MockServer::start(&[(
+      "/xlive/web-room/v1/fansMedal/wear",
+      r#"{"code":-503,"message":"过载保护,服务暂不可用"}"#,
+    )]);
+    let mut policy = RetryPolicy::default();
+    policy.base_delay(Duration::from_millis(1));
+    let agent = Client::builder()
+      .host(Host::ApiLive, server.base_url())
+      .retry_policy(policy)
+      .build();
+
+    // The medal may have been worn before the server failed
+    assert_error_code(wear_medal(&agent, 42, &get_fake_credential()), -503);
+    assert_eq!(1, server.received().len());
+  }
 
   #[test]
   pub fn test_client_send() {
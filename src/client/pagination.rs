@@ -0,0 +1,267 @@
+//! Iterating over list APIs page by page. A [Paginated] endpoint says how to get from one
+//! page to the next, and [Client::paginate](crate::Client::paginate) turns it into an
+//! [Iterator] of the items of all pages, fetching each page when the previous one has
+//! been consumed.
+
+use std::collections::VecDeque;
+
+#[cfg(feature = "async")]
+use futures_util::Stream;
+
+#[cfg(feature = "async")]
+use super::AsyncClient;
+use super::{
+  endpoint::{Auth, Endpoint},
+  Client,
+};
+
+/// The items of a page and where the next page starts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Page<T, C> {
+  pub items: Vec<T>,
+  /// None on the last page
+  pub next: Option<C>,
+}
+
+impl<T> Page<T, i32> {
+  /// 按页码分页: `page`从1开始, 直到`total_pages`
+  pub fn numbered(items: Vec<T>, page: i32, total_pages: i32) -> Self {
+    Self {
+      items,
+      next: (page < total_pages).then_some(page + 1),
+    }
+  }
+}
+
+impl<T, C> Page<T, C> {
+  /// 按游标/偏移量分页: 下一页从上一页下发的`cursor`开始, 直到`has_more`为false
+  pub fn cursor(items: Vec<T>, cursor: C, has_more: bool) -> Self {
+    Self {
+      items,
+      next: has_more.then_some(cursor),
+    }
+  }
+}
+
+/// An [Endpoint] returning one page of a list
+pub trait Paginated: Endpoint + Sized {
+  type Item;
+  /// Where a page starts, e.g., a page number or an offset handed out by the previous page
+  type Cursor;
+
+  /// The same endpoint for the page at `cursor`
+  fn at(&self, cursor: Self::Cursor) -> Self;
+
+  fn page(&self, response: Self::Response) -> Page<Self::Item, Self::Cursor>;
+}
+
+/// Turns a fetched page into its items and the endpoint of the next page. An empty page
+/// ends the list as well, in case the API keeps handing out cursors.
+fn split<E: Paginated>(endpoint: &E, response: E::Response) -> (Vec<E::Item>, Option<E>) {
+  let page = endpoint.page(response);
+  let next = match page.items.is_empty() {
+    true => None,
+    false => page.next.map(|cursor| endpoint.at(cursor)),
+  };
+  (page.items, next)
+}
+
+/// The items of all pages of a [Paginated] endpoint, see [Client::paginate].
+///
+/// Yields the error and stops if a page fails to load, after the retries of the client.
+pub struct Paginator<'a, E: Paginated> {
+  client: &'a Client,
+  auth: Auth<'a>,
+  next: Option<E>,
+  items: VecDeque<E::Item>,
+}
+
+impl<'a, E: Paginated> Iterator for Paginator<'a, E> {
+  type Item = crate::Result<E::Item>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    loop {
+      if let Some(item) = self.items.pop_front() {
+        return Some(Ok(item));
+      }
+      let endpoint = self.next.take()?;
+      match self.client.call(&endpoint, self.auth) {
+        Ok(response) => {
+          let (items, next) = split(&endpoint, response);
+          self.items.extend(items);
+          self.next = next;
+        }
+        Err(e) => return Some(Err(e)),
+      }
+    }
+  }
+}
+
+impl Client {
+  /// Iterates over the items of all pages of `endpoint`, starting at the page it points to
+  pub fn paginate<'a, E: Paginated>(
+    &'a self,
+    endpoint: E,
+    auth: impl Into<Auth<'a>>,
+  ) -> Paginator<'a, E> {
+    Paginator {
+      client: self,
+      auth: auth.into(),
+      next: Some(endpoint),
+      items: VecDeque::new(),
+    }
+  }
+}
+
+#[cfg(feature = "async")]
+impl AsyncClient {
+  /// Async version of [Client::paginate], as a [Stream]
+  pub fn paginate<'a, E>(
+    &'a self,
+    endpoint: E,
+    auth: impl Into<Auth<'a>>,
+  ) -> impl Stream<Item = crate::Result<E::Item>> + 'a
+  where
+    E: Paginated + Sync + 'a,
+    E::Item: 'a,
+  {
+    let auth = auth.into();
+    let state = (Some(endpoint), VecDeque::new());
+    futures_util::stream::unfold(state, move |(mut next, mut items)| async move {
+      loop {
+        if let Some(item) = items.pop_front() {
+          return Some((Ok(item), (next, items)));
+        }
+        let endpoint: E = next.take()?;
+        match self.call(&endpoint, auth).await {
+          Ok(response) => {
+            let (page, after) = split(&endpoint, response);
+            items.extend(page);
+            next = after;
+          }
+          Err(e) => return Some((Err(e), (None, items))),
+        }
+      }
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use reqwest::StatusCode;
+
+  use super::*;
+  use crate::client::{Host, HttpRequest, HttpResponse, Params};
+
+  #[derive(Debug, serde::Deserialize)]
+  struct Numbers {
+    data: Vec<i32>,
+    next: Option<String>,
+  }
+
+  /// A cursor style list of 1..=5, two numbers per page
+  #[derive(Clone)]
+  struct ListNumbers {
+    offset: String,
+  }
+
+  impl Endpoint for ListNumbers {
+    type Response = Numbers;
+
+    fn host(&self) -> Host {
+      Host::Api
+    }
+
+    fn path(&self) -> String {
+      "/numbers".to_string()
+    }
+
+    fn query(&self) -> crate::Result<Params> {
+      Ok(Params::new().with("offset", &self.offset))
+    }
+  }
+
+  impl Paginated for ListNumbers {
+    type Item = i32;
+    type Cursor = String;
+
+    fn at(&self, offset: String) -> Self {
+      Self { offset }
+    }
+
+    fn page(&self, response: Numbers) -> Page<i32, String> {
+      let has_more = response.next.is_some();
+      Page::cursor(response.data, response.next.unwrap_or_default(), has_more)
+    }
+  }
+
+  fn numbers(request: HttpRequest) -> crate::Result<HttpResponse> {
+    let body = match request.url.query() {
+      Some("offset=") => r#"{"code":0,"data":[1,2],"next":"a"}"#,
+      Some("offset=a") => r#"{"code":0,"data":[3,4],"next":"b"}"#,
+      Some("offset=b") => r#"{"code":0,"data":[5],"next":null}"#,
+      _ => r#"{"code":-400,"message":"请求错误"}"#,
+    };
+    Ok(HttpResponse::new(StatusCode::OK, body))
+  }
+
+  #[test]
+  fn test_numbered_page() {
+    assert_eq!(Some(2), Page::numbered(vec![1], 1, 3).next);
+    assert_eq!(None, Page::numbered(vec![1], 3, 3).next);
+    assert_eq!(None, Page::numbered(Vec::<i32>::new(), 1, 0).next);
+  }
+
+  #[test]
+  fn test_paginate_cursor() {
+    let client = Client::builder().transport(numbers).build();
+    let start = ListNumbers {
+      offset: String::new(),
+    };
+    let all: Vec<_> = client
+      .paginate(start, Auth::None)
+      .collect::<crate::Result<_>>()
+      .unwrap();
+    assert_eq!(vec![1, 2, 3, 4, 5], all);
+  }
+
+  #[test]
+  fn test_paginate_stops_on_error() {
+    let client = Client::builder()
+      .retry_policy(crate::client::RetryPolicy::none())
+      .transport(numbers)
+      .build();
+    let start = ListNumbers {
+      offset: "x".to_string(),
+    };
+    let mut pages = client.paginate(start, Auth::None);
+    assert!(pages.next().unwrap().is_err());
+    assert!(pages.next().is_none());
+  }
+
+  #[cfg(feature = "async")]
+  #[tokio::test]
+  async fn test_paginate_stream() {
+    use futures_util::TryStreamExt;
+
+    use crate::client::{AsyncTransport, TransportFuture};
+
+    struct Fake;
+    impl AsyncTransport for Fake {
+      fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        Box::pin(async move { numbers(request) })
+      }
+    }
+
+    let client = AsyncClient::builder().async_transport(Fake).build_async();
+    let start = ListNumbers {
+      offset: "a".to_string(),
+    };
+    let rest: Vec<i32> = client
+      .paginate(start, Auth::None)
+      .try_collect()
+      .await
+      .unwrap();
+    assert_eq!(vec![3, 4, 5], rest);
+  }
+}
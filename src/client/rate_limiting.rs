@@ -1,31 +1,212 @@
-use std::time::{Duration, Instant};
+use std::{
+  collections::HashMap,
+  sync::Mutex,
+  time::{Duration, Instant},
+};
 
-/// A Simple rate limiter which just enforces a configurable Duration between each consecutive calls.
-#[derive(Clone)]
-pub struct RateLimiter {
-  time_limit: Duration,
-  last_call: Option<Instant>,
+/// API分组, 每个分组使用独立的令牌桶限流
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RateLimitGroup {
+  /// 发送直播弹幕
+  LiveDanmaku,
+  /// 用户空间及其他需要WBI签名的API
+  Space,
+  /// 搜索API
+  Search,
+  /// 关注, 拉黑等关系写操作
+  RelationWrite,
+  /// 其他API
+  General,
 }
 
-impl RateLimiter {
-  pub fn new(time_limit: Duration) -> Self {
+/// Token bucket parameters: at most `burst` requests at once, refilled by one
+/// token every `refill_interval`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+  pub burst: u32,
+  pub refill_interval: Duration,
+}
+
+impl RateLimit {
+  pub fn new(burst: u32, refill_interval: Duration) -> Self {
+    Self {
+      burst: burst.max(1),
+      refill_interval,
+    }
+  }
+
+  /// One request per `interval`, without bursting
+  pub fn every(interval: Duration) -> Self {
+    Self::new(1, interval)
+  }
+}
+
+/// A token bucket that hands out reservations: callers take a token (possibly going
+/// into debt) while holding the lock, and sleep for the returned duration after releasing it.
+pub(crate) struct TokenBucket {
+  limit: RateLimit,
+  state: Mutex<BucketState>,
+}
+
+struct BucketState {
+  /// Available tokens, negative when waiters have reserved future tokens
+  tokens: f64,
+  last_refill: Instant,
+}
+
+impl TokenBucket {
+  pub fn new(limit: RateLimit) -> Self {
     Self {
-      time_limit,
-      last_call: None,
+      limit,
+      state: Mutex::new(BucketState {
+        tokens: limit.burst as f64,
+        last_refill: Instant::now(),
+      }),
     }
   }
+
+  /// Takes a token and returns how long the caller has to wait before using it
+  pub fn reserve(&self) -> Duration {
+    let interval = self.limit.refill_interval.as_secs_f64();
+    let mut state = self.state.lock().expect("Lock poisoned");
+
+    let now = Instant::now();
+    if interval > 0.0 {
+      let refilled = now.duration_since(state.last_refill).as_secs_f64() / interval;
+      state.tokens = (state.tokens + refilled).min(self.limit.burst as f64);
+    } else {
+      state.tokens = self.limit.burst as f64;
+    }
+    state.last_refill = now;
+
+    state.tokens -= 1.0;
+    if state.tokens >= 0.0 {
+      Duration::ZERO
+    } else {
+      Duration::from_secs_f64(-state.tokens * interval)
+    }
+  }
+}
+
+/// 按API分组的限流器. 未配置的分组不限流.
+pub(crate) struct RateLimiter {
+  buckets: HashMap<RateLimitGroup, TokenBucket>,
 }
 
 impl RateLimiter {
-  /// Blocks until next available
-  pub fn block_till_ready(&mut self) {
-    if let Some(last_call) = self.last_call.as_mut() {
-      let mut elapsed = last_call.elapsed();
-      while elapsed < self.time_limit {
-        std::thread::sleep(self.time_limit - elapsed);
-        elapsed = last_call.elapsed();
-      }
+  pub fn new(limits: &HashMap<RateLimitGroup, RateLimit>) -> Self {
+    let buckets = limits
+      .iter()
+      .map(|(&group, &limit)| (group, TokenBucket::new(limit)))
+      .collect();
+    Self { buckets }
+  }
+
+  /// Returns how long a request of `group` has to wait
+  pub fn reserve(&self, group: RateLimitGroup) -> Duration {
+    self
+      .buckets
+      .get(&group)
+      .map(TokenBucket::reserve)
+      .unwrap_or_default()
+  }
+
+  /// Blocks until a request of `group` is allowed
+  pub fn block_till_ready(&self, group: RateLimitGroup) {
+    let wait = self.reserve(group);
+    if !wait.is_zero() {
+      std::thread::sleep(wait);
+    }
+  }
+
+  /// Waits until a request of `group` is allowed without blocking the executor thread
+  #[cfg(feature = "async")]
+  pub async fn wait_till_ready(&self, group: RateLimitGroup) {
+    let wait = self.reserve(group);
+    if !wait.is_zero() {
+      tokio::time::sleep(wait).await;
+    }
+  }
+}
+
+/// 默认限流配置
+pub(crate) fn default_rate_limits() -> HashMap<RateLimitGroup, RateLimit> {
+  HashMap::from([
+    (
+      RateLimitGroup::LiveDanmaku,
+      RateLimit::every(Duration::from_millis(1000)),
+    ),
+    (
+      RateLimitGroup::Space,
+      RateLimit::new(5, Duration::from_millis(500)),
+    ),
+    (
+      RateLimitGroup::Search,
+      RateLimit::new(5, Duration::from_millis(1000)),
+    ),
+    (
+      RateLimitGroup::RelationWrite,
+      RateLimit::every(Duration::from_millis(2000)),
+    ),
+  ])
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+  };
+
+  use super::{RateLimit, RateLimitGroup, RateLimiter};
+
+  #[test]
+  fn test_burst_then_refill() {
+    let limiter = RateLimiter::new(&HashMap::from([(
+      RateLimitGroup::Space,
+      RateLimit::new(2, Duration::from_millis(100)),
+    )]));
+
+    assert_eq!(Duration::ZERO, limiter.reserve(RateLimitGroup::Space));
+    assert_eq!(Duration::ZERO, limiter.reserve(RateLimitGroup::Space));
+    // Bucket is empty, the next two have to wait for one and two refills
+    let third = limiter.reserve(RateLimitGroup::Space);
+    let fourth = limiter.reserve(RateLimitGroup::Space);
+    assert!(third > Duration::from_millis(90) && third <= Duration::from_millis(100));
+    assert!(fourth > Duration::from_millis(190) && fourth <= Duration::from_millis(200));
+
+    // Unconfigured groups are not limited
+    for _ in 0..10 {
+      assert_eq!(Duration::ZERO, limiter.reserve(RateLimitGroup::General));
     }
-    self.last_call = Some(Instant::now());
+  }
+
+  #[test]
+  fn test_groups_do_not_block_each_other() {
+    let limiter = Arc::new(RateLimiter::new(&HashMap::from([
+      (
+        RateLimitGroup::LiveDanmaku,
+        RateLimit::every(Duration::from_millis(300)),
+      ),
+      (
+        RateLimitGroup::Space,
+        RateLimit::every(Duration::from_millis(10)),
+      ),
+    ])));
+    limiter.block_till_ready(RateLimitGroup::LiveDanmaku);
+
+    let sleeper = {
+      let limiter = Arc::clone(&limiter);
+      std::thread::spawn(move || limiter.block_till_ready(RateLimitGroup::LiveDanmaku))
+    };
+    std::thread::sleep(Duration::from_millis(20));
+
+    // While the other thread waits on the danmaku bucket, space requests go through
+    let start = Instant::now();
+    limiter.block_till_ready(RateLimitGroup::Space);
+    assert!(start.elapsed() < Duration::from_millis(100));
+
+    sleeper.join().unwrap();
   }
 }
@@ -0,0 +1,774 @@
+//! Record and replay of the traffic of a client, for offline and deterministic tests.
+//!
+//! [RecordingTransport] sends requests through another transport and saves every
+//! request/response pair to a [Cassette] file, with cookies, csrf tokens and access tokens
+//! redacted. [ReplayTransport] answers requests with the responses of a cassette without
+//! touching the network. Set them up with [ClientBuilder::record](super::ClientBuilder::record)
+//! and [ClientBuilder::replay](super::ClientBuilder::replay).
+
+use std::{
+  collections::HashMap,
+  fs, io,
+  path::{Path, PathBuf},
+  sync::Mutex,
+};
+
+use reqwest::{
+  header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, COOKIE, SET_COOKIE, USER_AGENT},
+  StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[cfg(feature = "async")]
+use super::transport::{AsyncTransport, TransportFuture};
+use super::transport::{HttpRequest, HttpResponse, Transport};
+use crate::Error;
+
+/// Replaces secrets in cassettes
+pub const REDACTED: &str = "REDACTED";
+
+/// Query/form parameters, JSON keys and cookie names whose values are redacted
+const SECRET_NAMES: &[&str] = &[
+  "csrf",
+  "csrf_token",
+  "access_key",
+  "access_token",
+  "refresh_token",
+  "refresh_csrf",
+  "SESSDATA",
+  "bili_jct",
+  "DedeUserID__ckMd5",
+  "ac_time_value",
+  "sid",
+];
+
+/// Cookies whose presence, not value, tells requests apart when matching them. A logged-in
+/// credential carries `DedeUserID` as well, made-up ones in tests usually do not.
+const LOGIN_COOKIES: &[&str] = &["SESSDATA", "bili_jct", "DedeUserID"];
+
+/// Query and form parameters that differ between runs, ignored when matching requests
+const VOLATILE_PARAMS: &[&str] = &[
+  "ts",
+  "wts",
+  "w_rid",
+  "sign",
+  "rnd",
+  "hexsign",
+  "context[ts]",
+];
+
+/// Recorded interactions, saved as JSON
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+  pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+  pub fn load(path: impl AsRef<Path>) -> crate::Result<Self> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+  }
+
+  /// Writes the cassette to `path`, creating its directory if needed
+  pub fn save(&self, path: impl AsRef<Path>) -> crate::Result<()> {
+    let path = path.as_ref();
+    if let Some(dir) = path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    let mut json = serde_json::to_string_pretty(self)?;
+    json.push('\n');
+    fs::write(path, json)?;
+    Ok(())
+  }
+
+  /// Appends an interaction, redacting secrets
+  pub fn push(&mut self, request: &HttpRequest, response: &HttpResponse) {
+    self.interactions.push(Interaction {
+      request: RecordedRequest::from_request(request),
+      response: RecordedResponse::from_response(response),
+    });
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+  pub request: RecordedRequest,
+  pub response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+  pub method: String,
+  pub url: String,
+  /// Headers other than `User-Agent`, with cookie values redacted
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub headers: Vec<(String, String)>,
+  #[serde(default, skip_serializing_if = "String::is_empty")]
+  pub body: String,
+}
+
+impl RecordedRequest {
+  pub fn from_request(request: &HttpRequest) -> Self {
+    let headers = request
+      .headers
+      .iter()
+      .filter(|(name, _)| *name != USER_AGENT)
+      .map(|(name, value)| {
+        let value = value.to_str().unwrap_or_default();
+        let value = if name == COOKIE {
+          redact_cookies(value)
+        } else {
+          value.to_string()
+        };
+        (name.to_string(), value)
+      })
+      .collect();
+    let is_form = request.headers.get(CONTENT_TYPE).is_some_and(|v| {
+      v.as_bytes()
+        .starts_with(b"application/x-www-form-urlencoded")
+    });
+    let body = request.body_text();
+    let body = if is_form {
+      redact_form(&body)
+    } else {
+      match serde_json::from_str::<Value>(&body) {
+        Ok(mut json) => {
+          redact_json(&mut json);
+          json.to_string()
+        }
+        Err(_) => body,
+      }
+    };
+    Self {
+      method: request.method.to_string(),
+      url: redact_url(&request.url),
+      headers,
+      body,
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+  #[serde(default = "ok")]
+  pub status: u16,
+  /// With `Set-Cookie` values redacted
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub headers: Vec<(String, String)>,
+  /// JSON bodies are kept as JSON, others as a string
+  pub body: Value,
+}
+
+fn ok() -> u16 {
+  200
+}
+
+impl RecordedResponse {
+  pub fn from_response(response: &HttpResponse) -> Self {
+    let headers = response
+      .headers
+      .iter()
+      .map(|(name, value)| {
+        let value = value.to_str().unwrap_or_default();
+        let value = if name == SET_COOKIE {
+          redact_set_cookie(value)
+        } else {
+          value.to_string()
+        };
+        (name.to_string(), value)
+      })
+      .collect();
+    let body = match serde_json::from_slice::<Value>(&response.body) {
+      Ok(mut json) if json.is_object() || json.is_array() => {
+        redact_json(&mut json);
+        json
+      }
+      _ => Value::String(response.text()),
+    };
+    Self {
+      status: response.status.as_u16(),
+      headers,
+      body,
+    }
+  }
+
+  pub fn to_response(&self) -> HttpResponse {
+    let body = match &self.body {
+      Value::String(text) => text.clone(),
+      json => json.to_string(),
+    };
+    let mut response = HttpResponse::new(
+      StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK),
+      body,
+    );
+    response.headers = self
+      .headers
+      .iter()
+      .filter_map(|(name, value)| {
+        Some((
+          HeaderName::from_bytes(name.as_bytes()).ok()?,
+          HeaderValue::from_str(value).ok()?,
+        ))
+      })
+      .collect::<HeaderMap>();
+    response
+  }
+}
+
+fn is_secret(name: &str) -> bool {
+  SECRET_NAMES.contains(&name)
+}
+
+fn redact_pairs<'a>(pairs: impl Iterator<Item = (&'a str, &'a str)>) -> Vec<(String, String)> {
+  pairs
+    .map(|(name, value)| {
+      let value = if is_secret(name) { REDACTED } else { value };
+      (name.to_string(), value.to_string())
+    })
+    .collect()
+}
+
+fn redact_url(url: &Url) -> String {
+  if url.query().is_none() {
+    return url.to_string();
+  }
+  let pairs: Vec<_> = url.query_pairs().collect();
+  let pairs = redact_pairs(pairs.iter().map(|(k, v)| (k.as_ref(), v.as_ref())));
+  let mut url = url.clone();
+  url.query_pairs_mut().clear().extend_pairs(pairs);
+  url.to_string()
+}
+
+fn redact_form(body: &str) -> String {
+  let pairs: Vec<_> = url::form_urlencoded::parse(body.as_bytes()).collect();
+  let pairs = redact_pairs(pairs.iter().map(|(k, v)| (k.as_ref(), v.as_ref())));
+  url::form_urlencoded::Serializer::new(String::new())
+    .extend_pairs(pairs)
+    .finish()
+}
+
+/// `a=1; b=2` -> `a=REDACTED; b=REDACTED`
+fn redact_cookies(cookies: &str) -> String {
+  cookies
+    .split(';')
+    .map(|cookie| {
+      let name = cookie.split('=').next().unwrap_or_default().trim();
+      format!("{}={}", name, REDACTED)
+    })
+    .collect::<Vec<_>>()
+    .join("; ")
+}
+
+/// Redacts the value of a `Set-Cookie` header, keeping its attributes
+fn redact_set_cookie(value: &str) -> String {
+  let (cookie, attributes) = value.split_once(';').unwrap_or((value, ""));
+  let name = cookie.split('=').next().unwrap_or_default();
+  if attributes.is_empty() {
+    format!("{}={}", name, REDACTED)
+  } else {
+    format!("{}={};{}", name, REDACTED, attributes)
+  }
+}
+
+/// Redacts secret keys, `{"name": <secret>, "value": ..}` cookies and secrets in URLs,
+/// e.g., the cross-domain URL of a QR login
+fn redact_json(json: &mut Value) {
+  match json {
+    Value::Object(map) => {
+      let is_secret_cookie = map
+        .get("name")
+        .and_then(Value::as_str)
+        .is_some_and(is_secret);
+      for (key, value) in map.iter_mut() {
+        if is_secret(key) || (is_secret_cookie && key == "value") {
+          *value = Value::String(REDACTED.to_string());
+        } else {
+          redact_json(value);
+        }
+      }
+    }
+    Value::Array(values) => values.iter_mut().for_each(redact_json),
+    Value::String(text) if text.contains('?') => {
+      if let Ok(url) = Url::parse(text) {
+        *text = redact_url(&url);
+      }
+    }
+    _ => {}
+  }
+}
+
+/// `METHOD url form cookie:names`, with volatile and secret parameters removed from the query
+/// and the form and the rest sorted, followed by the [LOGIN_COOKIES] sent
+fn match_key(request: &RecordedRequest) -> String {
+  let header = |name: &str| {
+    request
+      .headers
+      .iter()
+      .find(|(n, _)| n.eq_ignore_ascii_case(name))
+      .map(|(_, v)| v.as_str())
+  };
+  let mut key = format!("{} ", request.method);
+  match Url::parse(&request.url) {
+    Ok(mut url) => {
+      let pairs = match_params(url.query().unwrap_or_default());
+      url.set_query(None);
+      key += url.as_str();
+      if !pairs.is_empty() {
+        key += "?";
+        key += &pairs;
+      }
+    }
+    Err(_) => key += &request.url,
+  }
+  let is_form = header(CONTENT_TYPE.as_str())
+    .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"));
+  if is_form {
+    key += " ";
+    key += &match_params(&request.body);
+  }
+  let mut cookies: Vec<_> = header(COOKIE.as_str())
+    .unwrap_or_default()
+    .split(';')
+    .filter_map(|cookie| cookie.split('=').next())
+    .map(str::trim)
+    .filter(|name| LOGIN_COOKIES.contains(name))
+    .collect();
+  if !cookies.is_empty() {
+    cookies.sort_unstable();
+    key += " cookie:";
+    key += &cookies.join(",");
+  }
+  key
+}
+
+/// `params` without volatile and secret parameters, sorted
+fn match_params(params: &str) -> String {
+  let mut pairs: Vec<(String, String)> = url::form_urlencoded::parse(params.as_bytes())
+    .into_owned()
+    .filter(|(name, _)| !VOLATILE_PARAMS.contains(&name.as_str()) && !is_secret(name))
+    .collect();
+  pairs.sort_by(|a, b| a.0.cmp(&b.0));
+  url::form_urlencoded::Serializer::new(String::new())
+    .extend_pairs(pairs)
+    .finish()
+}
+
+/// Passes requests to `inner` and saves them to a [Cassette] file along with the responses.
+/// Requests failing in `inner`, e.g., on connection errors, are not recorded.
+///
+/// The response is returned even if the cassette cannot be saved, as the request has been
+/// carried out already. The file is written again on the next response, or by [save].
+///
+/// [save]: RecordingTransport::save
+pub struct RecordingTransport<T> {
+  inner: T,
+  path: PathBuf,
+  cassette: Mutex<Cassette>,
+}
+
+impl<T> RecordingTransport<T> {
+  /// Starts an empty cassette, which replaces the file at `path` on the first response
+  pub fn new(inner: T, path: impl Into<PathBuf>) -> Self {
+    Self {
+      inner,
+      path: path.into(),
+      cassette: Mutex::new(Cassette::default()),
+    }
+  }
+
+  /// The interactions recorded so far
+  pub fn cassette(&self) -> Cassette {
+    self.cassette.lock().expect("Lock poisoned").clone()
+  }
+
+  /// Writes the interactions recorded so far to the cassette file
+  pub fn save(&self) -> crate::Result<()> {
+    self
+      .cassette
+      .lock()
+      .expect("Lock poisoned")
+      .save(&self.path)
+  }
+
+  fn record(&self, request: &HttpRequest, response: &HttpResponse) {
+    let mut cassette = self.cassette.lock().expect("Lock poisoned");
+    cassette.push(request, response);
+    if let Err(e) = cassette.save(&self.path) {
+      #[cfg(feature = "tracing")]
+      tracing::warn!(path = %self.path.display(), error = %e, "failed to save cassette");
+      #[cfg(not(feature = "tracing"))]
+      let _ = e;
+    }
+  }
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+  fn send(&self, request: HttpRequest) -> crate::Result<HttpResponse> {
+    let response = self.inner.send(request.clone())?;
+    self.record(&request, &response);
+    Ok(response)
+  }
+}
+
+#[cfg(feature = "async")]
+impl<T: AsyncTransport> AsyncTransport for RecordingTransport<T> {
+  fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+    Box::pin(async move {
+      let response = self.inner.send(request.clone()).await?;
+      self.record(&request, &response);
+      Ok(response)
+    })
+  }
+}
+
+/// Answers requests with the responses of a [Cassette].
+///
+/// A request is answered by the recorded interactions with the same method, URL and form,
+/// ignoring timestamps, signatures and redacted parameters, and sent with the same login
+/// cookies (`SESSDATA`, `bili_jct` and `DedeUserID`), whatever their values. Interactions sharing all of these are served in
+/// the recorded order, the last one repeatedly once all have been served. Requests without
+/// any are failed with HTTP 404 [Error::Status].
+pub struct ReplayTransport {
+  /// The interactions by [match_key], in the recorded order
+  interactions: Result<HashMap<String, Vec<RecordedResponse>>, (io::ErrorKind, String)>,
+  /// How many interactions of each key have been served
+  served: Mutex<HashMap<String, usize>>,
+}
+
+impl ReplayTransport {
+  pub fn new(cassette: Cassette) -> Self {
+    let mut interactions: HashMap<_, Vec<_>> = HashMap::new();
+    for interaction in cassette.interactions {
+      let key = match_key(&interaction.request);
+      interactions
+        .entry(key)
+        .or_default()
+        .push(interaction.response);
+    }
+    Self {
+      interactions: Ok(interactions),
+      served: Mutex::new(HashMap::new()),
+    }
+  }
+
+  pub fn load(path: impl AsRef<Path>) -> crate::Result<Self> {
+    Cassette::load(path).map(Self::new)
+  }
+
+  /// Like [ReplayTransport::load], but reports a cassette that cannot be loaded on every
+  /// request instead
+  pub(super) fn open(path: &Path) -> Self {
+    Self::load(path).unwrap_or_else(|e| {
+      let kind = match &e {
+        Error::Io(e) => e.kind(),
+        _ => io::ErrorKind::InvalidData,
+      };
+      Self {
+        interactions: Err((
+          kind,
+          format!("cannot load cassette {}: {}", path.display(), e),
+        )),
+        served: Mutex::new(HashMap::new()),
+      }
+    })
+  }
+
+  fn respond(&self, request: &HttpRequest) -> crate::Result<HttpResponse> {
+    let interactions = self
+      .interactions
+      .as_ref()
+      .map_err(|(kind, message)| io::Error::new(*kind, message.clone()))?;
+    let key = match_key(&RecordedRequest::from_request(request));
+    let responses = interactions.get(&key).ok_or_else(|| {
+      Error::Status(
+        StatusCode::NOT_FOUND,
+        format!("no recorded response for {}", key),
+      )
+    })?;
+    let mut served = self.served.lock().expect("Lock poisoned");
+    let count = served.entry(key).or_default();
+    let response = &responses[(*count).min(responses.len() - 1)];
+    *count += 1;
+    Ok(response.to_response())
+  }
+}
+
+impl Transport for ReplayTransport {
+  fn send(&self, request: HttpRequest) -> crate::Result<HttpResponse> {
+    self.respond(&request)
+  }
+}
+
+#[cfg(feature = "async")]
+impl AsyncTransport for ReplayTransport {
+  fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+    Box::pin(async move { self.respond(&request) })
+  }
+}
+
+/// Set up by [ClientBuilder::record](super::ClientBuilder::record) or
+/// [ClientBuilder::replay](super::ClientBuilder::replay)
+#[derive(Debug, Clone)]
+pub(super) enum CassetteMode {
+  Record(PathBuf),
+  Replay(PathBuf),
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{path::PathBuf, sync::Arc};
+
+  use reqwest::StatusCode;
+
+  use super::*;
+  use crate::{
+    apis::live::{
+      info::get_room_init_info,
+      user::{get_medal_for_user, wear_medal},
+    },
+    credential::Credential,
+    utils::{assert_error_code, random_u64},
+    Client,
+  };
+
+  const ROOM_INIT: &str = r#"{"code":0,"msg":"ok","message":"ok","data":{"room_id":5440,
+    "short_id":1029,"uid":9617619,"need_p2p":0,"is_hidden":false,"is_locked":false,
+    "is_portrait":false,"live_status":1,"hidden_till":0,"lock_till":0,"encrypted":false,
+    "pwd_verified":false,"live_time":0,"room_shield":0,"is_sp":0,"special_type":0}}"#;
+  const NOT_EXIST: &str =
+    r#"{"code":60004,"msg":"直播间不存在","message":"直播间不存在","data":{}}"#;
+
+  fn temp_path() -> PathBuf {
+    std::env::temp_dir().join(format!("bili-api-rs-cassette-{:x}.json", random_u64()))
+  }
+
+  fn fake(request: HttpRequest) -> crate::Result<HttpResponse> {
+    let body = match request.url.path() {
+      "/room/v1/Room/room_init" if request.url.query() == Some("id=1029") => ROOM_INIT,
+      "/room/v1/Room/room_init" => NOT_EXIST,
+      _ => r#"{"code":0,"message":"0","ttl":1,"access_token":"token","data":{}}"#,
+    };
+    let mut response = HttpResponse::new(StatusCode::OK, body);
+    response.headers.insert(
+      SET_COOKIE,
+      HeaderValue::from_static("SESSDATA=new; Path=/; HttpOnly"),
+    );
+    Ok(response)
+  }
+
+  #[test]
+  fn test_record_redacts_secrets() {
+    let path = temp_path();
+    let client = Client::builder().transport(fake).record(&path).build();
+    let credential = Credential::new("sess".to_string(), "jct".to_string());
+    get_room_init_info(&client, 1029).unwrap();
+    wear_medal(&client, 42, &credential).unwrap();
+
+    let cassette = Cassette::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(2, cassette.interactions.len());
+    let Interaction { request, response } = &cassette.interactions[1];
+    assert_eq!("POST", request.method);
+    assert!(request.headers.contains(&(
+      "cookie".to_string(),
+      "SESSDATA=REDACTED; bili_jct=REDACTED".to_string()
+    )));
+    assert!(!request.headers.iter().any(|(name, _)| name == "user-agent"));
+    assert_eq!(
+      "medal_id=42&csrf=REDACTED&csrf_token=REDACTED",
+      request.body
+    );
+    assert_eq!(
+      vec![(
+        "set-cookie".to_string(),
+        "SESSDATA=REDACTED; Path=/; HttpOnly".to_string()
+      )],
+      response.headers
+    );
+    assert_eq!("REDACTED", response.body["access_token"]);
+  }
+
+  #[test]
+  fn test_redact_json() {
+    let mut json = serde_json::json!({
+      "url": "https://passport.biligame.com/x/passport-login/web/crossDomain?DedeUserID=1&SESSDATA=sess&gourl=x",
+      "cookie_info": {"cookies": [{"name": "bili_jct", "value": "jct"}, {"name": "sid", "value": "s"},
+        {"name": "buvid3", "value": "b"}]},
+      "token_info": {"refresh_token": "r", "ac_time_value": "a"},
+    });
+    redact_json(&mut json);
+    assert_eq!(
+      serde_json::json!({
+        "url": "https://passport.biligame.com/x/passport-login/web/crossDomain?DedeUserID=1&SESSDATA=REDACTED&gourl=x",
+        "cookie_info": {"cookies": [{"name": "bili_jct", "value": "REDACTED"}, {"name": "sid", "value": "REDACTED"},
+          {"name": "buvid3", "value": "b"}]},
+        "token_info": {"refresh_token": "REDACTED", "ac_time_value": "REDACTED"},
+      }),
+      json
+    );
+  }
+
+  #[test]
+  fn test_record_save_failure() {
+    // A file where the directory of the cassette should be
+    let blocker = temp_path();
+    std::fs::write(&blocker, "").unwrap();
+    let transport = Arc::new(RecordingTransport::new(fake, blocker.join("cassette.json")));
+    let client = Client::builder().transport(Arc::clone(&transport)).build();
+
+    // The request has been carried out, so its response is returned all the same
+    let medal = wear_medal(
+      &client,
+      42,
+      &Credential::new("sess".to_string(), "jct".to_string()),
+    );
+    assert!(transport.save().is_err());
+    std::fs::remove_file(&blocker).unwrap();
+    assert!(medal.is_ok());
+    assert_eq!(1, transport.cassette().interactions.len());
+  }
+
+  #[test]
+  fn test_replay_recorded() {
+    let path = temp_path();
+    let recorder = Client::builder().transport(fake).record(&path).build();
+    get_room_init_info(&recorder, 1029).unwrap();
+    assert_error_code(get_room_init_info(&recorder, 1), 60004);
+
+    let client = Client::builder().replay(&path).build();
+    let data = get_room_init_info(&client, 1029).unwrap().data;
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(5440, data.room_id);
+    assert_error_code(get_room_init_info(&client, 1), 60004);
+    // Not recorded
+    assert!(matches!(
+      get_room_init_info(&client, 2),
+      Err(Error::Status(StatusCode::NOT_FOUND, _))
+    ));
+  }
+
+  #[test]
+  fn test_replay_in_recorded_order() {
+    let interaction = |body: Value| Interaction {
+      request: RecordedRequest {
+        method: "GET".to_string(),
+        url:
+          "https://api.live.bilibili.com/xlive/app-ucenter/v1/user/GetMyMedals?page=1&page_size=10"
+            .to_string(),
+        headers: vec![(
+          "cookie".to_string(),
+          "SESSDATA=REDACTED; bili_jct=REDACTED".to_string(),
+        )],
+        body: String::new(),
+      },
+      response: RecordedResponse {
+        status: 200,
+        headers: vec![],
+        body,
+      },
+    };
+    let transport = ReplayTransport::new(Cassette {
+      interactions: vec![
+        interaction(
+          serde_json::json!({"code": 0, "data": {"count": 1, "items": [],
+          "page_info": {"total_page": 1, "cur_page": 1}}}),
+        ),
+        interaction(serde_json::json!({"code": -101, "message": "账号未登录"})),
+      ],
+    });
+    let client = Client::builder().transport(transport).build();
+    let credential = Credential::new("sess".to_string(), "jct".to_string());
+
+    assert_eq!(
+      1,
+      get_medal_for_user(&client, 10, 1, &credential)
+        .unwrap()
+        .data
+        .count
+    );
+    assert_error_code(get_medal_for_user(&client, 10, 1, &credential), -101);
+    assert_error_code(get_medal_for_user(&client, 10, 1, &credential), -101);
+  }
+
+  #[test]
+  fn test_match_key_ignores_volatile_params() {
+    let request = |url: &str, body: &str| RecordedRequest {
+      method: "POST".to_string(),
+      url: url.to_string(),
+      headers: vec![(
+        "content-type".to_string(),
+        "application/x-www-form-urlencoded".to_string(),
+      )],
+      body: body.to_string(),
+    };
+    assert_eq!(
+      match_key(&request(
+        "https://api.bilibili.com/x/space/wbi/acc/info?mid=1&dm_img_list=%5B%5D&w_rid=abc&wts=1",
+        "msg=hi&rnd=1&csrf=REDACTED"
+      )),
+      match_key(&request(
+        "https://api.bilibili.com/x/space/wbi/acc/info?dm_img_list=[]&mid=1&wts=2&w_rid=def",
+        "rnd=2&csrf=REDACTED&msg=hi"
+      ))
+    );
+  }
+
+  #[test]
+  fn test_replay_matches_form_and_cookies() {
+    let path = temp_path();
+    let recorder = Client::builder().transport(fake).record(&path).build();
+    let credential = Credential::new("sess".to_string(), "jct".to_string());
+    wear_medal(&recorder, 42, &credential).unwrap();
+
+    let client = Client::builder().replay(&path).build();
+    let other = Credential::new("other".to_string(), "other".to_string());
+    let result = wear_medal(&client, 42, &other);
+    let wrong_form = wear_medal(&client, 43, &credential);
+    let mut with_uid = credential.clone();
+    with_uid.set_cookie("DedeUserID", "1");
+    let wrong_cookies = wear_medal(&client, 42, &with_uid);
+    std::fs::remove_file(&path).unwrap();
+    // Cookie values are redacted, only their presence is matched
+    assert!(result.is_ok());
+    for result in [wrong_form, wrong_cookies] {
+      assert!(matches!(
+        result,
+        Err(Error::Status(StatusCode::NOT_FOUND, _))
+      ));
+    }
+  }
+
+  #[test]
+  fn test_replay_missing_cassette() {
+    let client = Client::builder().replay(temp_path()).build();
+    assert!(matches!(
+      get_room_init_info(&client, 1029),
+      Err(Error::Io(e)) if e.kind() == io::ErrorKind::NotFound
+    ));
+  }
+
+  #[cfg(feature = "async")]
+  #[tokio::test]
+  async fn test_replay_async() {
+    use crate::{apis::live::info::get_room_init_info_async, AsyncClient};
+
+    let path = temp_path();
+    let mut cassette = Cassette::default();
+    let request = HttpRequest {
+      method: reqwest::Method::GET,
+      url: "https://api.live.bilibili.com/room/v1/Room/room_init?id=1029"
+        .parse()
+        .unwrap(),
+      headers: Default::default(),
+      body: Vec::new(),
+    };
+    let response = fake(request.clone()).unwrap();
+    cassette.push(&request, &response);
+    cassette.save(&path).unwrap();
+
+    let client = AsyncClient::builder().replay(&path).build_async();
+    let data = get_room_init_info_async(&client, 1029).await.unwrap().data;
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(1029, data.short_id);
+  }
+}
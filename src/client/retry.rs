@@ -0,0 +1,317 @@
+use std::{borrow::Cow, collections::HashMap, time::Duration};
+
+use super::Endpoint;
+use crate::{error::ErrorCode, utils::random_u64, Error};
+
+/// Classes of failures a [RetryPolicy] can be configured to retry on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RetryClass {
+  /// Connection, timeout or other I/O errors
+  Network,
+  /// HTTP 5xx or Bilibili's server side error codes (-500, -503, -504, -8888)
+  ServerError,
+  /// 请求被拦截 (-412, or HTTP 412)
+  Blocked,
+  /// 风控校验失败 (-352)
+  RiskControl,
+  /// 请求过于频繁 (-509, -799, 10030 or "频率过快" messages)
+  TooFrequent,
+}
+
+impl RetryClass {
+  /// Classify an error, returns None for errors that are never worth retrying,
+  /// e.g., deserialize errors or invalid credentials.
+  pub fn of(err: &Error) -> Option<RetryClass> {
+    match err {
+      Error::Reqwest(e) => match e.status() {
+        Some(status) => Self::of_status(status),
+        None if e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() => {
+          Some(RetryClass::Network)
+        }
+        None => None,
+      },
+      Error::Status(status, _) | Error::NonJson(status, _) => Self::of_status(*status),
+      // Connection failures reported by a custom transport
+      Error::Io(_) => Some(RetryClass::Network),
+      #[cfg(feature = "danmaku")]
+      Error::WebSocket(_) => Some(RetryClass::Network),
+      Error::Api(e) => match e.kind() {
+        ErrorCode::Blocked => Some(RetryClass::Blocked),
+        ErrorCode::RiskControl => Some(RetryClass::RiskControl),
+        kind if kind.is_rate_limited() => Some(RetryClass::TooFrequent),
+        kind if kind.is_retryable() => Some(RetryClass::ServerError),
+        // B站直播弹幕API在发送过快时不一定返回对应的错误码
+        _ if e.message().contains("频率过快") => Some(RetryClass::TooFrequent),
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+
+  /// Whether the request may have been carried out by the server despite the failure
+  fn may_have_succeeded(self) -> bool {
+    matches!(self, RetryClass::Network | RetryClass::ServerError)
+  }
+
+  fn of_status(status: reqwest::StatusCode) -> Option<RetryClass> {
+    if status == reqwest::StatusCode::PRECONDITION_FAILED {
+      Some(RetryClass::Blocked)
+    } else if status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+      Some(RetryClass::TooFrequent)
+    } else if status.is_server_error() {
+      Some(RetryClass::ServerError)
+    } else {
+      None
+    }
+  }
+}
+
+/// Client-wide retry policy: exponential backoff with jitter, bounded by
+/// `max_attempts`, applied only to the enabled [RetryClass]es.
+///
+/// The delay before the n-th retry is `base_delay * 2^(n-1)` capped at `max_delay`,
+/// of which a random fraction up to `jitter` is subtracted.
+///
+/// [RetryClass::Network] and [RetryClass::ServerError] are only retried for
+/// [idempotent](Endpoint::idempotent) endpoints, i.e., GET requests unless declared
+/// otherwise, as a POST may have been carried out before failing. Endpoints that opt out
+/// of [retrying](Endpoint::retry) are never retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+  max_attempts: u32,
+  base_delay: Duration,
+  max_delay: Duration,
+  jitter: f64,
+  /// Enabled classes, with an optional base delay overriding `base_delay`
+  classes: HashMap<RetryClass, Option<Duration>>,
+}
+
+impl Default for RetryPolicy {
+  /// Up to 3 attempts on network and server errors of idempotent endpoints
+  fn default() -> Self {
+    let mut policy = Self::none();
+    policy
+      .max_attempts(3)
+      .retry_on(RetryClass::Network)
+      .retry_on(RetryClass::ServerError);
+    policy
+  }
+}
+
+impl RetryPolicy {
+  /// A policy that never retries
+  pub fn none() -> Self {
+    Self {
+      max_attempts: 1,
+      base_delay: Duration::from_millis(500),
+      max_delay: Duration::from_secs(10),
+      jitter: 0.5,
+      classes: HashMap::new(),
+    }
+  }
+
+  /// 直播弹幕的默认策略: 发送过快时等待3秒后重试一次
+  pub fn live_msg() -> Self {
+    let mut policy = Self::none();
+    policy
+      .max_attempts(2)
+      .base_delay(Duration::from_secs(3))
+      .jitter(0.0)
+      .retry_on(RetryClass::TooFrequent);
+    policy
+  }
+
+  /// 直播信息流的默认重连策略: 不限次数, 间隔从1秒起翻倍直到1分钟
+  pub fn danmaku_reconnect() -> Self {
+    let mut policy = Self::none();
+    policy
+      .max_attempts(u32::MAX)
+      .base_delay(Duration::from_secs(1))
+      .max_delay(Duration::from_secs(60))
+      .retry_on(RetryClass::Network)
+      .retry_on(RetryClass::ServerError)
+      .retry_on_with_delay(RetryClass::Blocked, Duration::from_secs(30))
+      .retry_on_with_delay(RetryClass::RiskControl, Duration::from_secs(30))
+      .retry_on(RetryClass::TooFrequent);
+    policy
+  }
+
+  /// Maximum number of attempts including the first one
+  pub fn max_attempts(&mut self, max_attempts: u32) -> &mut Self {
+    self.max_attempts = max_attempts.max(1);
+    self
+  }
+
+  pub fn base_delay(&mut self, base_delay: Duration) -> &mut Self {
+    self.base_delay = base_delay;
+    self
+  }
+
+  pub fn max_delay(&mut self, max_delay: Duration) -> &mut Self {
+    self.max_delay = max_delay;
+    self
+  }
+
+  /// Fraction (0.0 ~ 1.0) of each delay that is randomized
+  pub fn jitter(&mut self, jitter: f64) -> &mut Self {
+    self.jitter = jitter.clamp(0.0, 1.0);
+    self
+  }
+
+  pub fn retry_on(&mut self, class: RetryClass) -> &mut Self {
+    self.classes.insert(class, None);
+    self
+  }
+
+  /// Retry on `class` with its own base delay, e.g., back off longer on -412
+  pub fn retry_on_with_delay(&mut self, class: RetryClass, base_delay: Duration) -> &mut Self {
+    self.classes.insert(class, Some(base_delay));
+    self
+  }
+
+  pub fn never_retry_on(&mut self, class: RetryClass) -> &mut Self {
+    self.classes.remove(&class);
+    self
+  }
+
+  /// This policy as applied to `endpoint`, see [RetryPolicy]
+  pub(crate) fn for_endpoint<E: Endpoint + ?Sized>(&self, endpoint: &E) -> Cow<'_, Self> {
+    if !endpoint.retry() {
+      return Cow::Owned(Self::none());
+    }
+    if endpoint.idempotent() {
+      return Cow::Borrowed(self);
+    }
+    let mut policy = self.clone();
+    policy
+      .classes
+      .retain(|class, _| !class.may_have_succeeded());
+    Cow::Owned(policy)
+  }
+
+  /// Returns how long to wait before the next attempt, or None if `err` after
+  /// `attempt` (1-based) attempts should be returned to the caller.
+  pub fn next_delay(&self, err: &Error, attempt: u32) -> Option<Duration> {
+    self.next_delay_for(RetryClass::of(err)?, attempt)
+  }
+
+  /// [RetryPolicy::next_delay] for an error of `class`
+  pub(crate) fn next_delay_for(&self, class: RetryClass, attempt: u32) -> Option<Duration> {
+    if attempt >= self.max_attempts {
+      return None;
+    }
+    let base_delay = (*self.classes.get(&class)?).unwrap_or(self.base_delay);
+
+    let exp = 2u32.saturating_pow(attempt - 1);
+    let delay = base_delay.saturating_mul(exp).min(self.max_delay);
+    Some(delay.mul_f64(1.0 - self.jitter * random_fraction()))
+  }
+}
+
+/// A random number in [0, 1), good enough for jittering
+fn random_fraction() -> f64 {
+  (random_u64() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::{RetryClass, RetryPolicy};
+  use crate::{error::ApiError, Error};
+
+  fn api_error(code: i32, message: &str) -> Error {
+    Error::Api(ApiError::new(code, message.to_string()))
+  }
+
+  #[test]
+  fn test_classify() {
+    assert_eq!(
+      Some(RetryClass::Blocked),
+      RetryClass::of(&api_error(-412, "请求被拦截"))
+    );
+    assert_eq!(
+      Some(RetryClass::RiskControl),
+      RetryClass::of(&api_error(-352, "风控校验失败"))
+    );
+    assert_eq!(
+      Some(RetryClass::TooFrequent),
+      RetryClass::of(&api_error(-799, "请求过于频繁，请稍后再试"))
+    );
+    assert_eq!(
+      Some(RetryClass::TooFrequent),
+      RetryClass::of(&api_error(1, "您发送弹幕的频率过快"))
+    );
+    assert_eq!(
+      Some(RetryClass::ServerError),
+      RetryClass::of(&api_error(-503, "过载保护"))
+    );
+    assert_eq!(
+      Some(RetryClass::ServerError),
+      RetryClass::of(&Error::Status(
+        reqwest::StatusCode::BAD_GATEWAY,
+        String::new()
+      ))
+    );
+    assert_eq!(None, RetryClass::of(&api_error(-101, "账号未登录")));
+    #[cfg(feature = "danmaku")]
+    assert_eq!(
+      Some(RetryClass::Network),
+      RetryClass::of(&Error::from(tungstenite::Error::ConnectionClosed))
+    );
+  }
+
+  #[test]
+  fn test_backoff() {
+    let mut policy = RetryPolicy::none();
+    policy
+      .max_attempts(4)
+      .base_delay(Duration::from_millis(100))
+      .max_delay(Duration::from_millis(300))
+      .jitter(0.0)
+      .retry_on(RetryClass::ServerError);
+    let err = api_error(-500, "服务器错误");
+
+    assert_eq!(Some(Duration::from_millis(100)), policy.next_delay(&err, 1));
+    assert_eq!(Some(Duration::from_millis(200)), policy.next_delay(&err, 2));
+    // Capped by max_delay
+    assert_eq!(Some(Duration::from_millis(300)), policy.next_delay(&err, 3));
+    // Out of attempts
+    assert_eq!(None, policy.next_delay(&err, 4));
+    // Class not enabled
+    assert_eq!(None, policy.next_delay(&api_error(-412, ""), 1));
+  }
+
+  #[test]
+  fn test_jitter_and_class_delay() {
+    let mut policy = RetryPolicy::none();
+    policy
+      .max_attempts(2)
+      .base_delay(Duration::from_millis(100))
+      .jitter(0.5)
+      .retry_on_with_delay(RetryClass::Blocked, Duration::from_secs(5));
+
+    let delay = policy.next_delay(&api_error(-412, ""), 1).unwrap();
+    assert!(delay <= Duration::from_secs(5));
+    assert!(delay >= Duration::from_millis(2500));
+  }
+
+  #[test]
+  fn test_live_msg_policy() {
+    let policy = RetryPolicy::live_msg();
+    let err = api_error(10030, "您发送弹幕的频率过快");
+    assert_eq!(Some(Duration::from_secs(3)), policy.next_delay(&err, 1));
+    assert_eq!(None, policy.next_delay(&err, 2));
+    assert_eq!(None, policy.next_delay(&api_error(-500, ""), 1));
+  }
+
+  #[test]
+  fn test_danmaku_reconnect_policy() {
+    let mut policy = RetryPolicy::danmaku_reconnect();
+    policy.jitter(0.0);
+    // e.g., getDanmuInfo under risk control for a while
+    let err = api_error(-352, "风控校验失败");
+    assert_eq!(Some(Duration::from_secs(30)), policy.next_delay(&err, 1));
+    assert_eq!(Some(Duration::from_secs(60)), policy.next_delay(&err, 100));
+  }
+}
@@ -0,0 +1,288 @@
+//! The HTTP stack a client sends its requests with. The requests built for
+//! [Endpoint](super::Endpoint)s are handed to the [Transport] as an [HttpRequest] with
+//! the body in memory, and parsed from the [HttpResponse] it returns.
+//!
+//! [ReqwestTransport] is the default. Plug in another one with
+//! [ClientBuilder::transport](super::ClientBuilder::transport), e.g., to go through a
+//! corporate proxy, fake responses in unit tests, or record traffic.
+
+use std::{fmt, sync::Arc};
+#[cfg(feature = "async")]
+use std::{future::Future, pin::Pin};
+
+use reqwest::{header::HeaderMap, Method, StatusCode, Url};
+
+/// An HTTP request to send, with its body in memory
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+  pub method: Method,
+  pub url: Url,
+  pub headers: HeaderMap,
+  /// Empty if the request has no body
+  pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+  /// The body as UTF-8, e.g., a form or JSON
+  pub fn body_text(&self) -> String {
+    String::from_utf8_lossy(&self.body).into_owned()
+  }
+}
+
+/// An HTTP response, with its body read into memory
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+  pub status: StatusCode,
+  pub headers: HeaderMap,
+  pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+  pub fn new(status: StatusCode, body: impl Into<Vec<u8>>) -> Self {
+    Self {
+      status,
+      headers: HeaderMap::new(),
+      body: body.into(),
+    }
+  }
+
+  pub fn status(&self) -> StatusCode {
+    self.status
+  }
+
+  pub fn headers(&self) -> &HeaderMap {
+    &self.headers
+  }
+
+  /// The body as UTF-8, with invalid sequences replaced
+  pub fn text(&self) -> String {
+    String::from_utf8_lossy(&self.body).into_owned()
+  }
+}
+
+/// Sends the requests of a [Client](crate::Client).
+///
+/// Connection failures should be reported as [Error::Io](crate::Error::Io), which the
+/// [RetryPolicy](super::RetryPolicy) treats as [RetryClass::Network](super::RetryClass::Network).
+/// Closures taking an [HttpRequest] implement this trait as well.
+pub trait Transport: Send + Sync {
+  fn send(&self, request: HttpRequest) -> crate::Result<HttpResponse>;
+}
+
+impl<F> Transport for F
+where
+  F: Fn(HttpRequest) -> crate::Result<HttpResponse> + Send + Sync,
+{
+  fn send(&self, request: HttpRequest) -> crate::Result<HttpResponse> {
+    self(request)
+  }
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+  fn send(&self, request: HttpRequest) -> crate::Result<HttpResponse> {
+    (**self).send(request)
+  }
+}
+
+impl fmt::Debug for dyn Transport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("Transport")
+  }
+}
+
+/// Sends requests with a blocking `reqwest` client, which may be configured with a proxy,
+/// client certificates, timeouts, etc.
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestTransport {
+  client: reqwest::blocking::Client,
+}
+
+impl ReqwestTransport {
+  pub fn new(client: reqwest::blocking::Client) -> Self {
+    Self { client }
+  }
+}
+
+impl Transport for ReqwestTransport {
+  fn send(&self, request: HttpRequest) -> crate::Result<HttpResponse> {
+    let mut builder = self
+      .client
+      .request(request.method, request.url)
+      .headers(request.headers);
+    if !request.body.is_empty() {
+      builder = builder.body(request.body);
+    }
+    let response = builder.send()?;
+    Ok(HttpResponse {
+      status: response.status(),
+      headers: response.headers().clone(),
+      body: response.bytes()?.to_vec(),
+    })
+  }
+}
+
+/// The future returned by [AsyncTransport::send]
+#[cfg(feature = "async")]
+pub type TransportFuture<'a> =
+  Pin<Box<dyn Future<Output = crate::Result<HttpResponse>> + Send + 'a>>;
+
+/// Sends the requests of an [AsyncClient](crate::AsyncClient), see [Transport]
+#[cfg(feature = "async")]
+pub trait AsyncTransport: Send + Sync {
+  fn send(&self, request: HttpRequest) -> TransportFuture<'_>;
+}
+
+#[cfg(feature = "async")]
+impl<T: AsyncTransport + ?Sized> AsyncTransport for Arc<T> {
+  fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+    (**self).send(request)
+  }
+}
+
+#[cfg(feature = "async")]
+impl fmt::Debug for dyn AsyncTransport {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("AsyncTransport")
+  }
+}
+
+/// Sends requests with an async `reqwest` client, see [ReqwestTransport]
+#[cfg(feature = "async")]
+#[derive(Debug, Clone, Default)]
+pub struct AsyncReqwestTransport {
+  client: reqwest::Client,
+}
+
+#[cfg(feature = "async")]
+impl AsyncReqwestTransport {
+  pub fn new(client: reqwest::Client) -> Self {
+    Self { client }
+  }
+}
+
+#[cfg(feature = "async")]
+impl AsyncTransport for AsyncReqwestTransport {
+  fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+    Box::pin(async move {
+      let mut builder = self
+        .client
+        .request(request.method, request.url)
+        .headers(request.headers);
+      if !request.body.is_empty() {
+        builder = builder.body(request.body);
+      }
+      let response = builder.send().await?;
+      Ok(HttpResponse {
+        status: response.status(),
+        headers: response.headers().clone(),
+        body: response.bytes().await?.to_vec(),
+      })
+    })
+  }
+}
+
+/// Shares a transport between builders and clients
+pub(crate) type SharedTransport = Arc<dyn Transport>;
+
+#[cfg(feature = "async")]
+pub(crate) type SharedAsyncTransport = Arc<dyn AsyncTransport>;
+
+#[cfg(test)]
+mod tests {
+  use std::sync::{Arc, Mutex};
+
+  use reqwest::StatusCode;
+
+  use super::{HttpRequest, HttpResponse};
+  use crate::{
+    apis::live::info::get_room_init_info,
+    client::{Client, RetryClass, RetryPolicy},
+    utils::assert_error_code,
+    Error,
+  };
+
+  const ROOM_INIT: &str = r#"{"code":0,"msg":"ok","message":"ok","data":{"room_id":5050,
+    "short_id":0,"uid":1,"need_p2p":0,"is_hidden":false,"is_locked":false,"is_portrait":false,
+    "live_status":1,"hidden_till":0,"lock_till":0,"encrypted":false,"pwd_verified":false,
+    "live_time":0,"room_shield":0,"is_sp":0,"special_type":0}}"#;
+
+  #[test]
+  fn test_fake_transport() {
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let client = {
+      let sent = Arc::clone(&sent);
+      Client::builder()
+        .transport(move |request: HttpRequest| {
+          sent.lock().unwrap().push(request);
+          Ok(HttpResponse::new(StatusCode::OK, ROOM_INIT))
+        })
+        .build()
+    };
+
+    let data = get_room_init_info(&client, 5050).unwrap().data;
+    assert_eq!(5050, data.room_id);
+
+    let sent = sent.lock().unwrap();
+    assert_eq!(1, sent.len());
+    assert_eq!("GET", sent[0].method);
+    assert_eq!(
+      "https://api.live.bilibili.com/room/v1/Room/room_init?id=5050",
+      sent[0].url.as_str()
+    );
+    assert!(sent[0].headers["user-agent"]
+      .to_str()
+      .unwrap()
+      .contains("BiliDroid"));
+  }
+
+  #[test]
+  fn test_transport_io_errors_are_retried() {
+    let attempts = Arc::new(Mutex::new(0));
+    let mut policy = RetryPolicy::none();
+    policy
+      .max_attempts(3)
+      .base_delay(std::time::Duration::from_millis(1))
+      .retry_on(RetryClass::Network);
+    let client = {
+      let attempts = Arc::clone(&attempts);
+      Client::builder()
+        .retry_policy(policy)
+        .transport(move |_: HttpRequest| {
+          let mut attempts = attempts.lock().unwrap();
+          *attempts += 1;
+          match *attempts {
+            1 => Err(Error::Io(std::io::ErrorKind::ConnectionReset.into())),
+            _ => Ok(HttpResponse::new(
+              StatusCode::OK,
+              r#"{"code":19002001,"message":"房间不存在"}"#,
+            )),
+          }
+        })
+        .build()
+    };
+
+    assert_error_code(get_room_init_info(&client, 5050), 19002001);
+    assert_eq!(2, *attempts.lock().unwrap());
+  }
+
+  #[cfg(feature = "async")]
+  #[tokio::test]
+  async fn test_async_fake_transport() {
+    use super::{AsyncTransport, TransportFuture};
+    use crate::{apis::live::info::get_room_init_info_async, AsyncClient};
+
+    struct Fake;
+    impl AsyncTransport for Fake {
+      fn send(&self, request: HttpRequest) -> TransportFuture<'_> {
+        Box::pin(async move {
+          assert_eq!("/room/v1/Room/room_init", request.url.path());
+          Ok(HttpResponse::new(StatusCode::OK, ROOM_INIT))
+        })
+      }
+    }
+
+    let client = AsyncClient::builder().async_transport(Fake).build_async();
+    let data = get_room_init_info_async(&client, 5050).await.unwrap().data;
+    assert_eq!(5050, data.room_id);
+  }
+}
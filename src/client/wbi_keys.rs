@@ -0,0 +1,65 @@
+use std::{
+  sync::Mutex,
+  time::{Duration, Instant},
+};
+
+/// Caches the WBI mixin key derived from the `wbi_img` of the nav API, so that
+/// WBI-signed requests do not need an extra round trip each time.
+pub(crate) struct WbiKeyCache {
+  ttl: Duration,
+  entry: Mutex<Option<(String, Instant)>>,
+}
+
+impl WbiKeyCache {
+  pub fn new(ttl: Duration) -> Self {
+    Self {
+      ttl,
+      entry: Mutex::new(None),
+    }
+  }
+
+  /// Returns the cached mixin key if it has not expired
+  pub fn get(&self) -> Option<String> {
+    let guard = self.entry.lock().expect("Lock poisoned");
+    match &*guard {
+      Some((key, fetched_at)) if fetched_at.elapsed() < self.ttl => Some(key.clone()),
+      _ => None,
+    }
+  }
+
+  pub fn set(&self, mixin_key: String) {
+    let mut guard = self.entry.lock().expect("Lock poisoned");
+    *guard = Some((mixin_key, Instant::now()));
+  }
+
+  pub fn invalidate(&self) {
+    let mut guard = self.entry.lock().expect("Lock poisoned");
+    *guard = None;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::WbiKeyCache;
+
+  #[test]
+  fn test_wbi_key_cache_expiry() {
+    let cache = WbiKeyCache::new(Duration::from_millis(50));
+    assert_eq!(None, cache.get());
+
+    cache.set("ea1db124af3c7062474693fa704f4ff8".to_string());
+    assert_eq!(
+      Some("ea1db124af3c7062474693fa704f4ff8".to_string()),
+      cache.get()
+    );
+
+    std::thread::sleep(Duration::from_millis(60));
+    assert_eq!(None, cache.get());
+
+    cache.set("ea1db124af3c7062474693fa704f4ff8".to_string());
+    cache.invalidate();
+    assert_eq!(None, cache.get());
+  }
+}
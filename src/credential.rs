@@ -1,21 +1,299 @@
+//! 用户登录凭据, i.e., the set of cookies Bilibili's web APIs authenticate with.
+
+use std::{collections::BTreeMap, path::Path};
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[cfg(feature = "async")]
+use crate::{
+  apis::login::{
+    cookie_refresh::{get_cookie_info_async, refresh_credential_async},
+    oauth2::refresh_app_token_async,
+  },
+  AsyncClient,
+};
+use crate::{
+  apis::{
+    login::{
+      cookie_refresh::{get_cookie_info, refresh_credential},
+      oauth2::refresh_app_token,
+    },
+    misc::sign::app::AppKey,
+  },
+  utils::now_millis,
+  Client, Error,
+};
+
+/// Bilibili web登录凭据.
+///
+/// `sessdata` and `bili_jct` are required by every authenticated API, the other cookies
+/// are sent along when present as some endpoints check them as well. Unrecognized cookies
+/// are kept in `extra` and sent as-is.
+///
+/// Deserializes from the JSON shape `{"sessdata": "", "bili_jct": ""}` with all other
+/// fields optional.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Credential {
   pub sessdata: String,
   pub bili_jct: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub dede_user_id: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub buvid3: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub buvid4: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub sid: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub ac_time_value: Option<String>,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub extra: BTreeMap<String, String>,
 }
 
 impl Credential {
   pub fn new(sessdata: String, bili_jct: String) -> Self {
-    Self { sessdata, bili_jct }
+    Self {
+      sessdata,
+      bili_jct,
+      ..Default::default()
+    }
   }
 
+  /// 用于刷新Cookie的refresh_token, 即网页端localStorage中的`ac_time_value`
+  pub fn refresh_token(&self) -> Option<&str> {
+    self.ac_time_value.as_deref()
+  }
+
+  /// Value of the `Cookie` header carrying every cookie of this credential
   pub fn to_cookie_str(&self) -> String {
-    format!("SESSDATA={}", self.sessdata)
+    self
+      .cookies()
+      .map(|(name, value)| format!("{}={}", name, value))
+      .collect::<Vec<_>>()
+      .join("; ")
+  }
+
+  /// All cookies of this credential as (name, value) pairs
+  pub fn cookies(&self) -> impl Iterator<Item = (&str, &str)> {
+    let known = [
+      ("SESSDATA", Some(&self.sessdata)),
+      ("bili_jct", Some(&self.bili_jct)),
+      ("DedeUserID", self.dede_user_id.as_ref()),
+      ("buvid3", self.buvid3.as_ref()),
+      ("buvid4", self.buvid4.as_ref()),
+      ("sid", self.sid.as_ref()),
+      ("ac_time_value", self.ac_time_value.as_ref()),
+    ];
+    known
+      .into_iter()
+      .filter_map(|(name, value)| Some((name, value?.as_str())))
+      .filter(|(_, value)| !value.is_empty())
+      .chain(self.extra.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+  }
+
+  /// Set a cookie by its name, e.g., as it appears in the `Set-Cookie` header.
+  pub fn set_cookie(&mut self, name: &str, value: impl Into<String>) -> &mut Self {
+    let value = value.into();
+    match name {
+      "SESSDATA" => self.sessdata = value,
+      "bili_jct" => self.bili_jct = value,
+      "DedeUserID" => self.dede_user_id = Some(value),
+      "buvid3" => self.buvid3 = Some(value),
+      "buvid4" => self.buvid4 = Some(value),
+      "sid" => self.sid = Some(value),
+      "ac_time_value" => self.ac_time_value = Some(value),
+      _ => {
+        self.extra.insert(name.to_string(), value);
+      }
+    }
+    self
+  }
+
+  /// Parse from a raw `Cookie:` header value, e.g.,
+  /// `SESSDATA=xxx; bili_jct=xxx; DedeUserID=xxx; buvid3=xxx`
+  pub fn from_cookie_str(cookies: &str) -> crate::Result<Self> {
+    let cookies = cookies.trim();
+    let cookies = cookies.strip_prefix("Cookie:").unwrap_or(cookies);
+    let pairs = cookies.split(';').filter_map(|pair| {
+      let (name, value) = pair.split_once('=')?;
+      Some((name.trim(), value.trim()))
+    });
+    Self::from_pairs(pairs)
+  }
+
+  /// Parse from a Netscape `cookies.txt`, keeping cookies of bilibili.com domains only
+  pub fn from_netscape(content: &str) -> crate::Result<Self> {
+    let pairs = content.lines().filter_map(|line| {
+      let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+      if line.starts_with('#') {
+        return None;
+      }
+      // domain, include subdomains, path, secure, expiry, name, value
+      let fields: Vec<&str> = line.split('\t').collect();
+      if fields.len() != 7 || !is_bilibili_domain(fields[0]) {
+        return None;
+      }
+      Some((fields[5], fields[6].trim_end()))
+    });
+    Self::from_pairs(pairs)
+  }
+
+  /// Read the credential from environment variables:
+  /// 1. `BILI_COOKIE`, a raw cookie string, or
+  /// 2. `SESSDATA` and `BILI_JCT`, optionally with `DEDEUSERID`, `BUVID3`, `BUVID4`,
+  ///    `SID` and `AC_TIME_VALUE`.
+  pub fn from_env() -> crate::Result<Self> {
+    if let Ok(cookies) = std::env::var("BILI_COOKIE") {
+      return Self::from_cookie_str(&cookies);
+    }
+
+    let var = |name: &str| std::env::var(name).ok();
+    let (Some(sessdata), Some(bili_jct)) = (var("SESSDATA"), var("BILI_JCT")) else {
+      return Err(Error::Credential(
+        "neither BILI_COOKIE nor SESSDATA and BILI_JCT are set".to_string(),
+      ));
+    };
+    Ok(Self {
+      sessdata,
+      bili_jct,
+      dede_user_id: var("DEDEUSERID"),
+      buvid3: var("BUVID3"),
+      buvid4: var("BUVID4"),
+      sid: var("SID"),
+      ac_time_value: var("AC_TIME_VALUE"),
+      extra: BTreeMap::new(),
+    })
+  }
+
+  /// Read the credential from a file, which may be a JSON object of this struct,
+  /// a Netscape `cookies.txt`, or a raw cookie string.
+  pub fn from_file(path: impl AsRef<Path>) -> crate::Result<Self> {
+    let content = std::fs::read_to_string(path)?;
+    let trimmed = content.trim_start();
+    if trimmed.starts_with('{') {
+      Ok(serde_json::from_str(trimmed)?)
+    } else if content.contains('\t') {
+      Self::from_netscape(&content)
+    } else {
+      Self::from_cookie_str(&content)
+    }
+  }
+
+  fn from_pairs<'a>(pairs: impl Iterator<Item = (&'a str, &'a str)>) -> crate::Result<Self> {
+    let mut credential = Self::default();
+    for (name, value) in pairs {
+      credential.set_cookie(name, value);
+    }
+    if credential.sessdata.is_empty() {
+      return Err(Error::Credential("missing SESSDATA cookie".to_string()));
+    }
+    if credential.bili_jct.is_empty() {
+      return Err(Error::Credential("missing bili_jct cookie".to_string()));
+    }
+    Ok(credential)
   }
 }
 
+/// Cookie刷新, 参见[cookie_refresh](crate::apis::login::cookie_refresh)
+impl Credential {
+  /// 检查Cookie是否需要刷新, 需要时刷新并返回true. 凭据中须带有refresh_token.
+  pub fn refresh(&mut self, client: &Client) -> crate::Result<bool> {
+    let info = get_cookie_info(client, self)?.data;
+    if !info.refresh {
+      return Ok(false);
+    }
+    *self = refresh_credential(client, self, info.timestamp)?;
+    Ok(true)
+  }
+
+  /// 不检查是否需要, 直接刷新Cookie, e.g., `SESSDATA`已经失效时
+  pub fn force_refresh(&mut self, client: &Client) -> crate::Result<()> {
+    *self = refresh_credential(client, self, now_millis())?;
+    Ok(())
+  }
+
+  /// Async version of [Credential::refresh]
+  #[cfg(feature = "async")]
+  pub async fn refresh_async(&mut self, client: &AsyncClient) -> crate::Result<bool> {
+    let info = get_cookie_info_async(client, self).await?.data;
+    if !info.refresh {
+      return Ok(false);
+    }
+    *self = refresh_credential_async(client, self, info.timestamp).await?;
+    Ok(true)
+  }
+
+  /// Async version of [Credential::force_refresh]
+  #[cfg(feature = "async")]
+  pub async fn force_refresh_async(&mut self, client: &AsyncClient) -> crate::Result<()> {
+    *self = refresh_credential_async(client, self, now_millis()).await?;
+    Ok(())
+  }
+}
+
+/// APP端登录凭据, 用于需要APP签名的API, 参见[app](crate::apis::misc::sign::app)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppCredential {
+  pub access_key: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub refresh_token: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub mid: Option<i64>,
+  /// access_key过期的Unix时间戳(秒)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub expires_at: Option<i64>,
+  /// 签发access_key时的appkey, 查询及刷新access_key时须使用. None则使用Client的appkey.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub app_key: Option<AppKey>,
+}
+
+impl AppCredential {
+  pub fn new(access_key: String) -> Self {
+    Self {
+      access_key,
+      ..Default::default()
+    }
+  }
+
+  /// Read the credential from environment variables `BILI_ACCESS_KEY` and, optionally,
+  /// `BILI_REFRESH_TOKEN`.
+  pub fn from_env() -> crate::Result<Self> {
+    let access_key = std::env::var("BILI_ACCESS_KEY")
+      .map_err(|_| Error::Credential("BILI_ACCESS_KEY is not set".to_string()))?;
+    Ok(Self {
+      refresh_token: std::env::var("BILI_REFRESH_TOKEN").ok(),
+      ..Self::new(access_key)
+    })
+  }
+
+  /// 以refresh_token换取新的access_key, 参见[refresh_app_token]
+  pub fn refresh(&mut self, client: &Client) -> crate::Result<()> {
+    *self = refresh_app_token(client, self)?
+      .data
+      .token_info
+      .to_credential(self.app_key.clone());
+    Ok(())
+  }
+
+  /// Async version of [AppCredential::refresh]
+  #[cfg(feature = "async")]
+  pub async fn refresh_async(&mut self, client: &AsyncClient) -> crate::Result<()> {
+    *self = refresh_app_token_async(client, self)
+      .await?
+      .data
+      .token_info
+      .to_credential(self.app_key.clone());
+    Ok(())
+  }
+}
+
+/// Whether a cookie of `domain` is sent to bilibili.com, i.e., it is bilibili.com or one of
+/// its subdomains, with or without the leading dot
+fn is_bilibili_domain(domain: &str) -> bool {
+  let domain = domain.strip_prefix('.').unwrap_or(domain);
+  domain == "bilibili.com" || domain.ends_with(".bilibili.com")
+}
+
 #[cfg(test)]
 pub mod extract_credential {
   use std::path::PathBuf;
@@ -24,10 +302,11 @@ pub mod extract_credential {
 
   /// This utility function is used for getting a valid user credential in test environment.
   /// Ways to supply credential in decreasing precedence are:
-  /// 1. Supply both SESSDATA and BILI_JCT on environment variables, e.g., SESSDATA=xxxx BILI_JCT=xxxx cargo test
+  /// 1. Supply both SESSDATA and BILI_JCT on environment variables, e.g., SESSDATA=xxxx BILI_JCT=xxxx cargo test,
+  ///    or the full cookie string in BILI_COOKIE. See [Credential::from_env].
   /// 2. Put a cookies.json file of format {sessdata: "", bili_jct: ""} in the project's work directory.
   pub(crate) fn get_credential_for_test_or_abort() -> Credential {
-    if let Some(cred) = get_credential_from_env() {
+    if let Ok(cred) = Credential::from_env() {
       cred
     } else if let Some(cred) = get_credential_from_file() {
       cred
@@ -50,29 +329,63 @@ pub mod extract_credential {
   }
 
   pub(crate) fn get_fake_credential() -> Credential {
-    Credential {
-      sessdata: "123".to_string(),
-      bili_jct: "456".to_string(),
-    }
-  }
-
-  fn get_credential_from_env() -> Option<Credential> {
-    let sessdata = std::env::var("SESSDATA").ok()?;
-    let bili_jct = std::env::var("BILI_JCT").ok()?;
-    Some(Credential { sessdata, bili_jct })
+    Credential::new("123".to_string(), "456".to_string())
   }
 
   fn get_credential_from_file() -> Option<Credential> {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     path.push("cookies.json");
 
-    let cookie = std::fs::OpenOptions::new()
-      .read(true)
-      .write(false)
-      .create(false)
-      .open(&path)
-      .ok()?;
-    let cookie = serde_json::from_reader(cookie).ok()?;
-    Some(cookie)
+    Credential::from_file(&path).ok()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Credential;
+
+  #[test]
+  fn test_from_cookie_str() {
+    let cred = Credential::from_cookie_str(
+      "SESSDATA=abc%2C123; bili_jct=def; DedeUserID=12734361; buvid3=XYZ-infoc; _uuid=foo",
+    )
+    .unwrap();
+    assert_eq!("abc%2C123", cred.sessdata);
+    assert_eq!("def", cred.bili_jct);
+    assert_eq!(Some("12734361"), cred.dede_user_id.as_deref());
+    assert_eq!(Some("XYZ-infoc"), cred.buvid3.as_deref());
+    assert_eq!(Some("foo"), cred.extra.get("_uuid").map(String::as_str));
+
+    assert_eq!(
+      "SESSDATA=abc%2C123; bili_jct=def; DedeUserID=12734361; buvid3=XYZ-infoc; _uuid=foo",
+      cred.to_cookie_str()
+    );
+
+    assert!(Credential::from_cookie_str("bili_jct=def").is_err());
+  }
+
+  #[test]
+  fn test_from_netscape() {
+    let content = "# Netscape HTTP Cookie File\n\
+      .bilibili.com\tTRUE\t/\tFALSE\t1735660800\tbuvid3\tXYZ-infoc\n\
+      #HttpOnly_.bilibili.com\tTRUE\t/\tTRUE\t1735660800\tSESSDATA\tabc\n\
+      .bilibili.com\tTRUE\t/\tFALSE\t1735660800\tbili_jct\tdef\n\
+      .example.com\tTRUE\t/\tFALSE\t1735660800\tsid\tnope\n\
+      .evilbilibili.com\tTRUE\t/\tFALSE\t1735660800\tbili_jct\tevil\n\
+      passport.bilibili.com\tFALSE\t/\tFALSE\t1735660800\tDedeUserID\t233\n";
+    let cred = Credential::from_netscape(content).unwrap();
+    assert_eq!("abc", cred.sessdata);
+    assert_eq!("def", cred.bili_jct);
+    assert_eq!(Some("XYZ-infoc"), cred.buvid3.as_deref());
+    assert_eq!(Some("233"), cred.dede_user_id.as_deref());
+    assert_eq!(None, cred.sid);
+  }
+
+  #[test]
+  fn test_json_shape() {
+    let cred: Credential =
+      serde_json::from_str(r#"{"sessdata": "abc", "bili_jct": "def"}"#).unwrap();
+    assert_eq!(Credential::new("abc".to_string(), "def".to_string()), cred);
+    assert_eq!("SESSDATA=abc; bili_jct=def", cred.to_cookie_str());
   }
 }
@@ -20,6 +20,35 @@ pub enum Error {
   /// Rust type we have.
   #[error("{0}")]
   Deserialize(serde_json::Error),
+  /// The API responded with a non-success HTTP status and a body that is not
+  /// Bilibili's JSON envelope, e.g., HTTP 412 from the anti-crawler system.
+  /// Carries the leading part of the body.
+  #[error("HTTP status error (status: {0}, body: {1})")]
+  Status(reqwest::StatusCode, String),
+  /// The API responded with a body that is not JSON, e.g., an HTML error page
+  /// from a CDN. Carries the leading part of the body.
+  #[error("Non-JSON response (status: {0}, body: {1})")]
+  NonJson(reqwest::StatusCode, String),
+  /// The response is JSON but does not follow Bilibili's `{code, message, data}`
+  /// envelope.
+  #[error("Malformed response envelope: {0}")]
+  MalformedEnvelope(String),
+  /// The supplied credential cannot be parsed or lacks required cookies.
+  #[error("Invalid credential: {0}")]
+  Credential(String),
+  /// I/O error, e.g., when reading a credential file.
+  #[error("{0}")]
+  Io(std::io::Error),
+  /// The query parameters cannot be serialized, e.g., a nested struct passed to a signer.
+  #[error("Invalid query: {0}")]
+  Query(String),
+  /// A packet of the live danmaku broadcast protocol cannot be decoded.
+  #[error("Malformed danmaku packet: {0}")]
+  Protocol(String),
+  /// WebSocket error of a live danmaku connection.
+  #[cfg(feature = "danmaku")]
+  #[error("{0}")]
+  WebSocket(Box<tungstenite::Error>),
 }
 
 /// This Error represents business logic error thrown by Bilibili's API server,
@@ -41,6 +70,11 @@ impl ApiError {
     self.code
   }
 
+  /// The typed [ErrorCode] of this error
+  pub fn kind(&self) -> ErrorCode {
+    ErrorCode::from(self.code)
+  }
+
   pub fn message(&self) -> &str {
     &self.message
   }
@@ -51,11 +85,181 @@ impl fmt::Display for ApiError {
     write!(
       f,
       "Bilibili API Error (code: {}, message: {})",
-      self.code, &self.message
+      self.code, self.message
     )
   }
 }
 
+impl Error {
+  /// The typed Bilibili error code if this is an [Error::Api]
+  pub fn api_code(&self) -> Option<ErrorCode> {
+    match self {
+      Error::Api(e) => Some(e.kind()),
+      _ => None,
+    }
+  }
+}
+
+macro_rules! error_codes {
+  ($($name:ident = $code:literal => $desc:literal,)*) => {
+    /// Bilibili API错误码
+    ///
+    /// Refer to `https://github.com/SocialSisterYi/bilibili-API-collect/blob/master/docs/misc/errcode.md`.
+    /// Codes not listed here are preserved in [ErrorCode::Unknown].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[non_exhaustive]
+    pub enum ErrorCode {
+      $(
+        #[doc = $desc]
+        $name,
+      )*
+      /// 未收录的错误码
+      Unknown(i32),
+    }
+
+    impl ErrorCode {
+      pub fn code(self) -> i32 {
+        match self {
+          $(ErrorCode::$name => $code,)*
+          ErrorCode::Unknown(code) => code,
+        }
+      }
+
+      /// 错误码的官方含义, 未收录的错误码返回None
+      pub fn description(self) -> Option<&'static str> {
+        match self {
+          $(ErrorCode::$name => Some($desc),)*
+          ErrorCode::Unknown(_) => None,
+        }
+      }
+    }
+
+    impl From<i32> for ErrorCode {
+      fn from(code: i32) -> Self {
+        match code {
+          $($code => ErrorCode::$name,)*
+          code => ErrorCode::Unknown(code),
+        }
+      }
+    }
+  };
+}
+
+error_codes! {
+  AppNotFound = -1 => "应用程序不存在或已被封禁",
+  AccessKeyError = -2 => "Access Key错误",
+  AppSecretError = -3 => "API校验密匙错误",
+  MethodForbidden = -4 => "调用方对该Method没有权限",
+  NotLoggedIn = -101 => "账号未登录",
+  AccountBanned = -102 => "账号被封停",
+  InsufficientPoints = -103 => "积分不足",
+  InsufficientCoins = -104 => "硬币不足",
+  CaptchaError = -105 => "验证码错误",
+  NotFormalMember = -106 => "账号非正式会员或在适应期",
+  AppBanned = -107 => "应用不存在或者被封禁",
+  PhoneNotBound = -108 => "未绑定手机",
+  PhoneNotBound2 = -110 => "未绑定手机",
+  CsrfFailed = -111 => "csrf校验失败",
+  SystemUpgrading = -112 => "系统升级中",
+  RealNameRequired = -113 => "账号尚未实名认证",
+  BindPhoneRequired = -114 => "请先绑定手机",
+  RealNameVerificationRequired = -115 => "请先完成实名认证",
+  NotModified = -304 => "木有改动",
+  Redirect = -307 => "撞车跳转",
+  RiskControl = -352 => "风控校验失败",
+  RequestError = -400 => "请求错误",
+  Unauthorized = -401 => "未认证或非法请求",
+  Forbidden = -403 => "访问权限不足",
+  NotFound = -404 => "啥都木有",
+  MethodNotAllowed = -405 => "不支持该方法",
+  Conflict = -409 => "冲突",
+  Blocked = -412 => "请求被拦截",
+  ServerError = -500 => "服务器错误",
+  ServiceUnavailable = -503 => "过载保护,服务暂不可用",
+  Timeout = -504 => "服务调用超时",
+  LimitExceeded = -509 => "超出限制",
+  UploadNotFound = -616 => "上传文件不存在",
+  UploadTooLarge = -617 => "上传文件太大",
+  TooManyLoginFailures = -625 => "登录失败次数太多",
+  UserNotFound = -626 => "用户不存在",
+  PasswordTooWeak = -628 => "密码太弱",
+  WrongPassword = -629 => "用户名或密码错误",
+  TooManyTargets = -632 => "操作对象数量限制",
+  Locked = -643 => "被锁定",
+  LevelTooLow = -650 => "用户等级太低",
+  DuplicateUser = -652 => "重复的用户",
+  TokenExpired = -658 => "Token过期",
+  PasswordTimestampExpired = -662 => "密码时间戳过期",
+  RegionRestricted = -688 => "地理区域限制",
+  CopyrightRestricted = -689 => "版权限制",
+  MoralDeductionFailed = -701 => "扣节操失败",
+  TooFrequent = -799 => "请求过于频繁，请稍后再试",
+  ServerHiccup = -8888 => "对不起，服务器开小差了~",
+  LiveGeneralError = 1 => "直播API默认错误码",
+  LiveTargetNotFound = 10003 => "直播API请求对象不存在",
+  LiveMsgTooFrequent = 10030 => "您发送弹幕的频率过快",
+  LiveRoomInitNotFound = 60004 => "直播间不存在",
+  LiveRoomNotFound = 19002001 => "直播间不存在",
+}
+
+impl ErrorCode {
+  /// Credential is missing, invalid or expired. Retrying with the same credential won't help.
+  pub fn is_auth_error(self) -> bool {
+    matches!(
+      self,
+      ErrorCode::AccessKeyError
+        | ErrorCode::NotLoggedIn
+        | ErrorCode::CsrfFailed
+        | ErrorCode::Unauthorized
+        | ErrorCode::TokenExpired
+    )
+  }
+
+  /// The request is throttled or blocked for being too frequent.
+  pub fn is_rate_limited(self) -> bool {
+    matches!(
+      self,
+      ErrorCode::Blocked
+        | ErrorCode::LimitExceeded
+        | ErrorCode::TooFrequent
+        | ErrorCode::LiveMsgTooFrequent
+    )
+  }
+
+  /// The request was rejected by risk control, normally because of invalid WBI signature,
+  /// User-Agent or device fingerprint.
+  pub fn is_risk_control(self) -> bool {
+    matches!(self, ErrorCode::RiskControl)
+  }
+
+  /// Transient failure on Bilibili's side or throttling, the same request may succeed later.
+  pub fn is_retryable(self) -> bool {
+    self.is_rate_limited()
+      || matches!(
+        self,
+        ErrorCode::ServerError
+          | ErrorCode::ServiceUnavailable
+          | ErrorCode::Timeout
+          | ErrorCode::ServerHiccup
+      )
+  }
+}
+
+impl fmt::Display for ErrorCode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self.description() {
+      Some(desc) => write!(f, "{} ({})", self.code(), desc),
+      None => write!(f, "{}", self.code()),
+    }
+  }
+}
+
+impl From<ErrorCode> for i32 {
+  fn from(value: ErrorCode) -> Self {
+    value.code()
+  }
+}
+
 impl From<reqwest::Error> for Error {
   fn from(value: reqwest::Error) -> Self {
     Self::Reqwest(value)
@@ -68,8 +272,56 @@ impl From<ApiError> for Error {
   }
 }
 
+impl From<std::io::Error> for Error {
+  fn from(value: std::io::Error) -> Self {
+    Self::Io(value)
+  }
+}
+
+#[cfg(feature = "danmaku")]
+impl From<tungstenite::Error> for Error {
+  fn from(value: tungstenite::Error) -> Self {
+    Self::WebSocket(Box::new(value))
+  }
+}
+
 impl From<serde_json::Error> for Error {
   fn from(value: serde_json::Error) -> Self {
     Self::Deserialize(value)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{ApiError, ErrorCode};
+
+  #[test]
+  fn test_error_code_roundtrip() {
+    for code in [-101, -111, -352, -412, -404, 10003, 19002001, 60004] {
+      let kind = ErrorCode::from(code);
+      assert!(!matches!(kind, ErrorCode::Unknown(_)));
+      assert_eq!(code, kind.code());
+    }
+    assert_eq!(ErrorCode::Unknown(123456), ErrorCode::from(123456));
+    assert_eq!(123456, ErrorCode::Unknown(123456).code());
+  }
+
+  #[test]
+  fn test_error_code_classification() {
+    assert!(ErrorCode::NotLoggedIn.is_auth_error());
+    assert!(ErrorCode::CsrfFailed.is_auth_error());
+    assert!(!ErrorCode::NotLoggedIn.is_retryable());
+
+    assert!(ErrorCode::Blocked.is_rate_limited());
+    assert!(ErrorCode::Blocked.is_retryable());
+    assert!(ErrorCode::ServiceUnavailable.is_retryable());
+    assert!(!ErrorCode::ServiceUnavailable.is_rate_limited());
+
+    assert!(ErrorCode::RiskControl.is_risk_control());
+    assert!(!ErrorCode::Unknown(42).is_retryable());
+
+    let err = ApiError::new(-101, "账号未登录".to_string());
+    assert_eq!(ErrorCode::NotLoggedIn, err.kind());
+    assert_eq!("-101 (账号未登录)", ErrorCode::NotLoggedIn.to_string());
+  }
+}
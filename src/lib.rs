@@ -7,5 +7,7 @@ pub mod error;
 
 pub type Result<T, E = error::Error> = std::result::Result<T, E>;
 
+#[cfg(feature = "async")]
+pub use client::AsyncClient;
 pub use client::Client;
 pub use error::Error;
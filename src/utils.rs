@@ -1,37 +1,58 @@
 //! Utilities
+use std::{
+  collections::hash_map::RandomState,
+  hash::{BuildHasher, Hasher},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{
+  header::{HeaderMap, HeaderValue, COOKIE, SET_COOKIE},
+  StatusCode,
+};
 use serde::{de::DeserializeOwned, Deserializer};
 use serde_json::Value;
 
-use crate::error::ApiError;
+use crate::{
+  client::HttpResponse,
+  error::{ApiError, Error},
+};
 
 /// Handles Bilibili business logic with regard to response:
-/// 1. If code != 0 or message is not a normal success indicator, throw an BiliResponseError
+/// 1. If the body is not a JSON object with a numeric `code`, throw an error describing the
+///    malformed response. HTTP status errors whose body is not a Bilibili envelope, e.g., a 412
+///    anti-crawler page, are reported as [Error::Status](crate::Error::Status).
+/// 2. If code != 0 or message is not a normal success indicator, throw an BiliResponseError
 ///    It is possible that code == 0 but message indicates failure, for example when user live message is
 ///    blocked due to blocked word.
-/// 2. Otherwise try deserialize to the corresponding type and throw Deserialize Error if failed.
-pub fn handle_api_response<T: DeserializeOwned>(
-  response: reqwest::blocking::Response,
+/// 3. Otherwise try deserialize to the corresponding type and throw Deserialize Error if failed.
+pub fn handle_api_response<T: DeserializeOwned>(response: HttpResponse) -> crate::Result<T> {
+  parse_api_response(response.status(), &response.text())
+}
+
+pub(crate) fn parse_api_response<T: DeserializeOwned>(
+  status: StatusCode,
+  body: &str,
 ) -> crate::Result<T> {
-  let value: Value = response.json().expect("Response has to be JSON formatted");
-
-  // uncomment this line when debugging :)
-  // println!("{}", serde_json::to_string_pretty(&value).unwrap());
-
-  let (code, message) = {
-    // We know that the response has to be
-    let obj = value.as_object().expect("Response has to be JSON object");
-    let code = obj
-      .get("code")
-      .expect("Response has to contain code field")
-      .as_i64()
-      .expect("Response code has to be number");
-    let message = obj
-      .get("message")
-      .expect("Response has to contain message field")
-      .as_str()
-      .expect("Response message has to be string")
-      .to_string();
-    (code, message)
+  let value = serde_json::from_str::<Value>(body);
+  // Bodies may carry tokens and cookies, so only their length is logged
+  #[cfg(feature = "tracing")]
+  tracing::trace!(
+    %status,
+    code = value.as_ref().ok().and_then(|v| v.get("code")?.as_i64()),
+    len = body.len(),
+    "API response"
+  );
+
+  let value = match value {
+    Ok(value) => value,
+    Err(_) if !status.is_success() => return Err(Error::Status(status, snippet(body))),
+    Err(_) => return Err(Error::NonJson(status, snippet(body))),
+  };
+
+  let (code, message) = match parse_envelope(&value) {
+    Ok(envelope) => envelope,
+    Err(_) if !status.is_success() => return Err(Error::Status(status, snippet(body))),
+    Err(e) => return Err(e),
   };
   match (code, message) {
     (0, _) => serde_json::from_value(value).map_err(crate::Error::from),
@@ -39,6 +60,90 @@ pub fn handle_api_response<T: DeserializeOwned>(
   }
 }
 
+/// Extracts `code` and `message` from a response. Live APIs sometimes use `msg` instead of
+/// `message`, or omit the message altogether.
+fn parse_envelope(value: &Value) -> crate::Result<(i64, String)> {
+  let obj = value
+    .as_object()
+    .ok_or_else(|| Error::MalformedEnvelope("response is not a JSON object".to_string()))?;
+  let code = obj
+    .get("code")
+    .ok_or_else(|| Error::MalformedEnvelope("missing code field".to_string()))?
+    .as_i64()
+    .ok_or_else(|| Error::MalformedEnvelope("code is not an integer".to_string()))?;
+  let message = obj
+    .get("message")
+    .or_else(|| obj.get("msg"))
+    .and_then(Value::as_str)
+    .unwrap_or_default()
+    .to_string();
+  Ok((code, message))
+}
+
+/// The leading part of a response body kept in errors
+pub(crate) fn snippet(body: &str) -> String {
+  const MAX_CHARS: usize = 200;
+  body.chars().take(MAX_CHARS).collect()
+}
+
+/// A random number from the std hasher seeds, good enough for jittering and fake device ids
+pub(crate) fn random_u64() -> u64 {
+  RandomState::new().build_hasher().finish()
+}
+
+/// 当前毫秒时间戳
+pub(crate) fn now_millis() -> i64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .expect("SystemTime before UNIX EPOCH")
+    .as_millis() as i64
+}
+
+/// Adds `cookies` to the `Cookie` header in `headers`, skipping those already present
+pub(crate) fn add_cookies<'a>(
+  headers: &mut HeaderMap,
+  cookies: impl IntoIterator<Item = (&'a str, String)>,
+) {
+  let existing = headers
+    .get(COOKIE)
+    .and_then(|value| value.to_str().ok())
+    .unwrap_or_default()
+    .to_string();
+  let present: Vec<&str> = existing
+    .split(';')
+    .filter_map(|pair| Some(pair.split_once('=')?.0.trim()))
+    .collect();
+
+  let mut merged: Vec<String> = Some(existing.trim())
+    .filter(|cookies| !cookies.is_empty())
+    .map(str::to_string)
+    .into_iter()
+    .collect();
+  merged.extend(
+    cookies
+      .into_iter()
+      .filter(|(name, _)| !present.contains(name))
+      .map(|(name, value)| format!("{}={}", name, value)),
+  );
+  if let Ok(value) = HeaderValue::from_str(&merged.join("; ")) {
+    headers.insert(COOKIE, value);
+  }
+}
+
+/// (name, value) of every `Set-Cookie` header
+pub(crate) fn set_cookies(headers: &HeaderMap) -> Vec<(String, String)> {
+  headers
+    .get_all(SET_COOKIE)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .filter_map(|value| {
+      let pair = value.split(';').next()?;
+      let (name, value) = pair.split_once('=')?;
+      Some((name.trim().to_string(), value.trim().to_string()))
+    })
+    .collect()
+}
+
 #[cfg(test)]
 pub fn assert_error_code<T: std::fmt::Debug>(result: crate::Result<T>, code: i32) {
   match result {
@@ -74,3 +179,301 @@ pub fn non_empty_json_obj<'de, D: Deserializer<'de>, T: DeserializeOwned>(
     Err(_e) => Ok(None),
   }
 }
+
+#[cfg(test)]
+pub mod mock_server {
+  //! A tiny local HTTP server standing in for Bilibili's API hosts in tests.
+
+  use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+  };
+
+  /// A request received by the [MockServer]
+  #[derive(Debug, Clone)]
+  pub struct MockRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+  }
+
+  impl MockRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+      self
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+    }
+  }
+
+  /// Serves a fixed JSON body for each registered path (query string is ignored),
+  /// and `{"code": -404}` for everything else. A path ending with `*` matches every
+  /// path starting with it.
+  pub struct MockServer {
+    server: Arc<tiny_http::Server>,
+    received: Arc<Mutex<Vec<MockRequest>>>,
+    handle: Option<JoinHandle<()>>,
+  }
+
+  impl MockServer {
+    pub fn start(routes: &[(&str, &str)]) -> Self {
+      Self::start_with_headers(routes, &[])
+    }
+
+    /// Like [MockServer::start], additionally sending `(path, name, value)` headers,
+    /// e.g., `Set-Cookie`, in responses of the path.
+    pub fn start_with_headers(routes: &[(&str, &str)], headers: &[(&str, &str, &str)]) -> Self {
+      let routes: HashMap<String, String> = routes
+        .iter()
+        .map(|(path, body)| (path.to_string(), body.to_string()))
+        .collect();
+      let extra_headers: Vec<(String, tiny_http::Header)> = headers
+        .iter()
+        .map(|(path, name, value)| {
+          let header = tiny_http::Header::from_bytes(*name, *value).unwrap();
+          (path.to_string(), header)
+        })
+        .collect();
+      let server = Arc::new(tiny_http::Server::http("127.0.0.1:0").unwrap());
+      let received = Arc::new(Mutex::new(Vec::new()));
+
+      let handle = {
+        let server = Arc::clone(&server);
+        let received = Arc::clone(&received);
+        std::thread::spawn(move || {
+          for mut request in server.incoming_requests() {
+            let mut body = String::new();
+            let _ = request.as_reader().read_to_string(&mut body);
+            let url = request.url().to_string();
+            let headers = request
+              .headers()
+              .iter()
+              .map(|h| (h.field.to_string(), h.value.to_string()))
+              .collect();
+            received.lock().unwrap().push(MockRequest {
+              method: request.method().to_string(),
+              url: url.clone(),
+              headers,
+              body,
+            });
+
+            let path = url.split('?').next().unwrap_or_default();
+            let route = routes.get(path).map(|body| (path, body)).or_else(|| {
+              routes.iter().find_map(|(route, body)| {
+                let prefix = route.strip_suffix('*')?;
+                path.starts_with(prefix).then_some((route.as_str(), body))
+              })
+            });
+            let mut response = match route {
+              Some((route, body)) => {
+                let mut response = tiny_http::Response::from_string(body.as_str());
+                for (_, header) in extra_headers.iter().filter(|(path, _)| path == route) {
+                  response.add_header(header.clone());
+                }
+                response
+              }
+              None => tiny_http::Response::from_string(r#"{"code":-404,"message":"啥都木有"}"#)
+                .with_status_code(404),
+            };
+            let content_type =
+              tiny_http::Header::from_bytes("Content-Type", "application/json").unwrap();
+            response.add_header(content_type);
+            let _ = request.respond(response);
+          }
+        })
+      };
+
+      Self {
+        server,
+        received,
+        handle: Some(handle),
+      }
+    }
+
+    pub fn base_url(&self) -> String {
+      format!("http://{}", self.server.server_addr().to_ip().unwrap())
+    }
+
+    pub fn received(&self) -> Vec<MockRequest> {
+      self.received.lock().unwrap().clone()
+    }
+  }
+
+  impl Drop for MockServer {
+    fn drop(&mut self) {
+      self.server.unblock();
+      if let Some(handle) = self.handle.take() {
+        let _ = handle.join();
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+pub mod cassette {
+  //! Clients replaying `fixtures/cassettes/{name}.json`, so that tests of APIs on the real
+  //! site run offline. Run them with `BILI_RECORD=1` and a credential including `DedeUserID`
+  //! (see [get_credential_for_test_or_abort]) to record the cassettes again.
+  //!
+  //! The committed cassettes are synthetic: written by hand after the documented responses,
+  //! not recorded from the real site. See `fixtures/cassettes/README.md`. The `_live` twins
+  //! of the tests, ignored by default, check the real site instead.
+
+  use std::path::PathBuf;
+
+  #[cfg(feature = "async")]
+  use crate::AsyncClient;
+  use crate::{
+    client::{ClientBuilder, REDACTED},
+    credential::{extract_credential::get_credential_for_test_or_abort, Credential},
+    Client,
+  };
+
+  fn recording() -> bool {
+    std::env::var_os("BILI_RECORD").is_some()
+  }
+
+  pub fn path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+      .join("fixtures/cassettes")
+      .join(format!("{}.json", name))
+  }
+
+  pub fn builder(name: &str) -> ClientBuilder {
+    let mut builder = Client::builder();
+    if recording() {
+      builder.record(path(name));
+    } else {
+      builder.replay(path(name));
+    }
+    builder
+  }
+
+  pub fn client(name: &str) -> Client {
+    builder(name).build()
+  }
+
+  #[cfg(feature = "async")]
+  pub fn async_client(name: &str) -> AsyncClient {
+    builder(name).build_async()
+  }
+
+  /// The logged-in user: the real credential while recording, a placeholder while replaying
+  pub fn credential() -> Credential {
+    if recording() {
+      get_credential_for_test_or_abort()
+    } else {
+      let mut credential = Credential::new(REDACTED.to_string(), REDACTED.to_string());
+      credential.set_cookie("DedeUserID", REDACTED);
+      credential
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use reqwest::{
+    header::{HeaderMap, HeaderValue, SET_COOKIE},
+    StatusCode,
+  };
+  use serde::Deserialize;
+
+  use super::{parse_api_response, set_cookies};
+  use crate::Error;
+
+  #[derive(Debug, Deserialize)]
+  struct Data {
+    #[allow(dead_code)]
+    data: i32,
+  }
+
+  #[test]
+  fn test_parse_success() {
+    let body = r#"{"code":0,"message":"0","data":1}"#;
+    assert!(parse_api_response::<Data>(StatusCode::OK, body).is_ok());
+  }
+
+  #[test]
+  fn test_parse_alternate_envelopes() {
+    // Live APIs use `msg` instead of `message`
+    let body = r#"{"code":60004,"msg":"直播间不存在","data":[]}"#;
+    match parse_api_response::<Data>(StatusCode::OK, body) {
+      Err(Error::Api(e)) => {
+        assert_eq!(60004, e.code());
+        assert_eq!("直播间不存在", e.message());
+      }
+      it => panic!("Expect Api Error, got {:?}", it),
+    }
+
+    // Missing message
+    let body = r#"{"code":-101}"#;
+    match parse_api_response::<Data>(StatusCode::OK, body) {
+      Err(Error::Api(e)) => {
+        assert_eq!(-101, e.code());
+        assert_eq!("", e.message());
+      }
+      it => panic!("Expect Api Error, got {:?}", it),
+    }
+  }
+
+  #[test]
+  fn test_parse_non_json() {
+    let body = "<html><body>502 Bad Gateway</body></html>";
+    assert!(matches!(
+      parse_api_response::<Data>(StatusCode::OK, body),
+      Err(Error::NonJson(StatusCode::OK, snippet)) if snippet == body
+    ));
+
+    let body = "<html>".repeat(100);
+    match parse_api_response::<Data>(StatusCode::PRECONDITION_FAILED, &body) {
+      Err(Error::Status(status, snippet)) => {
+        assert_eq!(StatusCode::PRECONDITION_FAILED, status);
+        assert_eq!(200, snippet.len());
+      }
+      it => panic!("Expect Status Error, got {:?}", it),
+    }
+  }
+
+  #[test]
+  fn test_parse_status_with_envelope() {
+    // Bilibili envelopes are honored even with a non-success status
+    let body = r#"{"code":-412,"message":"请求被拦截"}"#;
+    match parse_api_response::<Data>(StatusCode::PRECONDITION_FAILED, body) {
+      Err(Error::Api(e)) => assert_eq!(-412, e.code()),
+      it => panic!("Expect Api Error, got {:?}", it),
+    }
+  }
+
+  #[test]
+  fn test_parse_malformed_envelope() {
+    for body in [r#"[1, 2]"#, r#"{"message":"0"}"#, r#"{"code":"0"}"#] {
+      assert!(matches!(
+        parse_api_response::<Data>(StatusCode::OK, body),
+        Err(Error::MalformedEnvelope(_))
+      ));
+    }
+  }
+
+  #[test]
+  fn test_set_cookies() {
+    let mut headers = HeaderMap::new();
+    headers.append(
+      SET_COOKIE,
+      HeaderValue::from_static("SESSDATA=abc; Path=/; Domain=bilibili.com; HttpOnly"),
+    );
+    headers.append(
+      SET_COOKIE,
+      HeaderValue::from_static("bili_jct=def; Path=/; Domain=bilibili.com"),
+    );
+    assert_eq!(
+      vec![
+        ("SESSDATA".to_string(), "abc".to_string()),
+        ("bili_jct".to_string(), "def".to_string())
+      ],
+      set_cookies(&headers)
+    );
+  }
+}